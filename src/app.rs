@@ -1,34 +1,217 @@
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use eframe::egui;
 use eframe::egui::TextureHandle;
 use log::debug;
 use parking_lot::Mutex;
 
-use crate::config::{COVER_IMAGE_SIZE, get_default_app_dir_config, get_font_definitions};
-use crate::database::{Database, get_all_tracks};
-use crate::player::{GeneralMusicPlayer as _, MusicPlayer, MusicPlayerEvent};
+use crate::config::{
+    COVER_IMAGE_SIZE, LibraryFolder, Settings, VISUALIZER_HEIGHT, get_default_app_dir_config,
+    get_font_definitions,
+};
+use crate::database::{
+    Database, ScanEvent, ScanProgress, SearchQuery, SearchResults, delete_bookmark, get_all_tracks,
+    get_bookmarks, get_waveform, insert_bookmark, insert_play, search_tracks, set_loved,
+    set_modified, set_rating, set_unplayable, set_waveform,
+};
+use crate::loudness::LoudnessAnalysis;
+use crate::player::{GeneralMusicPlayer as _, MusicPlayer, MusicPlayerEvent, output_devices};
 use crate::playlist::{Playlist, PlaylistId};
-use crate::track::Track;
-use crate::ui::control_panel::ControlPanel;
+use crate::track::{Bookmark, Track, write_rating};
+use crate::ui::control_panel::{ControlPanel, ControlPanelAction};
 use crate::ui::cover_art::CoverArt;
+use crate::ui::equalizer::EqualizerPanel;
+use crate::ui::notifications::Notifications;
+use crate::ui::settings::SettingsPanel;
+use crate::ui::track_info::TrackInfo;
 use crate::ui::track_list::TrackListContextMenu;
 use crate::ui::track_list::{TrackList, TrackListAction, TrackListIndicator};
+use crate::ui::visualizer::Visualizer;
+use crate::watcher::LibraryWatcher;
+use crate::waveform::{Waveform, measure};
 
 enum TrackListView {
     Library,
     Playlist(Option<PlaylistId>),
 }
 
+/// A scan of the library folders that is still running.
+struct LibraryScan {
+    progress: ScanProgress,
+    events: mpsc::Receiver<ScanEvent>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Tracks of the library, along with a generation that changes whenever they are edited so
+/// that searches know when to run again.
+#[derive(Default)]
+struct Library {
+    tracks: Vec<Track>,
+    generation: usize,
+}
+
+impl Library {
+    fn generation(&self) -> usize {
+        self.generation
+    }
+
+    fn replace(&mut self, tracks: Vec<Track>) {
+        **self = tracks;
+    }
+
+    /// Replaces the tracks read from `paths`, which may be files or directories, with `tracks`
+    /// read from them again. New tracks are added at the end.
+    fn patch(&mut self, paths: &[PathBuf], mut tracks: Vec<Track>) {
+        self.retain_mut(|track| {
+            if !paths.iter().any(|path| track.path.starts_with(path)) {
+                return true;
+            }
+
+            match tracks.iter().position(|updated| updated == track) {
+                Some(index) => {
+                    *track = tracks.remove(index);
+                    true
+                }
+                None => false,
+            }
+        });
+        self.extend(tracks);
+    }
+}
+
+impl Deref for Library {
+    type Target = Vec<Track>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tracks
+    }
+}
+
+impl DerefMut for Library {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.generation = self.generation.wrapping_add(1);
+        &mut self.tracks
+    }
+}
+
+/// Search of a track list, run in the background so that typing stays smooth with large
+/// libraries.
+#[derive(Default)]
+struct TrackListSearch {
+    query: String,
+    /// Generation of the library and length of the list when the search ran, it runs again when
+    /// they change.
+    version: (usize, usize),
+    /// Results along with the number of the search they are for, so that earlier searches
+    /// finishing late are dropped.
+    results: Arc<Mutex<(usize, Option<Arc<SearchResults>>)>>,
+}
+
+impl TrackListSearch {
+    fn results(&self) -> Option<Arc<SearchResults>> {
+        self.results.lock().1.clone()
+    }
+
+    /// Searches `query`, or the last query if `None`, unless it has been searched already with
+    /// the library as it is. The results of the last search are kept until the new ones come.
+    ///
+    /// Tracks of the list that are not in the library are given by `others`, they are searched
+    /// in memory.
+    fn update(
+        &mut self,
+        query: Option<String>,
+        version: (usize, usize),
+        others: impl FnOnce() -> Vec<Track>,
+        database: &Database,
+        ctx: &egui::Context,
+    ) {
+        let query = query.unwrap_or_else(|| self.query.clone());
+        if query == self.query && version == self.version {
+            return;
+        }
+
+        self.query = query;
+        self.version = version;
+
+        let generation = {
+            let mut results = self.results.lock();
+            results.0 += 1;
+            if self.query.trim().is_empty() {
+                results.1 = None;
+                return;
+            }
+            results.0
+        };
+
+        let query = SearchQuery::parse(&self.query);
+        let others = others();
+        let database = database.clone();
+        let results = Arc::clone(&self.results);
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let found = search_tracks(&database.get_connection(), &query);
+
+            match found {
+                Ok(mut found) => {
+                    for track in others.iter().filter(|track| query.matches(track)) {
+                        found.insert(track);
+                    }
+
+                    let mut results = results.lock();
+
+                    if results.0 == generation {
+                        results.1 = Some(Arc::new(found));
+                        ctx.request_repaint();
+                    }
+                }
+                Err(err) => debug!("Failed to search tracks: {err:?}"),
+            }
+        });
+    }
+}
+
 pub struct App {
     player: Arc<Mutex<MusicPlayer>>,
-    library: Arc<Mutex<Vec<Track>>>,
+    library: Arc<Mutex<Library>>,
     cover: Arc<Mutex<Option<TextureHandle>>>,
+    /// Waveform of the track it belongs to, which may not be the current one anymore.
+    waveform: Arc<Mutex<Option<(Track, Waveform)>>>,
+    /// Errors to show to the user until they are dismissed.
+    notifications: Arc<Mutex<Vec<String>>>,
+    database: Database,
+    settings: Settings,
+    loudness_analysis: Option<Arc<LoudnessAnalysis>>,
+    output_devices: Vec<String>,
+    /// Track being played, shown with its bookmarks and waveform.
+    playing_track: Option<Track>,
+    /// Bookmarks of `playing_track`, reloaded when another track starts.
+    bookmarks: Vec<Bookmark>,
 
     current_track_list_view: TrackListView,
+    show_settings: bool,
+    show_equalizer: bool,
+    /// Set while changes to the settings have been applied but not saved yet.
+    settings_unsaved: bool,
+    /// Track shown in the track info dialog.
+    track_info: Option<Track>,
+    /// Library folders the library was last refreshed and watched with, which differ from the
+    /// settings while they're being edited.
+    library_folders: Vec<LibraryFolder>,
+    library_watcher: Option<LibraryWatcher>,
+    library_scan: Option<LibraryScan>,
+    /// Searches of the track lists, by the id of the list.
+    searches: HashMap<String, TrackListSearch>,
+    /// Ratings to write to the file tags, written one after the other.
+    rating_writer: mpsc::Sender<(PathBuf, Option<u8>)>,
 }
 
 impl App {
@@ -40,111 +223,217 @@ impl App {
             options.input_options.line_scroll_speed = 100.0;
         });
 
+        let database = Database::new().expect("Database connected.");
+        let settings = Settings::load(&database.get_connection());
+
         let (player_tx, player_rx) = mpsc::channel();
         let player = Arc::new(Mutex::new(MusicPlayer::new(player_tx)));
-        let library = Arc::new(Mutex::new(Vec::new()));
+        let library = Arc::new(Mutex::new(Library::default()));
         let cover = Arc::new(Mutex::new(None));
+        let waveform = Arc::new(Mutex::new(None));
+        let notifications = Arc::new(Mutex::new(Vec::new()));
 
-        {
-            let player = player.clone();
-            let library = library.clone();
-            let cover = cover.clone();
-            let ctx = cc.egui_ctx.clone();
+        settings.apply(&mut *player.lock());
+
+        let library_folders = settings.library_folders.clone();
+        let library_watcher = watch_library(
+            &database,
+            &library,
+            &notifications,
+            &cc.egui_ctx,
+            &library_folders,
+        );
 
-            thread::spawn(move || -> ! {
-                let database = Database::new().expect("Database connected.");
+        let rating_writer = spawn_rating_writer(database.clone());
 
-                database.refresh_library(false).ok();
+        let mut app = Self {
+            player,
+            library,
+            cover,
+            waveform,
+            notifications,
+            database,
+            settings,
+            loudness_analysis: None,
+            output_devices: Vec::new(),
+            playing_track: None,
+            bookmarks: Vec::new(),
 
-                let tracks = get_all_tracks(&database.get_connection()).unwrap_or_default();
+            current_track_list_view: TrackListView::Library,
+            show_settings: false,
+            show_equalizer: false,
+            settings_unsaved: false,
+            track_info: None,
+            library_folders,
+            library_watcher,
+            library_scan: None,
+            searches: HashMap::new(),
+            rating_writer,
+        };
 
-                *library.lock() = tracks;
+        let (scan_tx, scan_cancelled) = app.start_scan();
+        app.spawn_initial_scan(cc.egui_ctx.clone(), scan_tx, scan_cancelled);
+        app.spawn_event_loop(cc.egui_ctx.clone(), player_rx);
 
-                match Playlist::new_from_file(&get_default_app_dir_config().join("default.m3u")) {
-                    Ok(playlist) => {
-                        *player.lock().playlist_mut() = playlist;
-                    }
-                    Err(err) => {
-                        if err.kind() == io::ErrorKind::NotFound {
-                            debug!("Current playlist not found.");
-                        } else {
-                            debug!("{err:?}");
-                        }
-                    }
-                }
+        app
+    }
 
+    /// Refreshes the library in the background.
+    ///
+    /// The scan runs on its own so that the player is usable while it runs, it is shown as
+    /// finished once its events are disconnected.
+    fn spawn_initial_scan(
+        &self,
+        ctx: egui::Context,
+        scan_tx: mpsc::Sender<ScanEvent>,
+        scan_cancelled: Arc<AtomicBool>,
+    ) {
+        let library = self.library.clone();
+        let notifications = self.notifications.clone();
+        let database = self.database.clone();
+        let library_folders = self.library_folders.clone();
+
+        thread::spawn(move || {
+            // NOTE: The library as it was is shown until the scan is done.
+            if let Ok(tracks) = get_all_tracks(&database.get_connection()) {
+                library.lock().replace(tracks);
                 ctx.request_repaint();
+            }
 
-                loop {
-                    if let Ok(player_event) = player_rx.recv() {
-                        match player_event {
-                            MusicPlayerEvent::Tick => {
-                                let mut player = player.lock();
-                                if let Some(mpris_event) = player.mpris_event() {
-                                    player.mpris_handle(&mpris_event);
-                                }
-                                ctx.request_repaint();
+            refresh_library(
+                &database,
+                &library,
+                &notifications,
+                &library_folders,
+                &scan_tx,
+                &scan_cancelled,
+            );
+            ctx.request_repaint();
+        });
+    }
+
+    /// Loads the last playlist in the background, then handles the events of the player.
+    fn spawn_event_loop(&self, ctx: egui::Context, player_rx: mpsc::Receiver<MusicPlayerEvent>) {
+        let player = self.player.clone();
+        let library = self.library.clone();
+        let cover = self.cover.clone();
+        let waveform = self.waveform.clone();
+        let notifications = self.notifications.clone();
+        let database = self.database.clone();
+
+        thread::spawn(move || -> ! {
+            // NOTE: Set to cancel the measurement of the previous track's waveform.
+            let mut waveform_cancelled = Arc::new(AtomicBool::new(false));
+            let mut play: Option<Play> = None;
+
+            load_playlist(&player);
+            ctx.request_repaint();
+
+            loop {
+                if let Ok(player_event) = player_rx.recv() {
+                    match player_event {
+                        MusicPlayerEvent::Tick => {
+                            let mut player = player.lock();
+                            if let Some(mpris_event) = player.mpris_event() {
+                                player.mpris_handle(&mpris_event);
                             }
-                            MusicPlayerEvent::PlaybackStarted => {
-                                let track = player.lock().current_track().cloned();
-
-                                let texture = track.and_then(|t| match t.read_front_cover() {
-                                    Ok(front_cover) => {
-                                        let buffer = front_cover.as_deref()?;
-
-                                        image::load_from_memory(buffer)
-                                            .map(|image| {
-                                                let size =
-                                                    [image.width() as _, image.height() as _];
-                                                let image_buffer = image.to_rgba8();
-                                                let pixels = image_buffer.as_flat_samples();
-
-                                                ctx.load_texture(
-                                                    "cover",
-                                                    egui::ColorImage::from_rgba_unmultiplied(
-                                                        size,
-                                                        pixels.as_slice(),
-                                                    ),
-                                                    egui::TextureOptions::default(),
-                                                )
-                                            })
-                                            .ok()
-                                    }
-                                    Err(_) => None,
-                                });
-
-                                *cover.lock() = texture;
-
-                                ctx.request_repaint();
+                            ctx.request_repaint();
+                        }
+                        MusicPlayerEvent::PlaybackStarted => {
+                            let track = player.lock().current_track().cloned();
+
+                            finish_play(&mut play, &database, &library, false);
+                            play = track.clone().map(Play::new);
+
+                            let texture = track.as_ref().and_then(|t| match t.read_front_cover() {
+                                Ok(front_cover) => {
+                                    let buffer = front_cover.as_deref()?;
+
+                                    image::load_from_memory(buffer)
+                                        .map(|image| {
+                                            let size = [image.width() as _, image.height() as _];
+                                            let image_buffer = image.to_rgba8();
+                                            let pixels = image_buffer.as_flat_samples();
+
+                                            ctx.load_texture(
+                                                "cover",
+                                                egui::ColorImage::from_rgba_unmultiplied(
+                                                    size,
+                                                    pixels.as_slice(),
+                                                ),
+                                                egui::TextureOptions::default(),
+                                            )
+                                        })
+                                        .ok()
+                                }
+                                Err(_) => None,
+                            });
+
+                            *cover.lock() = texture;
+
+                            waveform_cancelled.store(true, Ordering::Relaxed);
+                            if let Some(track) = track {
+                                waveform_cancelled =
+                                    load_waveform(&ctx, &database, &waveform, track);
                             }
-                            MusicPlayerEvent::PlaybackProgress => {
-                                player.lock().mpris_update_progress();
+
+                            ctx.request_repaint();
+                        }
+                        MusicPlayerEvent::PlaybackProgress => {
+                            let mut player = player.lock();
+                            player.update_progress();
+
+                            if let Some(play) = play.as_mut() {
+                                play.progress(
+                                    player.position(),
+                                    player.playback_speed().rate,
+                                    Instant::now(),
+                                );
                             }
-                            MusicPlayerEvent::PlaybackEnded => {
-                                player.lock().play_next();
-                                // NOTE: Repaint is needed after doing something with playlist and
-                                // player so that the UI state isn't stale.
-                                ctx.request_repaint();
+                        }
+                        MusicPlayerEvent::PlaybackAdvanced => {
+                            let rate = player.lock().playback_speed().rate;
+                            complete_play(&mut play, rate, &database, &library);
+                            player.lock().advance();
+                            ctx.request_repaint();
+                        }
+                        MusicPlayerEvent::PlaybackEnded => {
+                            let rate = player.lock().playback_speed().rate;
+                            complete_play(&mut play, rate, &database, &library);
+                            player.lock().finish_track();
+                            // NOTE: Repaint is needed after doing something with playlist and
+                            // player so that the UI state isn't stale.
+                            ctx.request_repaint();
+                        }
+                        MusicPlayerEvent::PlaybackSeeked(position) => {
+                            player.lock().mpris_seeked(position);
+                        }
+                        MusicPlayerEvent::PlaybackStopped => {
+                            // NOTE: Also sent when another track replaces the current one.
+                            if player.lock().is_stopped() {
+                                finish_play(&mut play, &database, &library, false);
                             }
-                            MusicPlayerEvent::PlaybackStopped => {}
+                        }
+                        MusicPlayerEvent::PlaybackFailed(err) => {
+                            mark_unplayable(&database, &library, err.track());
+                            notifications.lock().push(err.to_string());
+
+                            player.lock().skip_failed_track(err.track());
+                            ctx.request_repaint();
+                        }
+                        // NOTE: The play of the track carries on on the new output.
+                        MusicPlayerEvent::PlaybackResumed => ctx.request_repaint(),
+                        MusicPlayerEvent::OutputDeviceLost => {
+                            player.lock().reopen_output();
+                            ctx.request_repaint();
                         }
                     }
                 }
-            });
-        }
-
-        Self {
-            player,
-            library,
-            cover,
-
-            current_track_list_view: TrackListView::Library,
-        }
+            }
+        });
     }
 
-    fn body(&mut self, ui: &mut egui::Ui) {
-        let mut player = self.player.lock();
-
+    fn header(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             let library_button = ui.add(egui::Button::new("Library"));
             let playlist_button = ui.add(egui::Button::new("Default Playlist"));
@@ -155,99 +444,293 @@ impl App {
             if playlist_button.clicked() {
                 self.current_track_list_view = TrackListView::Playlist(None);
             }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.add(egui::Button::new("Settings")).clicked() {
+                    self.show_settings = !self.show_settings;
+                    // NOTE: Listing devices is slow, only do it when the settings are opened.
+                    if self.show_settings {
+                        self.output_devices = output_devices();
+                    }
+                }
+                if ui.add(egui::Button::new("Equalizer")).clicked() {
+                    self.show_equalizer = !self.show_equalizer;
+                }
+            });
         });
+    }
+
+    fn body(&mut self, ui: &mut egui::Ui) {
+        self.header(ui);
 
         ui.separator();
 
+        let player = Arc::clone(&self.player);
+        let mut player = player.lock();
+
+        if let TrackListView::Playlist(view_playlist_id) = &self.current_track_list_view {
+            let mut id = String::from("playlist");
+
+            if let Some(playlist_id) = view_playlist_id {
+                id.push_str(":id:");
+                id.push_str(playlist_id);
+            }
+
+            self.playlist_view(ui, &mut player, &id);
+        } else {
+            self.library_view(ui, &mut player);
+        }
+    }
+
+    fn library_view(&mut self, ui: &mut egui::Ui, player: &mut MusicPlayer) {
+        let library = Arc::clone(&self.library);
+        let mut library = library.lock();
         let mut action = None;
-        let mut indicator = None;
-
-        match &self.current_track_list_view {
-            TrackListView::Library => {
-                let library = self.library.lock();
-
-                if !player.is_stopped()
-                    && let Some(track) = player.current_track()
-                    && let Some(index) = library
-                        .iter()
-                        .enumerate()
-                        .find_map(|(i, t)| track.eq(t).then_some(i))
-                {
-                    if player.is_paused() {
-                        indicator = Some(TrackListIndicator::Paused(index));
-                    } else {
-                        indicator = Some(TrackListIndicator::Playing(index));
-                    }
-                }
 
-                ui.add(
-                    TrackList::new(&mut action, library.as_slice(), indicator, "library")
-                        .context_menu(vec![TrackListContextMenu::SendToCurrentPlaylist]),
-                );
+        let index = player
+            .current_track()
+            .and_then(|track| library.iter().position(|t| track.eq(t)));
+        let indicator = playing_indicator(player, index);
+        let results = self
+            .searches
+            .get("library")
+            .and_then(TrackListSearch::results);
 
-                if let Some(action) = action {
-                    match action {
-                        TrackListAction::Select(_index) => {}
-                        TrackListAction::Play(index) => {
-                            player.playlist_mut().clear();
-                            player.playlist_mut().push(library[index].clone());
+        let response = ui.add(
+            TrackList::new(&mut action, library.as_slice(), indicator, "library")
+                .context_menu(vec![
+                    TrackListContextMenu::SendToCurrentPlaylist,
+                    TrackListContextMenu::EqualizerPreset(self.settings.equalizer_preset_names()),
+                    TrackListContextMenu::Rating,
+                    TrackListContextMenu::TrackInfo,
+                ])
+                .search_results(results.as_deref())
+                .settings(&mut self.settings.track_list),
+        );
+        self.settings_unsaved |= response.changed();
 
-                            player.stop();
-                            player.play();
-                        }
-                        TrackListAction::SendToCurrentPlaylist(indexes) => {
-                            for index in indexes {
-                                player.playlist_mut().push(library[index].clone());
-                            }
-                        }
+        let mut query = None;
+
+        if let Some(action) = action {
+            match action {
+                TrackListAction::Select(_index) => {}
+                TrackListAction::Play(index) => {
+                    player.playlist_mut().clear();
+                    player.playlist_mut().push(library[index].clone());
+
+                    player.stop();
+                    player.play();
+                }
+                TrackListAction::SendToCurrentPlaylist(indexes) => {
+                    for index in indexes {
+                        player.playlist_mut().push(library[index].clone());
                     }
                 }
-            }
-            TrackListView::Playlist(view_playlist_id) => {
-                let playlist = player.playlist();
-                let tracks = playlist.tracks();
-
-                if !player.is_stopped() {
-                    if player.is_paused() {
-                        indicator =
-                            Some(TrackListIndicator::Paused(playlist.current_track_index()));
-                    } else {
-                        indicator =
-                            Some(TrackListIndicator::Playing(playlist.current_track_index()));
+                TrackListAction::SetEqualizerPreset(indexes, name) => {
+                    for track in indexes.iter().filter_map(|index| library.get(*index)) {
+                        self.settings
+                            .set_track_equalizer_preset(track.path.clone(), name.clone());
                     }
+
+                    self.save_settings(player);
                 }
+                TrackListAction::SetRating(indexes, rating) => {
+                    let writer = self
+                        .settings
+                        .write_rating_tags
+                        .then_some(&self.rating_writer);
+                    rate_tracks(&self.database, &mut library, &indexes, rating, writer);
 
-                let mut id = String::from("playlist");
+                    for track in indexes.iter().filter_map(|index| library.get(*index)) {
+                        player.update_track(track);
+                    }
+                }
+                TrackListAction::SetLoved(indexes, loved) => {
+                    love_tracks(&self.database, &mut library, &indexes, loved);
 
-                if let Some(playlist_id) = view_playlist_id {
-                    id.push_str(":id:");
-                    id.push_str(playlist_id);
+                    for track in indexes.iter().filter_map(|index| library.get(*index)) {
+                        player.update_track(track);
+                    }
+                }
+                TrackListAction::ShowInfo(index) => {
+                    self.track_info = library.get(index).cloned();
                 }
+                TrackListAction::Search(search) => query = Some(search),
+            }
+        }
 
-                ui.add(TrackList::new(&mut action, tracks, indicator, id));
+        self.searches
+            .entry(String::from("library"))
+            .or_default()
+            .update(
+                query,
+                (library.generation(), library.len()),
+                Vec::new,
+                &self.database,
+                ui.ctx(),
+            );
+    }
 
-                if let Some(action) = action {
-                    match action {
-                        TrackListAction::Select(_index) => {}
-                        TrackListAction::Play(index) => {
-                            player.playlist_mut().select_track(index);
+    fn playlist_view(&mut self, ui: &mut egui::Ui, player: &mut MusicPlayer, id: &str) {
+        let mut action = None;
 
-                            player.stop();
-                            player.play();
-                        }
-                        TrackListAction::SendToCurrentPlaylist(_indexes) => {}
-                    }
+        let playlist = player.playlist();
+        let indicator = playing_indicator(player, Some(playlist.current_track_index()));
+        let results = self.searches.get(id).and_then(TrackListSearch::results);
+
+        let response = ui.add(
+            TrackList::new(&mut action, playlist.tracks(), indicator, id.to_owned())
+                .context_menu(vec![TrackListContextMenu::TrackInfo])
+                .search_results(results.as_deref())
+                .settings(&mut self.settings.track_list),
+        );
+        self.settings_unsaved |= response.changed();
+
+        let mut query = None;
+
+        if let Some(action) = action {
+            match action {
+                TrackListAction::Select(_index) => {}
+                TrackListAction::Play(index) => {
+                    player.playlist_mut().select_track(index);
+
+                    player.stop();
+                    player.play();
                 }
+                TrackListAction::ShowInfo(index) => {
+                    self.track_info = player.playlist().tracks().get(index).cloned();
+                }
+                TrackListAction::Search(search) => query = Some(search),
+                TrackListAction::SendToCurrentPlaylist(_indexes)
+                | TrackListAction::SetEqualizerPreset(_indexes, _)
+                | TrackListAction::SetRating(_indexes, _)
+                | TrackListAction::SetLoved(_indexes, _) => {}
             }
         }
+
+        // NOTE: Playlist tracks are searched in the library, tracks that are not part of it
+        // are searched in memory.
+        let library = self.library.lock();
+        let playlist = player.playlist().tracks();
+        self.searches.entry(id.to_owned()).or_default().update(
+            query,
+            (library.generation(), playlist.len()),
+            || {
+                let known: HashSet<_> = library
+                    .iter()
+                    .map(|track| (&track.path, track.start()))
+                    .collect();
+
+                playlist
+                    .iter()
+                    .filter(|track| !known.contains(&(&track.path, track.start())))
+                    .cloned()
+                    .collect()
+            },
+            &self.database,
+            ui.ctx(),
+        );
     }
 
-    fn panel(&self, ui: &mut egui::Ui) {
-        let mut player = self.player.lock();
+    fn panel(&mut self, ui: &mut egui::Ui) {
+        {
+            let mut notifications = self.notifications.lock();
+
+            if !notifications.is_empty() {
+                ui.add(Notifications::new(&mut notifications));
+                ui.separator();
+            }
+        }
+
+        let current_track = {
+            let player = self.player.lock();
+
+            player
+                .current_track()
+                .filter(|_| !player.is_stopped())
+                .cloned()
+        };
+        if current_track != self.playing_track {
+            self.playing_track = current_track;
+            self.load_bookmarks();
+        }
+
+        let mut action = None;
+        {
+            let waveform = self.waveform.lock();
+            let waveform = waveform
+                .as_ref()
+                .filter(|(track, _)| self.playing_track.as_ref() == Some(track))
+                .map(|(_, waveform)| waveform);
+
+            ui.add(
+                ControlPanel::new(&mut *self.player.lock(), &mut action)
+                    .bookmarks(&self.bookmarks)
+                    .waveform(waveform),
+            );
+        }
+
+        if let Some(action) = action {
+            self.handle_control_panel_action(action);
+        }
+
+        if let Some(analysis) = self
+            .loudness_analysis
+            .as_ref()
+            .filter(|analysis| !analysis.is_finished())
+        {
+            let (analyzed, total) = analysis.progress();
+
+            ui.horizontal(|ui| {
+                if ui.button("Cancel").clicked() {
+                    analysis.cancel();
+                }
+
+                let progress = if total == 0 {
+                    0.0
+                } else {
+                    analyzed as f32 / total as f32
+                };
+
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .text(format!("Analyzing loudness {analyzed}/{total}")),
+                );
+            });
+
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
+        }
 
-        ui.add(ControlPanel::new(&mut *player));
+        self.scan_progress(ui);
+    }
 
-        // TODO: Scan progress.
+    fn load_bookmarks(&mut self) {
+        self.bookmarks = self
+            .playing_track
+            .as_ref()
+            .and_then(|track| get_bookmarks(&self.database.get_connection(), track).ok())
+            .unwrap_or_default();
+    }
+
+    fn handle_control_panel_action(&mut self, action: ControlPanelAction) {
+        let Some(track) = self.playing_track.as_ref() else {
+            return;
+        };
+
+        let result = match action {
+            ControlPanelAction::AddBookmark(name, position) => {
+                insert_bookmark(&self.database.get_connection(), track, &name, position).map(drop)
+            }
+            ControlPanelAction::RemoveBookmark(id) => {
+                delete_bookmark(&self.database.get_connection(), id)
+            }
+        };
+
+        if let Err(err) = result {
+            debug!("Failed to update bookmarks: {err:?}");
+        }
+
+        self.load_bookmarks();
     }
 
     fn meta(&self, ui: &mut egui::Ui) {
@@ -263,6 +746,12 @@ impl App {
             .size(COVER_IMAGE_SIZE.into()),
         );
 
+        ui.add(
+            Visualizer::new(&player.tap())
+                .active(!player.is_stopped() && !player.is_paused())
+                .size(egui::Vec2::new(COVER_IMAGE_SIZE.0, VISUALIZER_HEIGHT)),
+        );
+
         if let Some(current_track) = player.current_track()
             && !player.is_stopped()
         {
@@ -290,6 +779,501 @@ impl App {
             });
         }
     }
+
+    fn save_settings(&self, player: &mut MusicPlayer) {
+        self.settings.apply(player);
+
+        if let Err(err) = self.settings.save(&self.database.get_connection()) {
+            debug!("Failed to save settings: {err:?}");
+        }
+    }
+
+    fn equalizer(&mut self, ui: &mut egui::Ui) {
+        if ui.add(EqualizerPanel::new(&mut self.settings)).changed() {
+            self.settings.apply(&mut *self.player.lock());
+            self.settings_unsaved = true;
+        }
+    }
+
+    fn settings(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .add(SettingsPanel::new(&mut self.settings, &self.output_devices))
+            .changed()
+        {
+            self.save_settings(&mut self.player.lock());
+        }
+
+        let analyzing = self
+            .loudness_analysis
+            .as_ref()
+            .is_some_and(|analysis| !analysis.is_finished());
+
+        if ui
+            .add_enabled(!analyzing, egui::Button::new("Analyze loudness"))
+            .on_hover_text("Compute replay gain for tracks without replay gain tags.")
+            .clicked()
+        {
+            self.analyze_loudness(ui.ctx().clone());
+        }
+    }
+
+    /// Refreshes and watches the library with the library folders of the settings.
+    ///
+    /// Patterns of the folders only change once their line loses focus, so the library is not
+    /// rescanned while they are typed.
+    fn apply_library_folders(&mut self, ctx: &egui::Context) {
+        if self.library_folders == self.settings.library_folders {
+            return;
+        }
+
+        // NOTE: Folders are only removed once the user confirmed it, their tracks go with them.
+        let removed: Vec<_> = self
+            .library_folders
+            .iter()
+            .filter(|folder| {
+                !self
+                    .settings
+                    .library_folders
+                    .iter()
+                    .any(|kept| kept.path == folder.path)
+            })
+            .map(|folder| folder.path.clone())
+            .collect();
+
+        self.library_folders = self.settings.library_folders.clone();
+        // NOTE: Drop the old watcher first so that both never watch the same folders.
+        drop(self.library_watcher.take());
+        self.library_watcher = watch_library(
+            &self.database,
+            &self.library,
+            &self.notifications,
+            ctx,
+            &self.library_folders,
+        );
+
+        let (scan_tx, scan_cancelled) = self.start_scan();
+        let library = self.library.clone();
+        let notifications = self.notifications.clone();
+        let database = self.database.clone();
+        let folders = self.library_folders.clone();
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            for path in removed {
+                if let Err(err) = database.remove_folder(&path, &folders) {
+                    debug!(
+                        "Failed to remove {} from the library: {err:?}",
+                        path.display()
+                    );
+                }
+            }
+
+            refresh_library(
+                &database,
+                &library,
+                &notifications,
+                &folders,
+                &scan_tx,
+                &scan_cancelled,
+            );
+            ctx.request_repaint();
+        });
+    }
+
+    /// Cancels the running scan, if any, and returns what a new one reports to.
+    fn start_scan(&mut self) -> (mpsc::Sender<ScanEvent>, Arc<AtomicBool>) {
+        if let Some(scan) = &self.library_scan {
+            scan.cancelled.store(true, Ordering::Relaxed);
+        }
+
+        let (events_tx, events) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        self.library_scan = Some(LibraryScan {
+            progress: ScanProgress::default(),
+            events,
+            cancelled: cancelled.clone(),
+        });
+
+        (events_tx, cancelled)
+    }
+
+    fn scan_progress(&mut self, ui: &mut egui::Ui) {
+        let Some(scan) = self.library_scan.as_mut() else {
+            return;
+        };
+
+        loop {
+            match scan.events.try_recv() {
+                Ok(event) => scan.progress.update(event),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.library_scan = None;
+                    return;
+                }
+            }
+        }
+
+        let ScanProgress {
+            found,
+            processed,
+            failed,
+        } = scan.progress;
+
+        ui.horizontal(|ui| {
+            if ui.button("Cancel").clicked() {
+                scan.cancelled.store(true, Ordering::Relaxed);
+            }
+
+            let progress = if found == 0 {
+                0.0
+            } else {
+                processed as f32 / found as f32
+            };
+            let text = if failed == 0 {
+                format!("Scanning library {processed}/{found}")
+            } else {
+                format!("Scanning library {processed}/{found}, {failed} failed")
+            };
+
+            ui.add(egui::ProgressBar::new(progress).text(text));
+        });
+
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+
+    fn analyze_loudness(&mut self, ctx: egui::Context) {
+        let analysis = Arc::new(LoudnessAnalysis::default());
+
+        {
+            let analysis = analysis.clone();
+            let library = self.library.clone();
+            let database = self.database.clone();
+            let write_tags = self.settings.write_replay_gain_tags;
+
+            thread::spawn(move || {
+                if let Err(err) = database.analyze_loudness(&analysis, write_tags) {
+                    debug!("Failed to analyze loudness: {err:?}");
+                }
+
+                if let Ok(tracks) = get_all_tracks(&database.get_connection()) {
+                    library.lock().replace(tracks);
+                }
+
+                analysis.finish();
+                ctx.request_repaint();
+            });
+        }
+
+        self.loudness_analysis = Some(analysis);
+    }
+}
+
+/// Marks the track at `index` as playing or paused, nothing is marked while stopped.
+fn playing_indicator(player: &MusicPlayer, index: Option<usize>) -> Option<TrackListIndicator> {
+    let index = index.filter(|_| !player.is_stopped())?;
+
+    Some(if player.is_paused() {
+        TrackListIndicator::Paused(index)
+    } else {
+        TrackListIndicator::Playing(index)
+    })
+}
+
+/// A track being played, until it is recorded in the listening history.
+struct Play {
+    track: Track,
+    started_at: SystemTime,
+    /// Time of the track listened to, in media time, seeks left out.
+    played: Duration,
+    /// Position at the last progress and when it was reported.
+    last_progress: Option<(Duration, Instant)>,
+}
+
+impl Play {
+    /// Longest time between two progresses counted as playback, playback is reported every
+    /// half a second so longer gaps come from pauses.
+    const MAX_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Leeway for progress reported late or early by the output.
+    const PROGRESS_LEEWAY: Duration = Duration::from_millis(250);
+
+    fn new(track: Track) -> Self {
+        Self {
+            track,
+            started_at: SystemTime::now(),
+            played: Duration::ZERO,
+            last_progress: None,
+        }
+    }
+
+    /// Adds the playback since the last progress, at `rate` times real time.
+    ///
+    /// Moves further than playback could have gone in the meantime are seeks, they are not
+    /// counted and neither are moves backwards.
+    fn progress(&mut self, position: Duration, rate: f32, now: Instant) {
+        if let Some((last_position, last_time)) = self.last_progress
+            && let Some(delta) = position.checked_sub(last_position)
+            && delta
+                <= now
+                    .saturating_duration_since(last_time)
+                    .min(Self::MAX_PROGRESS_INTERVAL)
+                    .mul_f32(rate)
+                    + Self::PROGRESS_LEEWAY
+        {
+            self.played += delta;
+        }
+
+        self.last_progress = Some((position, now));
+    }
+
+    /// Counts the playback up to the end of the track, once it has played to its end.
+    fn complete(&mut self, rate: f32) {
+        if let Some(duration) = self.track.duration {
+            self.progress(duration, rate, Instant::now());
+        }
+    }
+}
+
+/// Records the track being played in the listening history and counts it in the library.
+fn finish_play(
+    play: &mut Option<Play>,
+    database: &Database,
+    library: &Mutex<Library>,
+    completed: bool,
+) {
+    let Some(play) = play.take() else {
+        return;
+    };
+
+    if let Err(err) = insert_play(
+        &database.get_connection(),
+        &play.track,
+        play.started_at,
+        play.played,
+        completed,
+    ) {
+        debug!("Failed to record play: {err:?}");
+    }
+
+    for item in library
+        .lock()
+        .iter_mut()
+        .filter(|item| **item == play.track)
+    {
+        if completed {
+            item.play_count += 1;
+        } else {
+            item.skip_count += 1;
+        }
+        item.last_played = Some(play.started_at.into());
+    }
+}
+
+/// Records the track being played as played to its end, at `rate` times real time.
+fn complete_play(
+    play: &mut Option<Play>,
+    rate: f32,
+    database: &Database,
+    library: &Mutex<Library>,
+) {
+    if let Some(play) = play.as_mut() {
+        play.complete(rate);
+    }
+
+    finish_play(play, database, library, true);
+}
+
+/// Rates tracks of the library, their files are written to by `writer` if given.
+fn rate_tracks(
+    database: &Database,
+    library: &mut [Track],
+    indexes: &[usize],
+    rating: Option<u8>,
+    writer: Option<&mpsc::Sender<(PathBuf, Option<u8>)>>,
+) {
+    let conn = database.get_connection();
+
+    for index in indexes {
+        let Some(track) = library.get_mut(*index) else {
+            continue;
+        };
+        track.rating = rating;
+
+        if let Err(err) = set_rating(&conn, track, rating) {
+            debug!("Failed to rate {}: {err:?}", track.path.display());
+        }
+        // NOTE: Tags of a file split by a cue sheet cover all of its tracks.
+        if let Some(writer) = writer
+            && track.range.is_none()
+        {
+            writer.send((track.path.clone(), rating)).ok();
+        }
+    }
+}
+
+fn love_tracks(database: &Database, library: &mut [Track], indexes: &[usize], loved: bool) {
+    let conn = database.get_connection();
+
+    for index in indexes {
+        let Some(track) = library.get_mut(*index) else {
+            continue;
+        };
+        track.loved = loved;
+
+        if let Err(err) = set_loved(&conn, track, loved) {
+            debug!("Failed to love {}: {err:?}", track.path.display());
+        }
+    }
+}
+
+/// Writes ratings to the file tags in the background, in the order they are sent so that the
+/// last rating of a file is the one it keeps.
+fn spawn_rating_writer(database: Database) -> mpsc::Sender<(PathBuf, Option<u8>)> {
+    let (writer, ratings) = mpsc::channel::<(PathBuf, Option<u8>)>();
+
+    thread::spawn(move || {
+        for (path, rating) in ratings {
+            if let Err(err) = write_rating(&path, rating) {
+                debug!("Failed to write tags to {}: {err:?}", path.display());
+            } else if let Err(err) = set_modified(&database.get_connection(), &path) {
+                debug!("Failed to update database: {err:?}");
+            }
+        }
+    });
+
+    writer
+}
+
+/// Marks the file of a track as unplayable in the database and in the library.
+fn mark_unplayable(database: &Database, library: &Mutex<Library>, track: &Track) {
+    if let Err(err) = set_unplayable(&database.get_connection(), track, true) {
+        debug!("Failed to update database: {err:?}");
+    }
+
+    for item in library
+        .lock()
+        .iter_mut()
+        .filter(|item| item.path == track.path)
+    {
+        item.unplayable = true;
+    }
+}
+
+/// Refreshes the library from the library folders, errors are shown as notifications.
+fn refresh_library(
+    database: &Database,
+    library: &Mutex<Library>,
+    notifications: &Mutex<Vec<String>>,
+    folders: &[LibraryFolder],
+    progress: &mpsc::Sender<ScanEvent>,
+    cancelled: &AtomicBool,
+) {
+    match database.refresh_library(folders, false, progress, cancelled) {
+        Ok(errors) => notifications
+            .lock()
+            .extend(errors.iter().map(ToString::to_string)),
+        Err(err) => notifications.lock().push(err.to_string()),
+    }
+
+    match get_all_tracks(&database.get_connection()) {
+        Ok(tracks) => library.lock().replace(tracks),
+        Err(err) => debug!("Failed to reload library: {err:?}"),
+    }
+}
+
+/// Restores the playlist of the last session.
+fn load_playlist(player: &Mutex<MusicPlayer>) {
+    match Playlist::new_from_file(&get_default_app_dir_config().join("default.m3u")) {
+        Ok(playlist) => {
+            *player.lock().playlist_mut() = playlist;
+        }
+        Err(err) => {
+            if err.kind() == io::ErrorKind::NotFound {
+                debug!("Current playlist not found.");
+            } else {
+                debug!("{err:?}");
+            }
+        }
+    }
+}
+
+/// Updates the library whenever files in the library folders change.
+fn watch_library(
+    database: &Database,
+    library: &Arc<Mutex<Library>>,
+    notifications: &Arc<Mutex<Vec<String>>>,
+    ctx: &egui::Context,
+    folders: &[LibraryFolder],
+) -> Option<LibraryWatcher> {
+    let roots: Vec<_> = folders.iter().map(|folder| folder.path.clone()).collect();
+    let folders = folders.to_vec();
+
+    let database = database.clone();
+    let library = library.clone();
+    let notifications = notifications.clone();
+    let ctx = ctx.clone();
+
+    let watcher = LibraryWatcher::new(&roots, move |paths| {
+        match database.update_files(&folders, &paths) {
+            Ok(updated) => {
+                library.lock().patch(&updated.paths, updated.tracks);
+                notifications
+                    .lock()
+                    .extend(updated.errors.iter().map(ToString::to_string));
+            }
+            Err(err) => notifications.lock().push(err.to_string()),
+        }
+
+        ctx.request_repaint();
+    });
+
+    watcher
+        .inspect_err(|err| debug!("Failed to watch the library folders: {err:?}"))
+        .ok()
+}
+
+/// Loads the waveform of a track from the database, or measures it in the background.
+///
+/// Returns the flag that cancels the measurement.
+fn load_waveform(
+    ctx: &egui::Context,
+    database: &Database,
+    waveform: &Arc<Mutex<Option<(Track, Waveform)>>>,
+    track: Track,
+) -> Arc<AtomicBool> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    if let Ok(Some(stored)) = get_waveform(&database.get_connection(), &track) {
+        *waveform.lock() = Some((track, stored));
+        return cancelled;
+    }
+
+    let ctx = ctx.clone();
+    let database = database.clone();
+    let waveform = waveform.clone();
+
+    thread::spawn({
+        let cancelled = cancelled.clone();
+
+        move || match measure(&track, &cancelled) {
+            Ok(Some(measured)) => {
+                if let Err(err) = set_waveform(&database.get_connection(), &track, &measured) {
+                    debug!("Failed to store waveform: {err:?}");
+                }
+
+                *waveform.lock() = Some((track, measured));
+                ctx.request_repaint();
+            }
+            Ok(None) => {}
+            Err(err) => debug!(
+                "Failed to measure waveform of {}: {err:?}",
+                track.path.display()
+            ),
+        }
+    });
+
+    cancelled
 }
 
 impl eframe::App for App {
@@ -309,5 +1293,89 @@ impl eframe::App for App {
         egui::CentralPanel::default()
             .frame(frame)
             .show(ctx, |ui| self.body(ui));
+
+        let mut show_settings = self.show_settings;
+        egui::Window::new("Settings")
+            .open(&mut show_settings)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| self.settings(ui));
+        self.show_settings = show_settings;
+
+        self.apply_library_folders(ctx);
+
+        let mut show_equalizer = self.show_equalizer;
+        egui::Window::new("Equalizer")
+            .open(&mut show_equalizer)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| self.equalizer(ui));
+        self.show_equalizer = show_equalizer;
+
+        // NOTE: Dragging a gain or a bitrate changes it every frame, it is applied right away but
+        // only saved once the drag stops.
+        if self.settings_unsaved && ctx.dragged_id().is_none() {
+            self.save_settings(&mut self.player.lock());
+            self.settings_unsaved = false;
+        }
+
+        let mut show_track_info = self.track_info.is_some();
+        if let Some(track) = self.track_info.as_ref() {
+            egui::Window::new("Track info")
+                .open(&mut show_track_info)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| ui.add(TrackInfo::new(track)));
+        }
+        if !show_track_info {
+            self.track_info = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::Play;
+    use crate::track::Track;
+
+    #[test]
+    fn counts_played_time_without_seeks() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut play = Play::new(Track {
+            duration: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+
+        play.progress(Duration::ZERO, 1.0, at(0));
+        play.progress(Duration::from_millis(500), 1.0, at(500));
+        play.progress(Duration::from_secs(1), 1.0, at(1_000));
+        // NOTE: Seek forward, then backward.
+        play.progress(Duration::from_secs(50), 1.0, at(1_500));
+        play.progress(Duration::from_millis(50_500), 1.0, at(2_000));
+        play.progress(Duration::from_secs(10), 1.0, at(2_500));
+        assert_eq!(
+            play.played,
+            Duration::from_millis(1_500),
+            "Only playback should be counted."
+        );
+
+        // NOTE: Twice as much media time is played at double speed.
+        play.progress(Duration::from_secs(11), 2.0, at(3_000));
+        assert_eq!(
+            play.played,
+            Duration::from_millis(2_500),
+            "Playback at double speed should be counted."
+        );
+
+        // NOTE: A seek while paused is not playback either.
+        play.progress(Duration::from_secs(40), 1.0, at(60_000));
+        assert_eq!(
+            play.played,
+            Duration::from_millis(2_500),
+            "Seek after a pause should not be counted."
+        );
     }
 }
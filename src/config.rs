@@ -1,8 +1,29 @@
-use std::{path::PathBuf, sync::Arc};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::LazyLock;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use eframe::egui::{FontData, FontDefinitions, FontFamily};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::debug;
+use rusqlite::Connection;
+
+use crate::database::{
+    delete_settings_by_prefix, get_setting, get_settings_by_prefix, set_setting,
+};
+use crate::player::{
+    AutomaticEqualizer, Crossfade, CrossfadeCurve, Equalizer, EqualizerBand, EqualizerMode,
+    FilterKind, GeneralMusicPlayer, ReplayGainMode,
+};
+use crate::ui::track_list::{ExtraColumn, PropertiesFilter, Quality, TrackListSettings};
 
 pub const COVER_IMAGE_SIZE: (f32, f32) = (256., 256.);
+pub const VISUALIZER_HEIGHT: f32 = 96.;
+
+/// Length of the volume ramps on pause, stop and seek until another one is picked.
+const DEFAULT_TRANSPORT_FADE: Duration = Duration::from_millis(40);
 
 pub fn get_font_definitions() -> FontDefinitions {
     let mut font_definitions = FontDefinitions::default();
@@ -50,3 +71,634 @@ pub fn get_default_app_dir_config() -> PathBuf {
 pub fn get_default_audio_dir_config() -> Option<PathBuf> {
     dirs::audio_dir()
 }
+
+/// A folder whose music files are part of the library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryFolder {
+    pub path: PathBuf,
+    /// Glob patterns of the files to add, relative to the folder, every file is added when
+    /// there are none. Patterns cannot contain `;`, which separates them where they're stored.
+    pub include: Vec<String>,
+    /// Glob patterns of the files and directories to leave out, relative to the folder.
+    pub exclude: Vec<String>,
+    pub follow_symlinks: bool,
+}
+
+impl LibraryFolder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            follow_symlinks: true,
+        }
+    }
+
+    /// Compiles the patterns of the folder, invalid ones are skipped.
+    pub fn filter(&self) -> FolderFilter {
+        FolderFilter {
+            root: self.path.clone(),
+            include: Some(glob_set(&self.include)).filter(|set| !set.is_empty()),
+            exclude: glob_set(&self.exclude),
+            follow_symlinks: self.follow_symlinks,
+        }
+    }
+}
+
+/// Stored as the follow symlinks flag and the include and exclude patterns, one per line.
+impl fmt::Display for LibraryFolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\n{}\n{}",
+            self.follow_symlinks,
+            self.include.join(";"),
+            self.exclude.join(";")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLibraryFolderError;
+
+impl FromStr for LibraryFolder {
+    type Err = ParseLibraryFolderError;
+
+    /// Parses the options of a folder, the path is stored in the key of the setting.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.splitn(3, '\n');
+        let (Some(follow_symlinks), Some(include), Some(exclude)) =
+            (lines.next(), lines.next(), lines.next())
+        else {
+            return Err(ParseLibraryFolderError);
+        };
+
+        let patterns = |value: &str| {
+            value
+                .split(';')
+                .filter(|pattern| !pattern.is_empty())
+                .map(ToOwned::to_owned)
+                .collect()
+        };
+
+        Ok(Self {
+            path: PathBuf::new(),
+            include: patterns(include),
+            exclude: patterns(exclude),
+            follow_symlinks: follow_symlinks
+                .parse()
+                .map_err(|_err| ParseLibraryFolderError)?,
+        })
+    }
+}
+
+/// Decides which files of a `LibraryFolder` are part of the library.
+#[derive(Debug, Clone)]
+pub struct FolderFilter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    follow_symlinks: bool,
+}
+
+impl FolderFilter {
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    /// Whether a file is in the folder, matches one of the include patterns if there are any
+    /// and neither it nor a directory it is in matches one of the exclude patterns.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root).is_ok_and(|relative| {
+            !relative
+                .ancestors()
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .any(|ancestor| self.exclude.is_match(ancestor))
+                && self
+                    .include
+                    .as_ref()
+                    .is_none_or(|include| include.is_match(relative))
+        })
+    }
+
+    /// Whether a directory in the folder is left out along with everything in it.
+    pub fn excludes_dir(&self, path: &Path) -> bool {
+        path.strip_prefix(&self.root)
+            .is_ok_and(|relative| self.exclude.is_match(relative))
+    }
+}
+
+fn glob_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns.iter().map(|pattern| pattern.trim()) {
+        if pattern.is_empty() {
+            continue;
+        }
+
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => debug!("Invalid pattern {pattern}: {err}"),
+        }
+    }
+
+    builder.build().unwrap_or_else(|err| {
+        debug!("{err:?}");
+        GlobSet::empty()
+    })
+}
+
+/// Presets shipped with the player, they cannot be removed or overwritten.
+pub static BUILTIN_EQUALIZER_PRESETS: LazyLock<Vec<EqualizerPreset>> = LazyLock::new(|| {
+    let graphic = |name: &str, preamp, gains| EqualizerPreset {
+        name: name.to_owned(),
+        equalizer: Equalizer {
+            preamp,
+            mode: EqualizerMode::Graphic(gains),
+        },
+    };
+    let shelf = |kind, frequency, gain| EqualizerBand {
+        kind,
+        frequency,
+        gain,
+        q: 0.707,
+    };
+
+    vec![
+        graphic("Flat", 0.0, [0.0; 10]),
+        graphic(
+            "Rock",
+            -4.0,
+            [4.0, 3.0, 2.0, 0.0, -1.0, -1.0, 1.0, 2.0, 3.0, 4.0],
+        ),
+        graphic(
+            "Pop",
+            -3.0,
+            [-1.0, 0.0, 2.0, 3.0, 3.0, 2.0, 0.0, -1.0, -1.0, -1.0],
+        ),
+        graphic(
+            "Jazz",
+            -3.0,
+            [3.0, 2.0, 1.0, 2.0, -1.0, -1.0, 0.0, 1.0, 2.0, 3.0],
+        ),
+        graphic(
+            "Classical",
+            -3.0,
+            [3.0, 2.0, 1.0, 0.0, 0.0, 0.0, -1.0, -1.0, 1.0, 2.0],
+        ),
+        graphic(
+            "Electronic",
+            -5.0,
+            [5.0, 4.0, 1.0, 0.0, -2.0, 1.0, 0.0, 1.0, 4.0, 5.0],
+        ),
+        graphic(
+            "Vocal",
+            -3.0,
+            [-2.0, -2.0, -1.0, 1.0, 3.0, 3.0, 2.0, 1.0, 0.0, -1.0],
+        ),
+        EqualizerPreset {
+            name: "Bass boost".to_owned(),
+            equalizer: Equalizer {
+                preamp: -6.0,
+                mode: EqualizerMode::Parametric(vec![shelf(FilterKind::LowShelf, 120.0, 6.0)]),
+            },
+        },
+        EqualizerPreset {
+            name: "Treble boost".to_owned(),
+            equalizer: Equalizer {
+                preamp: -6.0,
+                mode: EqualizerMode::Parametric(vec![shelf(FilterKind::HighShelf, 6_000.0, 6.0)]),
+            },
+        },
+    ]
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqualizerPreset {
+    pub name: String,
+    pub equalizer: Equalizer,
+}
+
+/// User preferences, stored in the library database.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub crossfade: Option<Crossfade>,
+    /// Length of the volume ramps on pause, resume, stop, seek and skip.
+    pub transport_fade: Duration,
+    pub replay_gain: ReplayGainMode,
+    pub write_replay_gain_tags: bool,
+    /// Whether ratings are also written to the file tags, so that they outlive the library.
+    pub write_rating_tags: bool,
+    /// Name of the output device, `None` for the system default.
+    pub output_device: Option<String>,
+
+    pub equalizer_enabled: bool,
+    pub equalizer: Equalizer,
+    /// Presets saved by the user, see `BUILTIN_EQUALIZER_PRESETS` for the others.
+    pub equalizer_presets: Vec<EqualizerPreset>,
+    /// Preset names by lowercase genre.
+    pub genre_equalizer_presets: BTreeMap<String, String>,
+    /// Preset names by track path.
+    pub track_equalizer_presets: BTreeMap<PathBuf, String>,
+
+    /// Folders scanned for music, the audio directory of the user until others are picked.
+    pub library_folders: Vec<LibraryFolder>,
+
+    /// Extra columns and filter of the library and playlists.
+    pub track_list: TrackListSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            crossfade: None,
+            transport_fade: DEFAULT_TRANSPORT_FADE,
+            replay_gain: ReplayGainMode::default(),
+            write_replay_gain_tags: false,
+            write_rating_tags: false,
+            output_device: None,
+
+            equalizer_enabled: false,
+            equalizer: Equalizer::default(),
+            equalizer_presets: Vec::new(),
+            genre_equalizer_presets: BTreeMap::new(),
+            track_equalizer_presets: BTreeMap::new(),
+
+            library_folders: Vec::new(),
+
+            track_list: TrackListSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(conn: &Connection) -> Self {
+        let get = |key: &str| get_setting(conn, key).ok().flatten();
+
+        Self {
+            crossfade: get("crossfade_duration")
+                .and_then(|value| value.parse::<u64>().ok())
+                .filter(|value| *value > 0)
+                .map(|value| Crossfade {
+                    duration: Duration::from_millis(value),
+                    curve: match get("crossfade_curve").as_deref() {
+                        Some("equal_power") => CrossfadeCurve::EqualPower,
+                        _ => CrossfadeCurve::Linear,
+                    },
+                }),
+            transport_fade: get("transport_fade")
+                .and_then(|value| value.parse::<u64>().ok())
+                .map_or(DEFAULT_TRANSPORT_FADE, Duration::from_millis),
+            replay_gain: match get("replay_gain").as_deref() {
+                Some("track") => ReplayGainMode::Track,
+                Some("album") => ReplayGainMode::Album,
+                _ => ReplayGainMode::Off,
+            },
+            write_replay_gain_tags: get("write_replay_gain_tags").as_deref() == Some("true"),
+            write_rating_tags: get("write_rating_tags").as_deref() == Some("true"),
+            output_device: get("output_device").filter(|value| !value.is_empty()),
+
+            equalizer_enabled: get("equalizer_enabled").as_deref() == Some("true"),
+            equalizer: get("equalizer")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default(),
+            equalizer_presets: get_settings_by_prefix(conn, "equalizer_preset:")
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(name, value)| {
+                    Some(EqualizerPreset {
+                        name,
+                        equalizer: value.parse().ok()?,
+                    })
+                })
+                .collect(),
+            genre_equalizer_presets: get_settings_by_prefix(conn, "equalizer_genre:")
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            track_equalizer_presets: get_settings_by_prefix(conn, "equalizer_track:")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(path, name)| (PathBuf::from(path), name))
+                .collect(),
+
+            library_folders: load_library_folders(conn),
+
+            track_list: load_track_list(conn),
+        }
+    }
+
+    /// Built-in presets followed by the ones saved by the user.
+    pub fn all_equalizer_presets(&self) -> impl Iterator<Item = &EqualizerPreset> {
+        BUILTIN_EQUALIZER_PRESETS
+            .iter()
+            .chain(&self.equalizer_presets)
+    }
+
+    pub fn equalizer_preset_names(&self) -> Vec<String> {
+        self.all_equalizer_presets()
+            .map(|preset| preset.name.clone())
+            .collect()
+    }
+
+    /// Assigns a preset to a track, `None` removes its preset.
+    pub fn set_track_equalizer_preset(&mut self, path: PathBuf, name: Option<String>) {
+        if let Some(name) = name {
+            self.track_equalizer_presets.insert(path, name);
+        } else {
+            self.track_equalizer_presets.remove(&path);
+        }
+    }
+
+    pub fn find_equalizer_preset(&self, name: &str) -> Option<&EqualizerPreset> {
+        self.all_equalizer_presets()
+            .find(|preset| preset.name == name)
+    }
+
+    pub fn apply(&self, player: &mut impl GeneralMusicPlayer) {
+        player.set_crossfade(self.crossfade);
+        player.set_transport_fade(self.transport_fade);
+        player.set_replay_gain_mode(self.replay_gain);
+        player.set_output_device(self.output_device.clone());
+        player.set_equalizer(self.equalizer_enabled.then(|| self.equalizer.clone()));
+
+        let equalizer = |name: &String| {
+            self.find_equalizer_preset(name)
+                .map(|preset| preset.equalizer.clone())
+        };
+
+        player.set_automatic_equalizer(AutomaticEqualizer {
+            tracks: self
+                .track_equalizer_presets
+                .iter()
+                .filter_map(|(path, name)| Some((path.clone(), equalizer(name)?)))
+                .collect(),
+            genres: self
+                .genre_equalizer_presets
+                .iter()
+                .filter_map(|(genre, name)| Some((genre.clone(), equalizer(name)?)))
+                .collect(),
+        });
+    }
+
+    pub fn save(&self, conn: &Connection) -> Result<(), rusqlite::Error> {
+        set_setting(
+            conn,
+            "crossfade_duration",
+            &self
+                .crossfade
+                .map_or(0, |crossfade| crossfade.duration.as_millis())
+                .to_string(),
+        )?;
+
+        if let Some(crossfade) = self.crossfade {
+            set_setting(
+                conn,
+                "crossfade_curve",
+                match crossfade.curve {
+                    CrossfadeCurve::Linear => "linear",
+                    CrossfadeCurve::EqualPower => "equal_power",
+                },
+            )?;
+        }
+
+        set_setting(
+            conn,
+            "transport_fade",
+            &self.transport_fade.as_millis().to_string(),
+        )?;
+
+        set_setting(
+            conn,
+            "replay_gain",
+            match self.replay_gain {
+                ReplayGainMode::Off => "off",
+                ReplayGainMode::Track => "track",
+                ReplayGainMode::Album => "album",
+            },
+        )?;
+
+        set_setting(
+            conn,
+            "write_replay_gain_tags",
+            &self.write_replay_gain_tags.to_string(),
+        )?;
+
+        set_setting(
+            conn,
+            "write_rating_tags",
+            &self.write_rating_tags.to_string(),
+        )?;
+
+        set_setting(
+            conn,
+            "output_device",
+            self.output_device.as_deref().unwrap_or_default(),
+        )?;
+
+        set_setting(
+            conn,
+            "equalizer_enabled",
+            &self.equalizer_enabled.to_string(),
+        )?;
+        set_setting(conn, "equalizer", &self.equalizer.to_string())?;
+
+        // NOTE: Rewrite keyed settings entirely so that removed entries are removed as well.
+        delete_settings_by_prefix(conn, "equalizer_preset:")?;
+        for preset in &self.equalizer_presets {
+            set_setting(
+                conn,
+                &format!("equalizer_preset:{}", preset.name),
+                &preset.equalizer.to_string(),
+            )?;
+        }
+
+        delete_settings_by_prefix(conn, "equalizer_genre:")?;
+        for (genre, name) in &self.genre_equalizer_presets {
+            set_setting(conn, &format!("equalizer_genre:{genre}"), name)?;
+        }
+
+        delete_settings_by_prefix(conn, "equalizer_track:")?;
+        for (path, name) in &self.track_equalizer_presets {
+            set_setting(
+                conn,
+                &format!("equalizer_track:{}", path.to_string_lossy()),
+                name,
+            )?;
+        }
+
+        // NOTE: The count tells a library without folders apart from one never configured.
+        set_setting(
+            conn,
+            "library_folders",
+            &self.library_folders.len().to_string(),
+        )?;
+        delete_settings_by_prefix(conn, "library_folder:")?;
+        for folder in &self.library_folders {
+            set_setting(
+                conn,
+                &format!("library_folder:{}", folder.path.to_string_lossy()),
+                &folder.to_string(),
+            )?;
+        }
+
+        save_track_list(conn, &self.track_list)
+    }
+}
+
+fn load_library_folders(conn: &Connection) -> Vec<LibraryFolder> {
+    if get_setting(conn, "library_folders")
+        .ok()
+        .flatten()
+        .is_none()
+    {
+        return get_default_audio_dir_config()
+            .map(LibraryFolder::new)
+            .into_iter()
+            .collect();
+    }
+
+    get_settings_by_prefix(conn, "library_folder:")
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(path, value)| {
+            Some(LibraryFolder {
+                path: PathBuf::from(path),
+                ..value.parse().ok()?
+            })
+        })
+        .collect()
+}
+
+fn load_track_list(conn: &Connection) -> TrackListSettings {
+    let get = |key: &str| get_setting(conn, key).ok().flatten();
+
+    TrackListSettings {
+        columns: get("track_list_columns")
+            .unwrap_or_default()
+            .split(';')
+            .filter_map(ExtraColumn::from_key)
+            .collect(),
+        filter: PropertiesFilter {
+            quality: match get("track_list_quality").as_deref() {
+                Some("lossless") => Quality::Lossless,
+                Some("lossy") => Quality::Lossy,
+                _ => Quality::Any,
+            },
+            max_bitrate: get("track_list_max_bitrate").and_then(|value| value.parse().ok()),
+        },
+    }
+}
+
+fn save_track_list(
+    conn: &Connection,
+    track_list: &TrackListSettings,
+) -> Result<(), rusqlite::Error> {
+    set_setting(
+        conn,
+        "track_list_columns",
+        &track_list
+            .columns
+            .iter()
+            .map(|column| column.key())
+            .collect::<Vec<_>>()
+            .join(";"),
+    )?;
+    set_setting(
+        conn,
+        "track_list_quality",
+        match track_list.filter.quality {
+            Quality::Any => "any",
+            Quality::Lossless => "lossless",
+            Quality::Lossy => "lossy",
+        },
+    )?;
+    set_setting(
+        conn,
+        "track_list_max_bitrate",
+        &track_list
+            .filter
+            .max_bitrate
+            .map(|bitrate| bitrate.to_string())
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filters_library_folder() {
+        let folder = LibraryFolder {
+            include: vec!["*.flac".to_owned(), String::new()],
+            exclude: vec!["Podcasts".to_owned(), "**/demo*".to_owned()],
+            ..LibraryFolder::new(PathBuf::from("/music"))
+        };
+        let filter = folder.filter();
+
+        assert!(
+            filter.contains(Path::new("/music/Artist/Album/01.flac")),
+            "Included file is in the folder."
+        );
+        assert!(
+            !filter.contains(Path::new("/music/Artist/Album/01.mp3")),
+            "File not matching an include pattern is left out."
+        );
+        assert!(
+            !filter.contains(Path::new("/music/Artist/demo.flac")),
+            "Excluded file is left out."
+        );
+        assert!(
+            !filter.contains(Path::new("/other/01.flac")),
+            "File outside of the folder is left out."
+        );
+        assert!(
+            filter.excludes_dir(Path::new("/music/Podcasts")),
+            "Excluded directory is left out."
+        );
+        assert!(
+            !filter.contains(Path::new("/music/Podcasts/episode.flac")),
+            "File in an excluded directory is left out."
+        );
+
+        let parsed: LibraryFolder = folder.to_string().parse().expect("Folder parsed.");
+        assert_eq!(
+            parsed.exclude, folder.exclude,
+            "Patterns survive being stored."
+        );
+    }
+
+    #[test]
+    fn stores_track_list_settings() {
+        let mut conn = Connection::open_in_memory().expect("In-memory database opened.");
+        crate::database::migrate(&mut conn).expect("Database migrated.");
+
+        let mut settings = Settings::load(&conn);
+        assert_eq!(
+            settings.track_list,
+            TrackListSettings::default(),
+            "No extra column or filter should be set at first."
+        );
+
+        settings.track_list = TrackListSettings {
+            columns: vec![ExtraColumn::Codec, ExtraColumn::PlayCount],
+            filter: PropertiesFilter {
+                quality: Quality::Lossy,
+                max_bitrate: Some(160),
+            },
+        };
+        settings.save(&conn).expect("Settings saved.");
+        assert_eq!(
+            Settings::load(&conn).track_list,
+            settings.track_list,
+            "Columns and filter should survive being stored."
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::track::{ReplayGain, Track, TrackRange, parse_gain};
+
+/// Cue sheet timestamps count frames of 1/75 second, as on audio CDs.
+const FRAMES_PER_SECOND: f64 = 75.0;
+
+/// Fields of a cue sheet used to split a single-file album rip into tracks.
+#[derive(Default, Debug, Clone)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub genre: Option<String>,
+    pub disc: Option<String>,
+    pub disc_total: Option<String>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+    pub files: Vec<CueFile>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct CueFile {
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Position of `INDEX 01`, tracks without one are not playable.
+    pub start: Option<Duration>,
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+}
+
+impl CueSheet {
+    /// Parses a cue sheet, unknown commands and malformed lines are ignored.
+    pub fn parse(content: &str) -> Self {
+        let mut sheet = Self::default();
+
+        for line in content.trim_start_matches('\u{feff}').lines() {
+            let (command, arguments) = split_word(line.trim());
+            let track = sheet
+                .files
+                .last_mut()
+                .and_then(|file| file.tracks.last_mut());
+
+            match (command.to_ascii_uppercase().as_str(), track) {
+                ("FILE", _) => sheet.files.push(CueFile {
+                    name: file_name(arguments),
+                    tracks: Vec::new(),
+                }),
+                ("TRACK", _) => {
+                    let number = split_word(arguments).0.parse().unwrap_or_default();
+
+                    if let Some(file) = sheet.files.last_mut() {
+                        file.tracks.push(CueTrack {
+                            number,
+                            ..Default::default()
+                        });
+                    }
+                }
+                ("INDEX", Some(track)) => {
+                    let (number, time) = split_word(arguments);
+
+                    if number.parse::<u32>() == Ok(1) {
+                        track.start = parse_time(time);
+                    }
+                }
+                ("TITLE", Some(track)) => track.title = Some(unquote(arguments)),
+                ("TITLE", None) => sheet.title = Some(unquote(arguments)),
+                ("PERFORMER", Some(track)) => track.performer = Some(unquote(arguments)),
+                ("PERFORMER", None) => sheet.performer = Some(unquote(arguments)),
+                ("REM", track) => {
+                    let (key, value) = split_word(arguments);
+                    let value = unquote(value);
+
+                    match (key.to_ascii_uppercase().as_str(), track) {
+                        ("REPLAYGAIN_TRACK_GAIN", Some(track)) => {
+                            track.track_gain = parse_gain(&value);
+                        }
+                        ("REPLAYGAIN_TRACK_PEAK", Some(track)) => {
+                            track.track_peak = value.parse().ok();
+                        }
+                        ("REPLAYGAIN_ALBUM_GAIN", _) => sheet.album_gain = parse_gain(&value),
+                        ("REPLAYGAIN_ALBUM_PEAK", _) => sheet.album_peak = value.parse().ok(),
+                        ("GENRE", _) => sheet.genre = Some(value),
+                        ("DISCNUMBER", _) => sheet.disc = Some(value),
+                        ("TOTALDISCS", _) => sheet.disc_total = Some(value),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        sheet
+    }
+
+    /// Splits a music file into one track per cue sheet index.
+    ///
+    /// Cue sheet values take precedence over the tags of `file`. Returns an empty list when
+    /// the cue sheet has no playable track for the file.
+    pub fn split(&self, file: &Track) -> Vec<Track> {
+        let Some(cue_file) = self.find_file(&file.path) else {
+            return Vec::new();
+        };
+
+        let tracks = cue_file
+            .tracks
+            .iter()
+            .filter_map(|track| Some((track, track.start?)))
+            .collect::<Vec<_>>();
+        let track_total = tracks.len().to_string();
+
+        tracks
+            .iter()
+            .enumerate()
+            .map(|(index, (track, start))| {
+                let end = tracks.get(index + 1).map(|(_, end)| *end);
+
+                Track {
+                    title: track.title.clone().or_else(|| file.title.clone()),
+                    artist: track
+                        .performer
+                        .clone()
+                        .or_else(|| self.performer.clone())
+                        .or_else(|| file.artist.clone()),
+                    genre: self.genre.clone().or_else(|| file.genre.clone()),
+                    album: self.title.clone().or_else(|| file.album.clone()),
+                    album_artist: self.performer.clone().or_else(|| file.album_artist.clone()),
+                    duration: end.or(file.duration).map(|end| end.saturating_sub(*start)),
+                    disc: self.disc.clone().or_else(|| file.disc.clone()),
+                    disc_total: self.disc_total.clone().or_else(|| file.disc_total.clone()),
+                    track: Some(track.number.to_string()),
+                    track_total: Some(track_total.clone()),
+                    replay_gain: ReplayGain {
+                        track_gain: track.track_gain,
+                        track_peak: track.track_peak,
+                        album_gain: self.album_gain.or(file.replay_gain.album_gain),
+                        album_peak: self.album_peak.or(file.replay_gain.album_peak),
+                    },
+                    range: Some(TrackRange { start: *start, end }),
+                    // NOTE: A rating in the tags is meant for the whole file.
+                    rating: None,
+                    ..file.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// The `FILE` entry naming `path`, or the only one since rips are often re-encoded
+    /// without updating their cue sheet.
+    fn find_file(&self, path: &Path) -> Option<&CueFile> {
+        let name = path.file_name()?.to_string_lossy();
+
+        self.files
+            .iter()
+            .find(|file| {
+                Path::new(&file.name).file_name().is_some_and(|file_name| {
+                    file_name.to_string_lossy().eq_ignore_ascii_case(&name)
+                })
+            })
+            .or(match self.files.as_slice() {
+                [file] => Some(file),
+                _ => None,
+            })
+    }
+}
+
+fn split_word(value: &str) -> (&str, &str) {
+    value
+        .split_once(char::is_whitespace)
+        .map_or((value, ""), |(word, rest)| (word, rest.trim_start()))
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_owned()
+}
+
+/// File names may contain spaces when unquoted, the last word is the file type.
+fn file_name(arguments: &str) -> String {
+    let arguments = arguments.trim();
+
+    if let Some(quoted) = arguments.strip_prefix('"') {
+        return quoted
+            .split_once('"')
+            .map_or(quoted, |(name, _)| name)
+            .to_owned();
+    }
+
+    arguments
+        .rsplit_once(char::is_whitespace)
+        .map_or(arguments, |(name, _)| name.trim_end())
+        .to_owned()
+}
+
+/// Parses `mm:ss:ff` timestamps.
+fn parse_time(value: &str) -> Option<Duration> {
+    let mut parts = value.trim().splitn(3, ':').map(str::parse::<u32>);
+    let (Some(Ok(minutes)), Some(Ok(seconds)), Some(Ok(frames))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    Some(Duration::from_secs_f64(
+        f64::from(minutes * 60 + seconds) + f64::from(frames) / FRAMES_PER_SECOND,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::CueSheet;
+    use crate::track::{Track, TrackRange};
+
+    const SHEET: &str = "\u{feff}REM GENRE \"Post Rock\"
+REM DISCNUMBER 1
+REM REPLAYGAIN_ALBUM_GAIN -7.50 dB
+PERFORMER \"Band\"
+TITLE \"Album\"
+FILE \"Album.FLAC\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Opening\"
+    REM REPLAYGAIN_TRACK_GAIN -6.20 dB
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Hidden\"
+  TRACK 03 AUDIO
+    TITLE \"Closing\"
+    PERFORMER \"Guest\"
+    INDEX 00 03:13:00
+    INDEX 01 03:15:37
+";
+
+    #[test]
+    fn parses_cue_sheet() {
+        let sheet = CueSheet::parse(SHEET);
+
+        assert_eq!(
+            sheet.title.as_deref(),
+            Some("Album"),
+            "Album title is read."
+        );
+        assert_eq!(
+            sheet.genre.as_deref(),
+            Some("Post Rock"),
+            "Remarks are read."
+        );
+        assert_eq!(sheet.album_gain, Some(-7.5), "Album gain is read.");
+
+        let file = sheet.files.first().expect("File read.");
+        assert_eq!(file.name, "Album.FLAC", "File name is unquoted.");
+        assert_eq!(file.tracks.len(), 3, "Every track is read.");
+
+        let last = file.tracks.last().expect("Track read.");
+        assert_eq!(
+            last.performer.as_deref(),
+            Some("Guest"),
+            "Track performer is read."
+        );
+        assert_eq!(
+            last.start,
+            Some(Duration::from_secs_f64(195.0 + 37.0 / 75.0)),
+            "Track starts at index 01, in frames of 1/75 second."
+        );
+        assert!(
+            file.tracks
+                .get(1)
+                .is_some_and(|track| track.start.is_none()),
+            "Track without index 01 has no start."
+        );
+    }
+
+    #[test]
+    fn splits_file_into_tracks() {
+        let file = Track {
+            path: PathBuf::from("/music/album.flac"),
+            title: Some("Whole".to_owned()),
+            album: Some("Tagged".to_owned()),
+            duration: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+
+        let tracks = CueSheet::parse(SHEET).split(&file);
+        let [first, last] = tracks.as_slice() else {
+            panic!("Tracks without a start should be left out, got {tracks:?}.");
+        };
+        let start = Duration::from_secs_f64(195.0 + 37.0 / 75.0);
+
+        assert_eq!(
+            first.title.as_deref(),
+            Some("Opening"),
+            "Cue titles are used."
+        );
+        assert_eq!(
+            first.album.as_deref(),
+            Some("Album"),
+            "Cue sheet values take precedence over tags."
+        );
+        assert_eq!(
+            first.artist.as_deref(),
+            Some("Band"),
+            "Album performer is used."
+        );
+        assert_eq!(
+            first.replay_gain.track_gain,
+            Some(-6.2),
+            "Track gain is read."
+        );
+        assert_eq!(
+            first.range,
+            Some(TrackRange {
+                start: Duration::ZERO,
+                end: Some(start),
+            }),
+            "Track ends where the next one starts."
+        );
+        assert_eq!(
+            last.duration,
+            Some(Duration::from_secs(600).saturating_sub(start)),
+            "Last track lasts until the end of the file."
+        );
+        assert_eq!(
+            last.track_total.as_deref(),
+            Some("2"),
+            "Playable tracks are counted."
+        );
+
+        let other = CueSheet::parse("FILE \"a.flac\" WAVE\nFILE \"b.flac\" WAVE\n");
+        assert!(
+            other.split(&file).is_empty(),
+            "Sheet naming other files should not split the file."
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+use lofty::error::LoftyError;
+
+/// Errors while updating the library from the music files.
+#[derive(Debug)]
+pub enum LibraryError {
+    Database(rusqlite::Error),
+    /// A schema migration failed, the database is left at the version before it.
+    Migration(usize, rusqlite::Error),
+    /// The database comes from a newer version of the player.
+    UnknownVersion(usize),
+    /// The metadata of a music file cannot be read, the file is kept in the library as unplayable.
+    Read(PathBuf, LoftyError),
+}
+
+impl fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(err) => write!(f, "Cannot update the library: {err}"),
+            Self::Migration(version, err) => {
+                write!(f, "Cannot migrate the library to version {version}: {err}")
+            }
+            Self::UnknownVersion(version) => {
+                write!(f, "Library version {version} is newer than this player")
+            }
+            Self::Read(path, err) => write!(f, "Cannot read {}: {err}", path.display()),
+        }
+    }
+}
+
+impl Error for LibraryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Database(err) | Self::Migration(_, err) => Some(err),
+            Self::Read(_, err) => Some(err),
+            Self::UnknownVersion(_) => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LibraryError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Database(err)
+    }
+}
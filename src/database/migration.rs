@@ -0,0 +1,162 @@
+use log::info;
+use rusqlite::{Connection, named_params};
+
+use super::LibraryError;
+
+/// Schema migrations in the order they are applied, the schema version is the number applied.
+///
+/// The version is kept in `PRAGMA user_version`, so migrations must never be changed or
+/// reordered once released, only appended to.
+const MIGRATIONS: &[&str] = &[
+    include_str!("./migrations/001.sql"),
+    include_str!("./migrations/002.sql"),
+    include_str!("./migrations/003.sql"),
+    include_str!("./migrations/004.sql"),
+    include_str!("./migrations/005.sql"),
+    include_str!("./migrations/006.sql"),
+    include_str!("./migrations/007.sql"),
+    include_str!("./migrations/008.sql"),
+    include_str!("./migrations/009.sql"),
+    include_str!("./migrations/010.sql"),
+    include_str!("./migrations/011.sql"),
+    include_str!("./migrations/012.sql"),
+    include_str!("./migrations/013.sql"),
+];
+
+/// Brings the schema up to the latest version, each migration in a transaction of its own.
+///
+/// # Errors
+///
+/// Returns an error if a migration fails, the database is left at the version before it, or if
+/// the database comes from a newer version of the player.
+pub fn migrate(conn: &mut Connection) -> Result<(), LibraryError> {
+    let mut version = schema_version(conn)?;
+
+    // NOTE: Only the first schema was released before the schema version was kept.
+    if version == 0 && has_tracks(conn)? {
+        version = 1;
+        conn.pragma_update(None, "user_version", version)?;
+    }
+
+    if version > MIGRATIONS.len() {
+        return Err(LibraryError::UnknownVersion(version));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let version = index + 1;
+        let tx = conn.transaction()?;
+
+        tx.execute_batch(migration)
+            .map_err(|err| LibraryError::Migration(version, err))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+
+        info!("Migrated library database to version {version}.");
+    }
+
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Whether the database has a schema, which is the first one if it has no version.
+fn has_tracks(conn: &Connection) -> Result<bool, rusqlite::Error> {
+    conn.prepare_cached(include_str!("./sql/has_table.sql"))?
+        .query_row(named_params! { ":table": "tracks" }, |row| row.get(0))
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+
+    use super::{MIGRATIONS, migrate, schema_version};
+    use crate::database::{LibraryError, get_all_tracks, get_setting, set_setting};
+
+    fn connection() -> Connection {
+        Connection::open_in_memory().expect("In-memory database opened.")
+    }
+
+    #[test]
+    fn upgrades_v1_database() {
+        let mut conn = connection();
+
+        conn.execute_batch(include_str!("./migrations/001.sql"))
+            .expect("Version 1 schema created.");
+        conn.execute_batch(
+            "INSERT INTO tracks(path, title, modified) VALUES ('a.flac', 'A', '2024-01-01');",
+        )
+        .expect("Version 1 track inserted.");
+
+        migrate(&mut conn).expect("Database migrated.");
+
+        assert_eq!(
+            schema_version(&conn).ok(),
+            Some(MIGRATIONS.len()),
+            "Database should be at the latest version."
+        );
+
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        let track = tracks.first().expect("Track kept.");
+        assert_eq!(track.title.as_deref(), Some("A"), "Tags should be kept.");
+        assert!(track.range.is_none(), "Track should cover its whole file.");
+        assert!(
+            track.modified.is_none(),
+            "Track should be read again for the new columns."
+        );
+
+        set_setting(&conn, "key", "value").expect("Setting stored.");
+        assert_eq!(
+            get_setting(&conn, "key").ok().flatten().as_deref(),
+            Some("value"),
+            "Settings table should exist."
+        );
+    }
+
+    #[test]
+    fn migrates_new_database_once() {
+        let mut conn = connection();
+
+        migrate(&mut conn).expect("Database migrated.");
+        migrate(&mut conn).expect("Migrated database left as is.");
+
+        assert_eq!(
+            schema_version(&conn).ok(),
+            Some(MIGRATIONS.len()),
+            "Database should be at the latest version."
+        );
+    }
+
+    #[test]
+    fn reports_failed_migration() {
+        let mut conn = connection();
+
+        // NOTE: Version 3 adds columns to a table that is missing here.
+        conn.pragma_update(None, "user_version", 2)
+            .expect("Version set.");
+
+        assert!(
+            matches!(migrate(&mut conn), Err(LibraryError::Migration(3, _))),
+            "Failed migration should be reported."
+        );
+        assert_eq!(
+            schema_version(&conn).ok(),
+            Some(2),
+            "Database should stay at the version before the failed migration."
+        );
+    }
+
+    #[test]
+    fn rejects_newer_database() {
+        let mut conn = connection();
+
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .expect("Version set.");
+
+        assert!(
+            matches!(migrate(&mut conn), Err(LibraryError::UnknownVersion(_))),
+            "Newer database should be rejected."
+        );
+    }
+}
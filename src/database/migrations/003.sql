@@ -0,0 +1,7 @@
+ALTER TABLE tracks ADD COLUMN track_gain REAL;
+ALTER TABLE tracks ADD COLUMN track_peak REAL;
+ALTER TABLE tracks ADD COLUMN album_gain REAL;
+ALTER TABLE tracks ADD COLUMN album_peak REAL;
+
+-- Existing tracks have to be read again to pick up their gain tags.
+UPDATE tracks SET modified = NULL;
@@ -0,0 +1,10 @@
+-- Files split by a cue sheet have a row per track, told apart by where they start.
+DROP INDEX IF EXISTS track_file;
+
+ALTER TABLE tracks ADD COLUMN start_time INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tracks ADD COLUMN end_time INTEGER;
+
+CREATE UNIQUE INDEX IF NOT EXISTS track_part ON tracks(path, start_time);
+
+-- Existing tracks have to be read again to pick up their cue sheets.
+UPDATE tracks SET modified = NULL;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS bookmarks(
+  id INTEGER PRIMARY KEY,
+
+  path TEXT NOT NULL,
+  start_time INTEGER NOT NULL DEFAULT 0,
+
+  position INTEGER NOT NULL,
+  name TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS bookmark_track ON bookmarks(path, start_time);
@@ -0,0 +1,10 @@
+-- Waveforms drawn as the seek bar, measured again once the file is modified.
+CREATE TABLE IF NOT EXISTS waveforms(
+  path TEXT NOT NULL,
+  start_time INTEGER NOT NULL DEFAULT 0,
+
+  modified DATETIME,
+  data BLOB NOT NULL,
+
+  PRIMARY KEY(path, start_time)
+);
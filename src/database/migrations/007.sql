@@ -0,0 +1 @@
+ALTER TABLE tracks ADD COLUMN unplayable INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,16 @@
+-- Tracks whose file is gone are hidden from the library, and removed once it has been missing for
+-- a while, in case it is only on a drive that is not mounted.
+ALTER TABLE tracks ADD COLUMN missing_since INTEGER;
+
+-- Data kept for a track follows it when its file is moved and goes away with it.
+CREATE TRIGGER IF NOT EXISTS track_moved AFTER UPDATE OF path ON tracks BEGIN
+  UPDATE bookmarks SET path = NEW.path
+  WHERE path = OLD.path AND start_time = OLD.start_time;
+  UPDATE OR REPLACE waveforms SET path = NEW.path
+  WHERE path = OLD.path AND start_time = OLD.start_time;
+END;
+
+CREATE TRIGGER IF NOT EXISTS track_deleted AFTER DELETE ON tracks BEGIN
+  DELETE FROM bookmarks WHERE path = OLD.path AND start_time = OLD.start_time;
+  DELETE FROM waveforms WHERE path = OLD.path AND start_time = OLD.start_time;
+END;
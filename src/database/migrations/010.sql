@@ -0,0 +1,22 @@
+-- Every time a track was played, for play counts, skip counts and listening history.
+CREATE TABLE IF NOT EXISTS plays(
+  id INTEGER PRIMARY KEY,
+  track_id INTEGER NOT NULL REFERENCES tracks(id),
+  -- Seconds since the Unix epoch.
+  started_at INTEGER NOT NULL,
+  -- Milliseconds of the track that were played.
+  played INTEGER NOT NULL,
+  -- Whether the track played to its end, it was skipped otherwise.
+  completed BOOLEAN NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS play_track ON plays(track_id);
+
+-- NOTE: Plays refer to the track id, which stays the same when the file is moved.
+DROP TRIGGER IF EXISTS track_deleted;
+
+CREATE TRIGGER track_deleted AFTER DELETE ON tracks BEGIN
+  DELETE FROM bookmarks WHERE path = OLD.path AND start_time = OLD.start_time;
+  DELETE FROM waveforms WHERE path = OLD.path AND start_time = OLD.start_time;
+  DELETE FROM plays WHERE track_id = OLD.id;
+END;
@@ -0,0 +1,4 @@
+-- Star rating from 1 to 5, unrated tracks are NULL.
+ALTER TABLE tracks ADD COLUMN rating INTEGER;
+
+ALTER TABLE tracks ADD COLUMN loved BOOLEAN NOT NULL DEFAULT 0;
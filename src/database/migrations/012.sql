@@ -0,0 +1,37 @@
+ALTER TABLE tracks ADD COLUMN year INTEGER;
+
+-- Full text index of the tags searched, kept in sync with the tracks by the triggers below.
+-- Case and diacritics are folded, so that `beyonce` finds `Beyoncé`.
+CREATE VIRTUAL TABLE IF NOT EXISTS track_search USING fts5(
+  title,
+  artist,
+  album,
+  album_artist,
+  genre,
+  content = 'tracks',
+  content_rowid = 'id',
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO track_search(track_search) VALUES ('rebuild');
+
+CREATE TRIGGER IF NOT EXISTS track_search_inserted AFTER INSERT ON tracks BEGIN
+  INSERT INTO track_search(rowid, title, artist, album, album_artist, genre)
+  VALUES (NEW.id, NEW.title, NEW.artist, NEW.album, NEW.album_artist, NEW.genre);
+END;
+
+CREATE TRIGGER IF NOT EXISTS track_search_deleted AFTER DELETE ON tracks BEGIN
+  INSERT INTO track_search(track_search, rowid, title, artist, album, album_artist, genre)
+  VALUES ('delete', OLD.id, OLD.title, OLD.artist, OLD.album, OLD.album_artist, OLD.genre);
+END;
+
+CREATE TRIGGER IF NOT EXISTS track_search_updated
+AFTER UPDATE OF title, artist, album, album_artist, genre ON tracks BEGIN
+  INSERT INTO track_search(track_search, rowid, title, artist, album, album_artist, genre)
+  VALUES ('delete', OLD.id, OLD.title, OLD.artist, OLD.album, OLD.album_artist, OLD.genre);
+  INSERT INTO track_search(rowid, title, artist, album, album_artist, genre)
+  VALUES (NEW.id, NEW.title, NEW.artist, NEW.album, NEW.album_artist, NEW.genre);
+END;
+
+-- Existing tracks have to be read again to pick up their year.
+UPDATE tracks SET modified = NULL;
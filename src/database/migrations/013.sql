@@ -0,0 +1,8 @@
+-- Ratings set in the app win over the ones read from file tags, which may be stale.
+ALTER TABLE tracks ADD COLUMN rated_in_app BOOLEAN NOT NULL DEFAULT 0;
+
+-- Ratings stored so far may have been set in the app, they are kept.
+UPDATE tracks SET rated_in_app = 1 WHERE rating IS NOT NULL;
+
+-- Existing tracks have to be read again to pick up the ratings of their tags.
+UPDATE tracks SET modified = NULL;
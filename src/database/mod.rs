@@ -1,17 +1,60 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{MAIN_SEPARATOR_STR, Path, PathBuf};
 use std::str::FromStr as _;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use chrono::DateTime;
+use chrono::{DateTime, Local};
 use log::debug;
 use parking_lot::{Mutex, MutexGuard};
-use rusqlite::{Connection, named_params};
+use rusqlite::{Connection, OptionalExtension as _, Row, named_params};
 
-use crate::config::{get_default_app_dir_config, get_default_audio_dir_config};
-use crate::track::{Track, read_track_metadata, scan_tracks};
+use crate::config::{FolderFilter, LibraryFolder, get_default_app_dir_config};
+use crate::loudness::{Loudness, LoudnessAnalysis, measure};
+use crate::track::{
+    AudioProperties, Bookmark, ReplayGain, Track, TrackRange, cue_sheet_files, file_modified,
+    modified_time, read_tracks, scan_tracks, write_replay_gain,
+};
+use crate::waveform::Waveform;
+
+mod error;
+pub use error::LibraryError;
+
+mod migration;
+pub(crate) use migration::migrate;
+
+mod scanner;
+pub use scanner::{ScanEvent, ScanProgress};
+
+mod search;
+pub use search::{SearchQuery, SearchResults, search_tracks};
+
+/// How long a track whose file is missing is kept, in case the file is only on a drive that is
+/// not mounted.
+const MISSING_GRACE_PERIOD: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Size, length and tags of a file, which stay the same when the file is moved or renamed.
+type Fingerprint = (
+    u64,
+    Option<u64>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Files updated by `Database::update_files`.
+#[derive(Debug)]
+pub struct UpdatedFiles {
+    /// Files and directories that were updated, with the music files in place of their cue
+    /// sheets.
+    pub paths: Vec<PathBuf>,
+    /// Tracks of the library in `paths` since the update.
+    pub tracks: Vec<Track>,
+    /// Files that could not be read, they are kept as unplayable.
+    pub errors: Vec<LibraryError>,
+}
 
 #[derive(Clone)]
 pub struct Database {
@@ -19,16 +62,15 @@ pub struct Database {
 }
 
 impl Database {
-    fn migrate(conn: &Connection) -> Result<(), rusqlite::Error> {
-        conn.execute_batch(include_str!("./migrations/001.sql"))?;
-
-        Ok(())
-    }
-
-    pub fn new() -> Result<Self, rusqlite::Error> {
-        let conn = Connection::open(get_default_app_dir_config().join("library.db"))?;
+    /// Opens the library database and brings its schema up to date.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or migrated.
+    pub fn new() -> Result<Self, LibraryError> {
+        let mut conn = Connection::open(get_default_app_dir_config().join("library.db"))?;
 
-        Self::migrate(&conn).ok();
+        migrate(&mut conn)?;
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
@@ -39,85 +81,287 @@ impl Database {
         self.conn.lock()
     }
 
-    /// This function updates a music library database based on local audio files.
-    /// It either performs a full scan of all files or a partial, incremental update that only processes new or modified files.
+    /// Updates the library for files that were created, modified, moved or deleted, without
+    /// scanning the whole library folders.
     ///
-    /// # Arguments
+    /// Paths may be files or directories, whatever is gone from them is marked as missing and
+    /// whatever has been modified in them is read again. Cue sheets read the music files they
+    /// belong to again.
     ///
-    /// * `full` - A boolean flag.
-    ///   - If true, the function will perform a full refresh, scanning all audio files in the configured directory.
-    ///   - If false, it will perform an incremental refresh, only processing files that are new or have been modified since their last entry in the database.
-    pub fn refresh_library(&self, full: bool) -> Result<(), rusqlite::Error> {
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be updated.
+    pub fn update_files(
+        &self,
+        folders: &[LibraryFolder],
+        paths: &[PathBuf],
+    ) -> Result<UpdatedFiles, LibraryError> {
         let mut conn = self.get_connection();
-
-        let track_records: HashMap<PathBuf, Track> = get_all_tracks(&conn)
-            .unwrap_or_default()
-            .into_iter()
-            .map(|item| (item.path.clone(), item))
+        let filters: Vec<_> = folders.iter().map(LibraryFolder::filter).collect();
+        let paths: Vec<_> = paths
+            .iter()
+            .flat_map(|path| cue_sheet_files(path))
             .collect();
-        let mut track_entries = get_default_audio_dir_config()
-            .as_deref()
-            .map(scan_tracks)
-            .unwrap_or_default();
-
-        if !full {
-            track_entries.retain(|entry| {
-                track_records.get(entry).is_none_or(|v| {
-                    v.modified.as_deref().is_none_or(|modified| {
-                        let record_modified_dt =
-                            DateTime::<chrono::Local>::from_str(modified).unwrap_or_default();
-                        let source_modified_dt = DateTime::<chrono::Local>::from(
-                            entry
-                                .metadata()
-                                .and_then(|metadata| metadata.modified())
-                                .unwrap_or(SystemTime::now()),
-                        );
-
-                        source_modified_dt.cmp(&record_modified_dt) == Ordering::Greater
-                    })
-                })
-            });
+        let track_records = records_by_path(get_tracks_in(&conn, &paths)?);
+
+        let mut errors = Vec::new();
+        let tx = conn.transaction()?;
+
+        // NOTE: Removals go first so that files moved within `paths` are found where they came from.
+        let now = unix_time(SystemTime::now());
+        for record in track_records.keys().filter(|record| !record.exists()) {
+            mark_missing(&tx, record, now)?;
+        }
+
+        let mut moved_from = missing_fingerprints(&tx, &track_records, &[]);
+
+        for entry in paths.iter().filter(|path| path.exists()).flat_map(|path| {
+            filters
+                .iter()
+                .find(|filter| path.starts_with(filter.root()))
+                .map(|filter| scan_tracks(path, filter))
+                .into_iter()
+                .flatten()
+        }) {
+            let records = track_records.get(&entry).map(Vec::as_slice);
+            // NOTE: Tags written by the app itself are not read again.
+            if records
+                .and_then(<[Track]>::first)
+                .is_some_and(|record| !is_modified(&entry, record))
+            {
+                continue;
+            }
+
+            let tracks = read_file(&entry, &mut errors);
+
+            store_file(&tx, &entry, &tracks, records, &mut moved_from);
+        }
+
+        let tracks = get_tracks_in(&tx, &paths)?;
+        tx.commit()?;
+
+        Ok(UpdatedFiles {
+            paths,
+            tracks,
+            errors,
+        })
+    }
+
+    /// Removes the tracks of a folder taken out of the library, missing or not, along with their
+    /// plays, ratings, bookmarks and waveforms. Tracks that are still part of one of the
+    /// remaining `folders` are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be updated.
+    pub fn remove_folder(
+        &self,
+        path: &Path,
+        folders: &[LibraryFolder],
+    ) -> Result<(), rusqlite::Error> {
+        let filters: Vec<_> = folders.iter().map(LibraryFolder::filter).collect();
+        let mut conn = self.get_connection();
+        let tx = conn.transaction()?;
+
+        for track in get_all_tracks(&tx)?
+            .iter()
+            .chain(&get_missing_tracks(&tx)?)
+            .filter(|track| {
+                track.path.starts_with(path)
+                    && !filters.iter().any(|filter| filter.contains(&track.path))
+            })
+        {
+            delete_track(&tx, track)?;
         }
 
-        if let Ok(tx) = conn.transaction() {
-            for entry in &track_entries {
-                if let Err(err) =
-                    upsert_track(&tx, &read_track_metadata(entry).expect("Music metadata."))
-                {
-                    debug!("Failed to update database: {err:?}");
+        tx.commit()
+    }
+
+    /// Computes missing replay gain values by measuring the EBU R128 loudness of the tracks.
+    ///
+    /// Tracks are analyzed album by album since the album gain needs every track of the album.
+    /// Values read from tags are kept, only the missing ones are filled in. The connection is
+    /// only locked while storing results so the library stays usable in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `analysis` - Progress is reported to it and it is checked for cancellation between tracks.
+    /// * `write_tags` - Whether the computed values are also written to the file tags.
+    pub fn analyze_loudness(
+        &self,
+        analysis: &LoudnessAnalysis,
+        write_tags: bool,
+    ) -> Result<(), rusqlite::Error> {
+        let mut albums: BTreeMap<(Option<String>, Option<String>), Vec<Track>> = BTreeMap::new();
+        let mut singles = Vec::new();
+
+        for track in get_all_tracks(&self.get_connection())? {
+            if track.album.is_some() {
+                albums
+                    .entry((track.album.clone(), track.album_artist.clone()))
+                    .or_default()
+                    .push(track);
+            } else {
+                singles.push(vec![track]);
+            }
+        }
+
+        let groups = albums
+            .into_values()
+            .chain(singles)
+            .filter(|tracks| {
+                tracks.iter().any(|track| {
+                    track.replay_gain.track_gain.is_none()
+                        || (track.album.is_some() && track.replay_gain.album_gain.is_none())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        analysis.set_total(groups.iter().map(Vec::len).sum());
+
+        for tracks in groups {
+            let mut measured = Vec::new();
+
+            for track in tracks {
+                if analysis.is_cancelled() {
+                    return Ok(());
+                }
+
+                match measure(&track) {
+                    Ok(loudness) => measured.push((track, loudness)),
+                    Err(err) => debug!("Failed to analyze {}: {err:?}", track.path.display()),
+                }
+
+                analysis.advance();
+            }
+
+            let album = Loudness::album(measured.iter().map(|(_, loudness)| loudness));
+
+            let mut updated = Vec::new();
+
+            let conn = self.get_connection();
+            for (track, loudness) in &measured {
+                let mut replay_gain = track.replay_gain;
+
+                replay_gain.track_gain = replay_gain.track_gain.or_else(|| loudness.gain());
+                replay_gain.track_peak = replay_gain.track_peak.or(Some(loudness.peak()));
+
+                if track.album.is_some() {
+                    replay_gain.album_gain = replay_gain.album_gain.or_else(|| album.gain());
+                    replay_gain.album_peak = replay_gain.album_peak.or(Some(album.peak()));
+                }
+
+                if replay_gain == track.replay_gain {
+                    continue;
+                }
+
+                update_replay_gain(&conn, track, &replay_gain)?;
+                // NOTE: Tags of a file split by a cue sheet cover all of its tracks.
+                if track.range.is_none() {
+                    updated.push((track.path.as_path(), replay_gain));
                 }
             }
+            drop(conn);
 
-            tx.commit()?;
+            if write_tags {
+                for (path, replay_gain) in updated {
+                    if let Err(err) = write_replay_gain(path, &replay_gain) {
+                        debug!("Failed to write tags to {}: {err:?}", path.display());
+                    } else if let Err(err) = set_modified(&self.get_connection(), path) {
+                        debug!("Failed to update database: {err:?}");
+                    }
+                }
+            }
         }
 
         Ok(())
     }
 }
 
+/// Tracks of the library, without the ones whose file is missing.
 pub fn get_all_tracks(conn: &Connection) -> Result<Vec<Track>, rusqlite::Error> {
     let mut stmt = conn.prepare_cached(include_str!("./sql/get_all_tracks.sql"))?;
 
-    stmt.query_map(named_params! {}, |row| {
-        Ok(Track {
-            path: row.get("path").map(|v: String| PathBuf::from(v))?,
-            modified: row.get("modified").ok(),
-            title: row.get("title").ok(),
-            artist: row.get("artist").ok(),
-            genre: row.get("genre").ok(),
-            album: row.get("album").ok(),
-            album_artist: row.get("album_artist").ok(),
-            track: row.get("track").ok(),
-            track_total: row.get("track_total").ok(),
-            disc: row.get("disc").ok(),
-            disc_total: row.get("disc_total").ok(),
-            duration: row
-                .get("duration")
-                .map(|v: i32| Duration::from_secs(u64::try_from(v.max(0)).unwrap_or_default()))
-                .ok(),
-        })
-    })?
-    .collect()
+    stmt.query_map(named_params! {}, track_from_row)?.collect()
+}
+
+/// Tracks of the library read from `paths`, which may be files or directories.
+pub fn get_tracks_in(conn: &Connection, paths: &[PathBuf]) -> Result<Vec<Track>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/get_tracks_in.sql"))?;
+    let mut tracks = Vec::new();
+
+    for path in paths {
+        let mut prefix = path.as_os_str().to_owned();
+        prefix.push(MAIN_SEPARATOR_STR);
+
+        for track in stmt.query_map(
+            named_params! {
+                ":path": path.to_string_lossy(),
+                ":prefix": prefix.to_string_lossy(),
+            },
+            track_from_row,
+        )? {
+            tracks.push(track?);
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// Tracks hidden from the library since their file went missing.
+pub fn get_missing_tracks(conn: &Connection) -> Result<Vec<Track>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/get_missing_tracks.sql"))?;
+
+    stmt.query_map(named_params! {}, track_from_row)?.collect()
+}
+
+fn track_from_row(row: &Row<'_>) -> Result<Track, rusqlite::Error> {
+    Ok(Track {
+        path: row.get("path").map(|v: String| PathBuf::from(v))?,
+        modified: row.get("modified").ok(),
+        title: row.get("title").ok(),
+        artist: row.get("artist").ok(),
+        genre: row.get("genre").ok(),
+        album: row.get("album").ok(),
+        album_artist: row.get("album_artist").ok(),
+        track: row.get("track").ok(),
+        track_total: row.get("track_total").ok(),
+        disc: row.get("disc").ok(),
+        disc_total: row.get("disc_total").ok(),
+        year: row.get("year").ok().flatten(),
+        duration: row
+            .get("duration")
+            .map(|v: i32| Duration::from_secs(u64::try_from(v.max(0)).unwrap_or_default()))
+            .ok(),
+        replay_gain: ReplayGain {
+            track_gain: row.get("track_gain").ok(),
+            track_peak: row.get("track_peak").ok(),
+            album_gain: row.get("album_gain").ok(),
+            album_peak: row.get("album_peak").ok(),
+        },
+        range: track_range(
+            row.get("start_time").unwrap_or_default(),
+            row.get("end_time").ok().flatten(),
+        ),
+        properties: AudioProperties {
+            bitrate: row.get("bitrate").ok(),
+            sample_rate: row.get("sample_rate").ok(),
+            bit_depth: row.get("bit_depth").ok(),
+            channels: row.get("channels").ok(),
+            codec: row.get("codec").ok(),
+            file_size: row.get("file_size").ok(),
+        },
+        unplayable: row.get("unplayable").unwrap_or_default(),
+        play_count: row.get("play_count").unwrap_or_default(),
+        skip_count: row.get("skip_count").unwrap_or_default(),
+        last_played: row
+            .get("last_played")
+            .ok()
+            .and_then(|secs: i64| DateTime::from_timestamp(secs, 0))
+            .map(|time| time.with_timezone(&Local)),
+        rating: row.get("rating").ok().flatten(),
+        loved: row.get("loved").unwrap_or_default(),
+    })
 }
 
 pub fn upsert_track(conn: &Connection, track: &Track) -> Result<i32, rusqlite::Error> {
@@ -126,6 +370,8 @@ pub fn upsert_track(conn: &Connection, track: &Track) -> Result<i32, rusqlite::E
     stmt.query_row(
         named_params! {
             ":path": track.path.to_string_lossy(),
+            ":start_time": millis(track.start()),
+            ":end_time": track.range.and_then(|range| range.end).map(millis),
             ":modified": track.modified,
             ":title": track.title,
             ":artist": track.artist,
@@ -137,7 +383,896 @@ pub fn upsert_track(conn: &Connection, track: &Track) -> Result<i32, rusqlite::E
             ":disc": track.disc,
             ":disc_total": track.disc_total,
             ":duration": track.duration.map(|v| u32::try_from(v.as_secs()).unwrap_or(0)),
+            ":track_gain": track.replay_gain.track_gain,
+            ":track_peak": track.replay_gain.track_peak,
+            ":album_gain": track.replay_gain.album_gain,
+            ":album_peak": track.replay_gain.album_peak,
+            ":bitrate": track.properties.bitrate,
+            ":sample_rate": track.properties.sample_rate,
+            ":bit_depth": track.properties.bit_depth,
+            ":channels": track.properties.channels,
+            ":codec": track.properties.codec,
+            ":file_size": track.properties.file_size,
+            ":unplayable": track.unplayable,
+            ":rating": track.rating,
+            ":year": track.year,
         },
         |row| row.get(0),
     )
 }
+
+pub fn update_replay_gain(
+    conn: &Connection,
+    track: &Track,
+    replay_gain: &ReplayGain,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/update_replay_gain.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":start_time": millis(track.start()),
+        ":track_gain": replay_gain.track_gain,
+        ":track_peak": replay_gain.track_peak,
+        ":album_gain": replay_gain.album_gain,
+        ":album_peak": replay_gain.album_peak,
+    })?;
+
+    Ok(())
+}
+
+/// Marks every track of the file of `track`, since a file that fails to decode fails as a whole.
+pub fn set_unplayable(
+    conn: &Connection,
+    track: &Track,
+    unplayable: bool,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/set_unplayable.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":unplayable": unplayable,
+    })?;
+
+    Ok(())
+}
+
+/// Records that a track was played, from `started_at` for `played` of its length.
+pub fn insert_play(
+    conn: &Connection,
+    track: &Track,
+    started_at: SystemTime,
+    played: Duration,
+    completed: bool,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/insert_play.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":start_time": millis(track.start()),
+        ":started_at": unix_time(started_at),
+        ":played": millis(played),
+        ":completed": completed,
+    })?;
+
+    Ok(())
+}
+
+/// Sets the star rating of a track, `None` leaves it unrated.
+pub fn set_rating(
+    conn: &Connection,
+    track: &Track,
+    rating: Option<u8>,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/set_rating.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":start_time": millis(track.start()),
+        ":rating": rating,
+    })?;
+
+    Ok(())
+}
+
+pub fn set_loved(conn: &Connection, track: &Track, loved: bool) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/set_loved.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":start_time": millis(track.start()),
+        ":loved": loved,
+    })?;
+
+    Ok(())
+}
+
+/// Takes the file as it is now as the one the tracks were read from, after the app wrote to its
+/// tags, so that it is not read again.
+pub fn set_modified(conn: &Connection, path: &Path) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/set_modified.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": path.to_string_lossy(),
+        ":modified": file_modified(path),
+    })?;
+
+    Ok(())
+}
+
+/// Moves the tracks of a file to its new path, along with the data kept for them.
+pub fn move_file(conn: &Connection, from: &Path, path: &Path) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/move_file.sql"))?;
+
+    stmt.execute(named_params! {
+        ":from": from.to_string_lossy(),
+        ":path": path.to_string_lossy(),
+    })?;
+
+    Ok(())
+}
+
+/// Hides the tracks of a missing file, `now` is kept if they were already missing.
+pub fn mark_missing(conn: &Connection, path: &Path, now: i64) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/mark_missing.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": path.to_string_lossy(),
+        ":now": now,
+    })?;
+
+    Ok(())
+}
+
+/// Removes the tracks that have been missing since before `before`.
+pub fn delete_missing(conn: &Connection, before: i64) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/delete_missing.sql"))?;
+
+    stmt.execute(named_params! { ":before": before })?;
+
+    Ok(())
+}
+
+pub fn delete_track(conn: &Connection, track: &Track) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/delete_track.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":start_time": millis(track.start()),
+    })?;
+
+    Ok(())
+}
+
+pub fn get_bookmarks(conn: &Connection, track: &Track) -> Result<Vec<Bookmark>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/get_bookmarks.sql"))?;
+
+    stmt.query_map(
+        named_params! {
+            ":path": track.path.to_string_lossy(),
+            ":start_time": millis(track.start()),
+        },
+        |row| {
+            Ok(Bookmark {
+                id: row.get("id")?,
+                name: row.get("name")?,
+                position: row.get("position").map(|v: i64| {
+                    Duration::from_millis(u64::try_from(v.max(0)).unwrap_or_default())
+                })?,
+            })
+        },
+    )?
+    .collect()
+}
+
+pub fn insert_bookmark(
+    conn: &Connection,
+    track: &Track,
+    name: &str,
+    position: Duration,
+) -> Result<i64, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/insert_bookmark.sql"))?;
+
+    stmt.query_row(
+        named_params! {
+            ":path": track.path.to_string_lossy(),
+            ":start_time": millis(track.start()),
+            ":position": millis(position),
+            ":name": name,
+        },
+        |row| row.get(0),
+    )
+}
+
+pub fn delete_bookmark(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/delete_bookmark.sql"))?;
+
+    stmt.execute(named_params! { ":id": id })?;
+
+    Ok(())
+}
+
+/// Returns the stored waveform of a track, `None` if there is none or the file has changed since.
+pub fn get_waveform(conn: &Connection, track: &Track) -> Result<Option<Waveform>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/get_waveform.sql"))?;
+
+    stmt.query_row(
+        named_params! {
+            ":path": track.path.to_string_lossy(),
+            ":start_time": millis(track.start()),
+            ":modified": track.modified,
+        },
+        |row| row.get("data").map(|v: Vec<u8>| Waveform::from_bytes(&v)),
+    )
+    .optional()
+}
+
+pub fn set_waveform(
+    conn: &Connection,
+    track: &Track,
+    waveform: &Waveform,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/set_waveform.sql"))?;
+
+    stmt.execute(named_params! {
+        ":path": track.path.to_string_lossy(),
+        ":start_time": millis(track.start()),
+        ":modified": track.modified,
+        ":data": waveform.to_bytes(),
+    })?;
+
+    Ok(())
+}
+
+pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/get_setting.sql"))?;
+
+    stmt.query_row(named_params! { ":key": key }, |row| row.get("value"))
+        .optional()
+        .map(Option::flatten)
+}
+
+pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/set_setting.sql"))?;
+
+    stmt.execute(named_params! { ":key": key, ":value": value })?;
+
+    Ok(())
+}
+
+/// Returns the settings whose key starts with `prefix`, with the prefix stripped from the keys.
+pub fn get_settings_by_prefix(
+    conn: &Connection,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/get_settings_by_prefix.sql"))?;
+
+    stmt.query_map(named_params! { ":prefix": prefix }, |row| {
+        let key: String = row.get("key")?;
+
+        Ok((
+            key.strip_prefix(prefix).unwrap_or(&key).to_owned(),
+            row.get("value")?,
+        ))
+    })?
+    .collect()
+}
+
+pub fn delete_settings_by_prefix(conn: &Connection, prefix: &str) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/delete_settings_by_prefix.sql"))?;
+
+    stmt.execute(named_params! { ":prefix": prefix })?;
+
+    Ok(())
+}
+
+/// Tracks grouped by the file they're read from.
+fn records_by_path(tracks: Vec<Track>) -> HashMap<PathBuf, Vec<Track>> {
+    let mut track_records: HashMap<PathBuf, Vec<Track>> = HashMap::new();
+    for track in tracks {
+        track_records
+            .entry(track.path.clone())
+            .or_default()
+            .push(track);
+    }
+
+    track_records
+}
+
+/// Reads the tracks of a file, a file that cannot be read is kept as an unplayable track.
+fn read_file(entry: &Path, errors: &mut Vec<LibraryError>) -> Vec<Track> {
+    match read_tracks(entry) {
+        Ok(tracks) => tracks,
+        Err(err) => {
+            errors.push(LibraryError::Read(entry.to_owned(), err));
+
+            vec![Track {
+                path: entry.to_owned(),
+                modified: Some(file_modified(entry)),
+                unplayable: true,
+                ..Default::default()
+            }]
+        }
+    }
+}
+
+/// Stores the tracks of a file in place of the `records` of the file.
+///
+/// A file new to the library, without `records`, takes over the tracks of the missing file
+/// with the same fingerprint. Returns the path of that file.
+fn store_file(
+    conn: &Connection,
+    entry: &Path,
+    tracks: &[Track],
+    records: Option<&[Track]>,
+    missing: &mut HashMap<Fingerprint, PathBuf>,
+) -> Option<PathBuf> {
+    let moved_from = tracks
+        .first()
+        .filter(|_| records.is_none())
+        .and_then(fingerprint)
+        .and_then(|fingerprint| missing.remove(&fingerprint))
+        .filter(|from| from != entry);
+
+    if let Some(from) = moved_from.as_deref()
+        && let Err(err) = move_file(conn, from, entry)
+    {
+        debug!("Failed to update database: {err:?}");
+    }
+
+    // NOTE: Tracks of a cue sheet that has since been changed or removed.
+    for record in records.into_iter().flatten() {
+        if !tracks
+            .iter()
+            .any(|track| track.start().as_millis() == record.start().as_millis())
+            && let Err(err) = delete_track(conn, record)
+        {
+            debug!("Failed to update database: {err:?}");
+        }
+    }
+
+    for track in tracks {
+        if let Err(err) = upsert_track(conn, track) {
+            debug!("Failed to update database: {err:?}");
+        }
+    }
+
+    moved_from
+}
+
+/// Hides the tracks that are not part of any of the library folders anymore, like the tracks of
+/// missing files, so that they come back with their data when the patterns are changed back.
+fn hide_excluded_tracks(
+    conn: &Connection,
+    filters: &[FolderFilter],
+    now: i64,
+) -> Result<(), rusqlite::Error> {
+    for track in get_all_tracks(conn)?
+        .iter()
+        .filter(|track| !filters.iter().any(|filter| filter.contains(&track.path)))
+    {
+        mark_missing(conn, &track.path, now)?;
+    }
+
+    Ok(())
+}
+
+/// Whether a file has been modified since the track was read from it.
+fn is_modified(path: &Path, record: &Track) -> bool {
+    record.modified.as_deref().is_none_or(|modified| {
+        let record_modified_dt = DateTime::<Local>::from_str(modified).unwrap_or_default();
+        let source_modified_dt = DateTime::<Local>::from(modified_time(path));
+
+        source_modified_dt.cmp(&record_modified_dt) == Ordering::Greater
+    })
+}
+
+/// Missing files that new files may have been moved from, by fingerprint.
+///
+/// These are the files missing since an earlier refresh and the ones that went missing since.
+/// Fingerprints shared by several files are left out, since there is no telling which of them
+/// a new file was moved from.
+fn missing_fingerprints(
+    conn: &Connection,
+    track_records: &HashMap<PathBuf, Vec<Track>>,
+    vanished: &[PathBuf],
+) -> HashMap<Fingerprint, PathBuf> {
+    let missing = get_missing_tracks(conn).unwrap_or_default();
+    let mut paths: HashMap<Fingerprint, Option<PathBuf>> = HashMap::new();
+
+    for track in missing.iter().chain(
+        vanished
+            .iter()
+            .filter_map(|path| track_records.get(path)?.first()),
+    ) {
+        let Some(fingerprint) = fingerprint(track) else {
+            continue;
+        };
+
+        paths
+            .entry(fingerprint)
+            .and_modify(|path| {
+                if path.as_ref() != Some(&track.path) {
+                    *path = None;
+                }
+            })
+            .or_insert_with(|| Some(track.path.clone()));
+    }
+
+    paths
+        .into_iter()
+        .filter_map(|(fingerprint, path)| Some((fingerprint, path?)))
+        .collect()
+}
+
+fn fingerprint(track: &Track) -> Option<Fingerprint> {
+    Some((
+        track.properties.file_size?,
+        // NOTE: Durations are stored in whole seconds.
+        track.duration.map(|duration| duration.as_secs()),
+        track.title.clone(),
+        track.artist.clone(),
+        track.album.clone(),
+        track.track.clone(),
+    ))
+}
+
+/// Times are stored as seconds since the Unix epoch.
+fn unix_time(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| i64::try_from(duration.as_secs()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
+
+/// Track offsets are stored in milliseconds.
+fn millis(duration: Duration) -> i64 {
+    i64::try_from(duration.as_millis()).unwrap_or(i64::MAX)
+}
+
+fn track_range(start: i64, end: Option<i64>) -> Option<TrackRange> {
+    let duration =
+        |millis: i64| Duration::from_millis(u64::try_from(millis.max(0)).unwrap_or_default());
+
+    (start > 0 || end.is_some()).then(|| TrackRange {
+        start: duration(start),
+        end: end.map(duration),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+    use std::time::{Duration, SystemTime};
+
+    use parking_lot::Mutex;
+    use rusqlite::Connection;
+
+    use super::{
+        Database, MISSING_GRACE_PERIOD, delete_missing, delete_track, get_all_tracks,
+        get_missing_tracks, insert_play, mark_missing, migrate, missing_fingerprints, move_file,
+        records_by_path, set_loved, set_rating, store_file, unix_time, upsert_track,
+    };
+    use crate::config::LibraryFolder;
+    use crate::track::{AudioProperties, Track};
+
+    pub(super) fn database() -> Database {
+        let mut conn = Connection::open_in_memory().expect("In-memory database opened.");
+        migrate(&mut conn).expect("Database migrated.");
+
+        Database {
+            conn: Arc::new(Mutex::new(conn)),
+        }
+    }
+
+    /// Empty directory of a test, in the temporary directory.
+    pub(super) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ferrum-{name}-{}", std::process::id()));
+
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).expect("Directory created.");
+
+        dir
+    }
+
+    /// Track of a file of 1000 bytes, whose fingerprint is that of any other track of the title.
+    fn track(path: &str, title: &str) -> Track {
+        Track {
+            path: PathBuf::from(path),
+            title: Some(title.to_owned()),
+            duration: Some(Duration::from_secs(180)),
+            properties: AudioProperties {
+                file_size: Some(1000),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn refresh(database: &Database, folders: &[LibraryFolder]) {
+        let (progress, _events) = mpsc::channel();
+
+        database
+            .refresh_library(folders, false, &progress, &AtomicBool::new(false))
+            .expect("Library refreshed.");
+    }
+
+    #[test]
+    fn keeps_data_of_excluded_tracks() {
+        let dir = temp_dir("excluded");
+        fs::create_dir(dir.join("Live")).expect("Directory created.");
+        // NOTE: Files that cannot be read are kept as unplayable tracks.
+        for path in ["a.flac", "Live/b.flac"] {
+            fs::write(dir.join(path), b"not audio").expect("File written.");
+        }
+
+        let database = database();
+        let mut folder = LibraryFolder::new(dir.clone());
+        refresh(&database, std::slice::from_ref(&folder));
+
+        let tracks = get_all_tracks(&database.get_connection()).expect("Tracks read.");
+        assert_eq!(tracks.len(), 2, "Every file should be in the library.");
+
+        let live = tracks
+            .iter()
+            .find(|track| track.path.ends_with("Live/b.flac"))
+            .expect("Track found.");
+        set_rating(&database.get_connection(), live, Some(5)).expect("Track rated.");
+        insert_play(
+            &database.get_connection(),
+            live,
+            SystemTime::now(),
+            Duration::from_secs(1),
+            true,
+        )
+        .expect("Play recorded.");
+
+        folder.exclude = vec!["Live".to_owned()];
+        refresh(&database, std::slice::from_ref(&folder));
+        assert_eq!(
+            get_all_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .len(),
+            1,
+            "Excluded track should be hidden."
+        );
+
+        folder.exclude.clear();
+        refresh(&database, std::slice::from_ref(&folder));
+        let tracks = get_all_tracks(&database.get_connection()).expect("Tracks read.");
+        let live = tracks
+            .iter()
+            .find(|track| track.path.ends_with("Live/b.flac"))
+            .expect("Track included again.");
+        assert_eq!(live.rating, Some(5), "Rating should be kept.");
+        assert_eq!(live.play_count, 1, "Plays should be kept.");
+
+        refresh(&database, &[]);
+        assert_eq!(
+            get_all_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .len(),
+            2,
+            "Tracks should be kept without folders."
+        );
+
+        database.remove_folder(&dir, &[]).expect("Folder removed.");
+        assert!(
+            get_all_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .is_empty(),
+            "Tracks of a removed folder should be deleted."
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn updates_changed_files_only() {
+        let dir = temp_dir("update");
+        let disc = dir.join("Disc 1");
+        fs::create_dir(&disc).expect("Directory created.");
+        fs::create_dir(dir.join("Disc 10")).expect("Directory created.");
+        for path in ["Disc 1/a.flac", "Disc 10/c.flac"] {
+            fs::write(dir.join(path), b"not audio").expect("File written.");
+        }
+
+        let database = database();
+        let folders = [LibraryFolder::new(dir.clone())];
+        let update = |paths: &[PathBuf]| {
+            database
+                .update_files(&folders, paths)
+                .expect("Files updated.")
+        };
+
+        let updated = update(&[disc.join("a.flac"), dir.join("Disc 10/c.flac")]);
+        assert_eq!(updated.tracks.len(), 2, "New files should be read.");
+        assert_eq!(
+            updated.errors.len(),
+            2,
+            "Unreadable files should be reported."
+        );
+
+        let updated = update(&[disc.join("a.flac")]);
+        assert_eq!(
+            updated.tracks.len(),
+            1,
+            "Tracks of the file should be given."
+        );
+        assert!(
+            updated.errors.is_empty(),
+            "Unmodified file should not be read again."
+        );
+
+        fs::write(disc.join("b.flac"), b"not audio").expect("File written.");
+        let updated = update(std::slice::from_ref(&disc));
+        assert_eq!(
+            updated.tracks.len(),
+            2,
+            "Tracks of the directory should be given, without the ones of similar paths."
+        );
+        assert_eq!(updated.errors.len(), 1, "Only the new file should be read.");
+
+        fs::remove_dir_all(&disc).expect("Directory removed.");
+        let updated = update(std::slice::from_ref(&disc));
+        assert!(
+            updated.tracks.is_empty(),
+            "Tracks of the removed directory should be gone."
+        );
+        assert_eq!(
+            get_missing_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .len(),
+            2,
+            "Tracks of the removed directory should be missing."
+        );
+        assert_eq!(
+            get_all_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .len(),
+            1,
+            "Tracks of other directories should be kept."
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn moves_and_deletes_missing_tracks() {
+        let database = database();
+        let conn = database.get_connection();
+        let track = track("/music/a.flac", "A");
+        upsert_track(&conn, &track).expect("Track stored.");
+        set_rating(&conn, &track, Some(4)).expect("Track rated.");
+
+        mark_missing(&conn, &track.path, 100).expect("Track hidden.");
+        mark_missing(&conn, &track.path, 200).expect("Track hidden again.");
+        assert!(
+            get_all_tracks(&conn).expect("Tracks read.").is_empty(),
+            "Missing track should be hidden."
+        );
+
+        delete_missing(&conn, 100).expect("Tracks deleted.");
+        assert_eq!(
+            get_missing_tracks(&conn).expect("Tracks read.").len(),
+            1,
+            "Track missing since the cutoff should be kept."
+        );
+
+        move_file(&conn, &track.path, &PathBuf::from("/music/b.flac")).expect("File moved.");
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        assert_eq!(tracks.len(), 1, "Moved track should be shown again.");
+        assert!(
+            tracks
+                .iter()
+                .all(|moved| moved.path.ends_with("b.flac") && moved.rating == Some(4)),
+            "Moved track should keep its rating."
+        );
+
+        mark_missing(&conn, &PathBuf::from("/music/b.flac"), 100).expect("Track hidden.");
+        delete_missing(&conn, 101).expect("Tracks deleted.");
+        assert!(
+            get_missing_tracks(&conn).expect("Tracks read.").is_empty(),
+            "Track missing since before the cutoff should be deleted."
+        );
+    }
+
+    #[test]
+    fn detects_moved_files() {
+        let database = database();
+        let conn = database.get_connection();
+        let old = track("/music/old.flac", "A");
+        upsert_track(&conn, &old).expect("Track stored.");
+        upsert_track(&conn, &track("/music/other.flac", "B")).expect("Track stored.");
+        insert_play(&conn, &old, SystemTime::now(), Duration::from_secs(1), true)
+            .expect("Play recorded.");
+
+        let records = records_by_path(get_all_tracks(&conn).expect("Tracks read."));
+        let vanished = [old.path.clone()];
+        mark_missing(&conn, &old.path, 100).expect("Track hidden.");
+        let mut missing = missing_fingerprints(&conn, &records, &vanished);
+        assert_eq!(missing.len(), 1, "Only the missing file should be given.");
+
+        let new = track("/music/new.flac", "A");
+        let moved_from = store_file(
+            &conn,
+            &new.path,
+            std::slice::from_ref(&new),
+            None,
+            &mut missing,
+        );
+        assert_eq!(
+            moved_from,
+            Some(old.path),
+            "New file should be taken as moved."
+        );
+        assert!(
+            missing.is_empty(),
+            "A missing file should only be moved once."
+        );
+
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        let moved = tracks
+            .iter()
+            .find(|track| track.path == new.path)
+            .expect("Track moved.");
+        assert_eq!(moved.play_count, 1, "Plays should be kept.");
+        assert!(
+            get_missing_tracks(&conn).expect("Tracks read.").is_empty(),
+            "Moved track should not be missing."
+        );
+    }
+
+    #[test]
+    fn skips_ambiguous_fingerprints() {
+        let database = database();
+        let conn = database.get_connection();
+        for path in ["/music/a/track.flac", "/music/b/track.flac"] {
+            let track = track(path, "A");
+            upsert_track(&conn, &track).expect("Track stored.");
+            mark_missing(&conn, &track.path, 100).expect("Track hidden.");
+        }
+
+        let mut missing = missing_fingerprints(&conn, &HashMap::new(), &[]);
+        assert!(
+            missing.is_empty(),
+            "Fingerprint of several missing files should be skipped."
+        );
+
+        let new = track("/music/c/track.flac", "A");
+        assert_eq!(
+            store_file(
+                &conn,
+                &new.path,
+                std::slice::from_ref(&new),
+                None,
+                &mut missing
+            ),
+            None,
+            "New file should not be taken as moved."
+        );
+        assert_eq!(
+            get_missing_tracks(&conn).expect("Tracks read.").len(),
+            2,
+            "Both missing tracks should be kept."
+        );
+    }
+
+    #[test]
+    fn keeps_missing_tracks_for_grace_period() {
+        let dir = temp_dir("grace");
+        let database = database();
+        let now = unix_time(SystemTime::now());
+        let grace_period = i64::try_from(MISSING_GRACE_PERIOD.as_secs()).expect("Seconds fit.");
+
+        for (name, missing_since) in [
+            ("recent.flac", now - 60),
+            ("old.flac", now - grace_period - 60),
+        ] {
+            let track = track(&dir.join(name).to_string_lossy(), name);
+            let conn = database.get_connection();
+            upsert_track(&conn, &track).expect("Track stored.");
+            mark_missing(&conn, &track.path, missing_since).expect("Track hidden.");
+        }
+
+        refresh(&database, &[LibraryFolder::new(dir.join("unmounted"))]);
+        assert_eq!(
+            get_missing_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .len(),
+            2,
+            "Tracks should be kept while a folder is not mounted."
+        );
+
+        refresh(&database, &[LibraryFolder::new(dir.clone())]);
+        let missing = get_missing_tracks(&database.get_connection()).expect("Tracks read.");
+        assert_eq!(missing.len(), 1, "Old missing track should be deleted.");
+        assert!(
+            missing
+                .iter()
+                .all(|track| track.path.ends_with("recent.flac")),
+            "Recently missing track should be kept."
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn counts_plays_of_tracks() {
+        let database = database();
+        let conn = database.get_connection();
+
+        let track = Track {
+            path: "a.flac".into(),
+            ..Default::default()
+        };
+        upsert_track(&conn, &track).expect("Track inserted.");
+
+        let now = SystemTime::now();
+        for completed in [true, true, false] {
+            insert_play(&conn, &track, now, Duration::from_secs(1), completed)
+                .expect("Play recorded.");
+        }
+
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        let stored = tracks.first().expect("Track kept.");
+        assert_eq!(stored.play_count, 2, "Completed plays should be counted.");
+        assert_eq!(stored.skip_count, 1, "Skipped plays should be counted.");
+        assert!(stored.last_played.is_some(), "Last play should be kept.");
+
+        delete_track(&conn, &track).expect("Track deleted.");
+        let plays: i64 = conn
+            .query_row("SELECT COUNT(*) FROM plays", [], |row| row.get(0))
+            .expect("Plays counted.");
+        assert_eq!(plays, 0, "Plays should go away with their track.");
+    }
+
+    #[test]
+    fn keeps_rating_of_rescanned_tracks() {
+        let database = database();
+        let conn = database.get_connection();
+
+        let mut track = Track {
+            path: "a.flac".into(),
+            rating: Some(3),
+            ..Default::default()
+        };
+        upsert_track(&conn, &track).expect("Track inserted.");
+        track.rating = Some(2);
+        upsert_track(&conn, &track).expect("Track updated.");
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        let stored = tracks.first().expect("Track kept.");
+        assert_eq!(
+            stored.rating,
+            Some(2),
+            "Rating of the tag should be taken until the track is rated in the app."
+        );
+
+        track.rating = None;
+        set_rating(&conn, &track, Some(4)).expect("Track rated.");
+        set_loved(&conn, &track, true).expect("Track loved.");
+
+        upsert_track(&conn, &track).expect("Track updated.");
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        let stored = tracks.first().expect("Track kept.");
+        assert_eq!(
+            stored.rating,
+            Some(4),
+            "Rating should be kept without a tag."
+        );
+        assert!(stored.loved, "Loved flag should be kept.");
+
+        track.rating = Some(1);
+        upsert_track(&conn, &track).expect("Track updated.");
+        let tracks = get_all_tracks(&conn).expect("Tracks read.");
+        let stored = tracks.first().expect("Track kept.");
+        assert_eq!(
+            stored.rating,
+            Some(4),
+            "Rating set in the app should win over a stale tag."
+        );
+    }
+}
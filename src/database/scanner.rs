@@ -0,0 +1,371 @@
+use std::collections::{HashMap, HashSet};
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+
+use super::{
+    Database, Fingerprint, LibraryError, MISSING_GRACE_PERIOD, delete_missing, get_all_tracks,
+    hide_excluded_tracks, is_modified, mark_missing, missing_fingerprints, read_file,
+    records_by_path, store_file, unix_time,
+};
+use crate::config::{FolderFilter, LibraryFolder};
+use crate::track::{Track, scan_tracks};
+
+/// Files read before they are written to the database in one transaction, the connection is
+/// released in between so that the library stays usable during a scan.
+const BATCH_SIZE: usize = 64;
+
+/// Reading metadata is mostly waiting for the disk, more readers than this don't help.
+const MAX_READERS: usize = 8;
+
+/// What happened to a file during a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanEvent {
+    /// A file that is new or has been modified was found and is going to be read.
+    Found,
+    Processed,
+    /// The file cannot be read, it's kept as unplayable.
+    Failed,
+}
+
+/// Counts of the events of a scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanProgress {
+    pub found: usize,
+    /// Files read so far, including the ones that failed.
+    pub processed: usize,
+    pub failed: usize,
+}
+
+impl ScanProgress {
+    pub fn update(&mut self, event: ScanEvent) {
+        match event {
+            ScanEvent::Found => self.found += 1,
+            ScanEvent::Processed => self.processed += 1,
+            ScanEvent::Failed => {
+                self.processed += 1;
+                self.failed += 1;
+            }
+        }
+    }
+}
+
+/// Tracks read from a file, along with the error if it could not be read.
+type ReadFile = (PathBuf, Vec<Track>, Vec<LibraryError>);
+
+impl Database {
+    /// This function updates a music library database based on local audio files.
+    /// It either performs a full scan of all files or a partial, incremental update that only processes new or modified files.
+    ///
+    /// Files are found by a walker thread, read by several reader threads and written to the
+    /// database in batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `folders` - The folders whose music files make up the library.
+    /// * `full` - A boolean flag.
+    ///   - If true, the function will perform a full refresh, scanning all audio files in the configured folders.
+    ///   - If false, it will perform an incremental refresh, only processing files that are new or have been modified since their last entry in the database.
+    /// * `progress` - Receives an event for every file found and read.
+    /// * `cancelled` - Checked between files, what has been read until then is still stored.
+    ///
+    /// Files whose metadata cannot be read are stored as unplayable and returned as errors, the
+    /// rest of the files are still processed.
+    ///
+    /// Tracks whose file is gone are hidden and removed after `MISSING_GRACE_PERIOD`, unless a
+    /// new file with the same fingerprint shows up, which is taken as the file being moved.
+    /// Tracks outside of the folders, or left out by their patterns, are hidden the same way.
+    /// Nothing is hidden without folders, which is more likely a broken configuration than an
+    /// empty library.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be updated.
+    pub fn refresh_library(
+        &self,
+        folders: &[LibraryFolder],
+        full: bool,
+        progress: &Sender<ScanEvent>,
+        cancelled: &AtomicBool,
+    ) -> Result<Vec<LibraryError>, LibraryError> {
+        if folders.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let filters: Vec<_> = folders.iter().map(LibraryFolder::filter).collect();
+
+        let track_records = {
+            let mut conn = self.get_connection();
+
+            let tx = conn.transaction()?;
+            hide_excluded_tracks(&tx, &filters, unix_time(SystemTime::now()))?;
+            tx.commit()?;
+
+            records_by_path(get_all_tracks(&conn)?)
+        };
+
+        // NOTE: Every file looks deleted while its drive is not mounted, leave them be until then.
+        let mounted = |path: &Path| {
+            filters
+                .iter()
+                .any(|filter| filter.contains(path) && filter.root().is_dir())
+        };
+        let vanished = track_records
+            .keys()
+            .filter(|path| !path.exists() && mounted(path))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut moved_from =
+            missing_fingerprints(&self.get_connection(), &track_records, &vanished);
+
+        // NOTE: Set when writing fails, so that the other threads don't keep going for nothing.
+        let failed = AtomicBool::new(false);
+        let stopped = || cancelled.load(Ordering::Relaxed) || failed.load(Ordering::Relaxed);
+
+        let (path_tx, path_rx) = mpsc::channel();
+        let (read_tx, read_rx) = mpsc::channel();
+        let path_rx = Mutex::new(path_rx);
+
+        let (errors, moved) = thread::scope(|scope| {
+            let (filters, track_records, stopped, path_rx) =
+                (&filters, &track_records, &stopped, &path_rx);
+
+            scope.spawn(move || {
+                walk(filters, track_records, full, &path_tx, progress, stopped);
+            });
+
+            let readers = thread::available_parallelism()
+                .map_or(1, NonZero::get)
+                .min(MAX_READERS);
+            for _ in 0..readers {
+                let read_tx = read_tx.clone();
+
+                scope.spawn(move || read(path_rx, &read_tx, progress, stopped));
+            }
+            drop(read_tx);
+
+            let written = self.write_files(&read_rx, track_records, &mut moved_from);
+            if written.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+
+            written
+        })?;
+
+        if !cancelled.load(Ordering::Relaxed) {
+            let mut conn = self.get_connection();
+            let tx = conn.transaction()?;
+
+            let now = unix_time(SystemTime::now());
+            for path in vanished.iter().filter(|path| !moved.contains(*path)) {
+                mark_missing(&tx, path, now)?;
+            }
+            if filters.iter().all(|filter| filter.root().is_dir()) {
+                delete_missing(
+                    &tx,
+                    now.saturating_sub(
+                        i64::try_from(MISSING_GRACE_PERIOD.as_secs()).unwrap_or_default(),
+                    ),
+                )?;
+            }
+
+            tx.commit()?;
+        }
+
+        Ok(errors)
+    }
+
+    /// Writes the files read by the readers until they are done.
+    ///
+    /// Returns the errors of the files and the paths of the missing files that were moved.
+    fn write_files(
+        &self,
+        read_rx: &Receiver<ReadFile>,
+        track_records: &HashMap<PathBuf, Vec<Track>>,
+        missing: &mut HashMap<Fingerprint, PathBuf>,
+    ) -> Result<(Vec<LibraryError>, HashSet<PathBuf>), LibraryError> {
+        let mut errors = Vec::new();
+        let mut moved = HashSet::new();
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+        loop {
+            let file = read_rx.recv().ok();
+            let done = file.is_none();
+
+            batch.extend(file);
+
+            if batch.len() < BATCH_SIZE && !done {
+                continue;
+            }
+
+            let mut conn = self.get_connection();
+            let tx = conn.transaction()?;
+
+            for (entry, tracks, file_errors) in batch.drain(..) {
+                let records = track_records.get(&entry).map(Vec::as_slice);
+
+                if let Some(from) = store_file(&tx, &entry, &tracks, records, missing) {
+                    moved.insert(from);
+                }
+                errors.extend(file_errors);
+            }
+
+            tx.commit()?;
+
+            if done {
+                return Ok((errors, moved));
+            }
+        }
+    }
+}
+
+/// Sends the files of the folders that have to be read, every file once even if the folders
+/// are nested in one another.
+fn walk(
+    filters: &[FolderFilter],
+    track_records: &HashMap<PathBuf, Vec<Track>>,
+    full: bool,
+    path_tx: &Sender<PathBuf>,
+    progress: &Sender<ScanEvent>,
+    stopped: &(dyn Fn() -> bool + Sync),
+) {
+    let mut seen = HashSet::new();
+
+    for entry in filters
+        .iter()
+        .flat_map(|filter| scan_tracks(filter.root(), filter))
+    {
+        if stopped() {
+            return;
+        }
+
+        let modified = full
+            || track_records
+                .get(&entry)
+                .and_then(|records| records.first())
+                .is_none_or(|record| is_modified(&entry, record));
+
+        if modified && seen.insert(entry.clone()) {
+            progress.send(ScanEvent::Found).ok();
+
+            if path_tx.send(entry).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Reads files until there are no more or the scan is stopped.
+fn read(
+    path_rx: &Mutex<Receiver<PathBuf>>,
+    read_tx: &Sender<ReadFile>,
+    progress: &Sender<ScanEvent>,
+    stopped: &(dyn Fn() -> bool + Sync),
+) {
+    while !stopped() {
+        let Ok(entry) = path_rx.lock().recv() else {
+            return;
+        };
+
+        let mut errors = Vec::new();
+        let tracks = read_file(&entry, &mut errors);
+
+        progress
+            .send(if errors.is_empty() {
+                ScanEvent::Processed
+            } else {
+                ScanEvent::Failed
+            })
+            .ok();
+
+        if read_tx.send((entry, tracks, errors)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+
+    use super::ScanProgress;
+    use crate::config::LibraryFolder;
+    use crate::database::test::{database, temp_dir};
+    use crate::database::{Database, get_all_tracks};
+
+    #[test]
+    fn reports_progress_and_stops_when_cancelled() {
+        let dir = temp_dir("scan");
+        fs::create_dir(dir.join("Disc 2")).expect("Directory created.");
+        // NOTE: Files that cannot be read are still stored, as unplayable tracks.
+        for path in ["a.flac", "b.flac", "Disc 2/c.flac"] {
+            fs::write(dir.join(path), b"not audio").expect("File written.");
+        }
+        fs::write(dir.join("cover.txt"), b"not music").expect("File written.");
+
+        let folders = [LibraryFolder::new(dir.clone())];
+        let scan = |database: &Database, cancelled: bool| {
+            let (progress_tx, progress_rx) = mpsc::channel();
+            let cancelled = AtomicBool::new(cancelled);
+
+            let errors = database
+                .refresh_library(&folders, false, &progress_tx, &cancelled)
+                .expect("Library refreshed.");
+            drop(progress_tx);
+
+            let mut progress = ScanProgress::default();
+            for event in progress_rx {
+                progress.update(event);
+            }
+            (progress, errors.len())
+        };
+
+        let cancelled = database();
+        assert_eq!(
+            scan(&cancelled, true),
+            (ScanProgress::default(), 0),
+            "Cancelled scan should not read any file."
+        );
+        assert!(
+            get_all_tracks(&cancelled.get_connection())
+                .expect("Tracks read.")
+                .is_empty(),
+            "Cancelled scan should not store any track."
+        );
+
+        let database = database();
+        assert_eq!(
+            scan(&database, false),
+            (
+                ScanProgress {
+                    found: 3,
+                    processed: 3,
+                    failed: 3,
+                },
+                3
+            ),
+            "Every music file should be found and read."
+        );
+        assert_eq!(
+            get_all_tracks(&database.get_connection())
+                .expect("Tracks read.")
+                .len(),
+            3,
+            "Every music file should be stored."
+        );
+        assert_eq!(
+            scan(&database, false),
+            (ScanProgress::default(), 0),
+            "Unmodified files should not be read again."
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rusqlite::{Connection, named_params};
+use unicode_normalization::UnicodeNormalization as _;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::track::Track;
+
+/// Inclusive range of a number searched for, such as the year of the tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Bounds {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl Bounds {
+    /// Parses comparisons such as `>2000`, `<=3`, `1990..1999` or `2000`.
+    fn parse(value: &str) -> Option<Self> {
+        let number = |value: &str| value.trim().parse::<i64>().ok();
+
+        Some(if let Some(value) = value.strip_prefix(">=") {
+            Self {
+                min: Some(number(value)?),
+                max: None,
+            }
+        } else if let Some(value) = value.strip_prefix("<=") {
+            Self {
+                min: None,
+                max: Some(number(value)?),
+            }
+        } else if let Some(value) = value.strip_prefix('>') {
+            Self {
+                min: Some(number(value)?.saturating_add(1)),
+                max: None,
+            }
+        } else if let Some(value) = value.strip_prefix('<') {
+            Self {
+                min: None,
+                max: Some(number(value)?.saturating_sub(1)),
+            }
+        } else if let Some((from, to)) = value.split_once("..") {
+            Self {
+                min: Some(number(from)?),
+                max: Some(number(to)?),
+            }
+        } else {
+            let value = number(value.strip_prefix('=').unwrap_or(value))?;
+
+            Self {
+                min: Some(value),
+                max: Some(value),
+            }
+        })
+    }
+
+    /// The numbers left out by these bounds, `None` if they are not a single range.
+    fn invert(self) -> Option<Self> {
+        match (self.min, self.max) {
+            (Some(min), None) => Some(Self {
+                min: None,
+                max: Some(min.saturating_sub(1)),
+            }),
+            (None, Some(max)) => Some(Self {
+                min: Some(max.saturating_add(1)),
+                max: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` is within the bounds, unknown values are only within unbounded ones.
+    fn contains(self, value: Option<i64>) -> bool {
+        match value {
+            Some(value) => {
+                self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+            }
+            None => self.min.is_none() && self.max.is_none(),
+        }
+    }
+
+    fn intersect(self, other: Self) -> Self {
+        Self {
+            min: self.min.max(other.min),
+            max: match (self.max, other.max) {
+                (Some(max), Some(other)) => Some(max.min(other)),
+                (max, other) => max.or(other),
+            },
+        }
+    }
+}
+
+/// Words searched as a phrase in the tags of tracks that are not in the library, the last word
+/// matching the start of a word as it does with FTS5.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Phrase {
+    /// Column of the tag searched, `None` for any of them.
+    column: Option<&'static str>,
+    words: Vec<String>,
+}
+
+impl Phrase {
+    fn matches(&self, track: &Track) -> bool {
+        let tags = [
+            ("title", &track.title),
+            ("artist", &track.artist),
+            ("album", &track.album),
+            ("album_artist", &track.album_artist),
+            ("genre", &track.genre),
+        ];
+
+        tags.into_iter()
+            .filter(|(column, _)| self.column.is_none_or(|searched| searched == *column))
+            .filter_map(|(_, tag)| tag.as_deref())
+            .any(|tag| {
+                words(tag).windows(self.words.len()).any(|window| {
+                    window
+                        .iter()
+                        .zip(&self.words)
+                        .enumerate()
+                        .all(|(index, (word, searched))| {
+                            if index + 1 == self.words.len() {
+                                word.starts_with(searched.as_str())
+                            } else {
+                                word == searched
+                            }
+                        })
+                })
+            })
+    }
+}
+
+/// Search of the library such as `artist:radiohead year:>2000 genre:"post rock" -live`.
+///
+/// Words match the start of the words of the tags and quoted words match as a phrase, all of
+/// them have to match and the ones starting with `-` must not. Tags are picked with `title:`,
+/// `artist:`, `album:`, `albumartist:` and `genre:`, while `year:`, `rating:` and `plays:`
+/// compare numbers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// FTS5 expression the tracks have to match, `None` for every track.
+    matched: Option<String>,
+    /// FTS5 expression of the tracks left out.
+    excluded: Option<String>,
+    /// Phrases of `matched` and `excluded`, for tracks that are not in the library.
+    matched_phrases: Vec<Phrase>,
+    excluded_phrases: Vec<Phrase>,
+    year: Bounds,
+    rating: Bounds,
+    plays: Bounds,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut search = Self::default();
+        let mut matched = Vec::new();
+        let mut excluded = Vec::new();
+
+        for term in split_terms(query) {
+            let (negated, term) = match term.strip_prefix('-') {
+                Some(term) if !term.is_empty() => (true, term),
+                _ => (false, term),
+            };
+            let (field, value) = term
+                .split_once(':')
+                .filter(|(field, _)| !field.contains('"'))
+                .unwrap_or(("", term));
+            let field = field.to_lowercase();
+
+            let bounds = match field.as_str() {
+                "year" => Some(&mut search.year),
+                "rating" => Some(&mut search.rating),
+                "plays" => Some(&mut search.plays),
+                _ => None,
+            };
+            if let Some(bounds) = bounds {
+                // NOTE: Comparisons still being typed, such as `year:>`, are left out.
+                if let Some(other) = Bounds::parse(value)
+                    .and_then(|other| if negated { other.invert() } else { Some(other) })
+                {
+                    *bounds = bounds.intersect(other);
+                }
+                continue;
+            }
+
+            let column = match field.as_str() {
+                "title" => Some("title"),
+                "artist" => Some("artist"),
+                "album" => Some("album"),
+                "albumartist" => Some("album_artist"),
+                "genre" => Some("genre"),
+                _ => None,
+            };
+            let value = if column.is_some() { value } else { term };
+            let expression = match column {
+                Some(column) => phrase(value).map(|phrase| format!("{column} : {phrase}")),
+                None => phrase(value),
+            };
+
+            if let Some(expression) = expression {
+                let phrase = Phrase {
+                    column,
+                    words: words(value),
+                };
+
+                if negated {
+                    excluded.push(expression);
+                    search.excluded_phrases.push(phrase);
+                } else {
+                    matched.push(expression);
+                    search.matched_phrases.push(phrase);
+                }
+            }
+        }
+
+        search.matched = (!matched.is_empty()).then(|| matched.join(" AND "));
+        search.excluded = (!excluded.is_empty()).then(|| excluded.join(" OR "));
+        search
+    }
+
+    /// Whether a track that is not in the library matches, as the search of the library would
+    /// find it. Case and diacritics are folded.
+    pub fn matches(&self, track: &Track) -> bool {
+        self.matched_phrases
+            .iter()
+            .all(|phrase| phrase.matches(track))
+            && !self
+                .excluded_phrases
+                .iter()
+                .any(|phrase| phrase.matches(track))
+            && self.year.contains(track.year.map(i64::from))
+            && self
+                .rating
+                .contains(Some(i64::from(track.rating.unwrap_or_default())))
+            && self.plays.contains(Some(i64::from(track.play_count)))
+    }
+}
+
+/// Splits a query at the spaces that are not quoted.
+fn split_terms(query: &str) -> Vec<&str> {
+    let mut terms = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (index, c) in query.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c.is_whitespace() && !quoted {
+            terms.extend(query.get(start..index));
+            start = index + c.len_utf8();
+        }
+    }
+    terms.extend(query.get(start..));
+
+    terms.retain(|term| !term.is_empty());
+    terms
+}
+
+/// FTS5 phrase matching the start of the words of `value`, `None` if it has no words.
+fn phrase(value: &str) -> Option<String> {
+    // NOTE: Quotes can't be escaped in a phrase, the tokenizer drops them anyway.
+    let value = value.replace('"', " ");
+
+    value
+        .chars()
+        .any(char::is_alphanumeric)
+        .then(|| format!("\"{}\"*", value.trim()))
+}
+
+/// Lowercase words of `value` without diacritics, split and folded as the FTS5 tokenizer does.
+fn words(value: &str) -> Vec<String> {
+    value
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Tracks found by a search, by file and then start in the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchResults(HashMap<PathBuf, Vec<Duration>>);
+
+impl SearchResults {
+    pub fn contains(&self, track: &Track) -> bool {
+        self.0
+            .get(&track.path)
+            .is_some_and(|starts| starts.contains(&track.start()))
+    }
+
+    pub fn insert(&mut self, track: &Track) {
+        self.0
+            .entry(track.path.clone())
+            .or_default()
+            .push(track.start());
+    }
+}
+
+/// Tracks of the library matching a search, without the ones whose file is missing.
+pub fn search_tracks(
+    conn: &Connection,
+    query: &SearchQuery,
+) -> Result<SearchResults, rusqlite::Error> {
+    let mut stmt = conn.prepare_cached(include_str!("./sql/search_tracks.sql"))?;
+
+    let rows = stmt.query_map(
+        named_params! {
+            ":matched": query.matched,
+            ":excluded": query.excluded,
+            ":min_year": query.year.min,
+            ":max_year": query.year.max,
+            ":min_rating": query.rating.min,
+            ":max_rating": query.rating.max,
+            ":min_plays": query.plays.min,
+            ":max_plays": query.plays.max,
+        },
+        |row| {
+            Ok((
+                row.get("path").map(|v: String| PathBuf::from(v))?,
+                row.get("start_time").map(|v: i64| {
+                    Duration::from_millis(u64::try_from(v.max(0)).unwrap_or_default())
+                })?,
+            ))
+        },
+    )?;
+
+    let mut results = SearchResults::default();
+    for row in rows {
+        let (path, start) = row?;
+
+        results.0.entry(path).or_default().push(start);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::Connection;
+
+    use super::{Bounds, SearchQuery, search_tracks};
+    use crate::database::{migrate, set_rating, upsert_track};
+    use crate::track::Track;
+
+    #[test]
+    fn parses_fields_and_exclusions() {
+        let query = SearchQuery::parse(r#"artist:radiohead year:>2000 genre:"post rock" -live"#);
+
+        assert_eq!(
+            query.matched.as_deref(),
+            Some(r#"artist : "radiohead"* AND genre : "post rock"*"#),
+            "Tags should be searched in their column."
+        );
+        assert_eq!(
+            query.excluded.as_deref(),
+            Some(r#""live"*"#),
+            "Negated words should be left out."
+        );
+        assert_eq!(
+            query.year,
+            Bounds {
+                min: Some(2001),
+                max: None,
+            },
+            "Years should be compared."
+        );
+        assert_eq!(
+            SearchQuery::parse("year:> -rating:<3 \"\""),
+            SearchQuery {
+                rating: Bounds {
+                    min: Some(3),
+                    max: None,
+                },
+                ..SearchQuery::default()
+            },
+            "Incomplete terms should be left out and comparisons inverted."
+        );
+    }
+
+    #[test]
+    fn searches_without_case_and_diacritics() {
+        let mut conn = Connection::open_in_memory().expect("In-memory database opened.");
+        migrate(&mut conn).expect("Database migrated.");
+
+        for (path, artist, title, year) in [
+            ("a.flac", "Beyoncé", "Halo", 2008),
+            ("b.flac", "Björk", "Jóga", 1997),
+            ("c.flac", "Björk", "Hyperballad (Live)", 1998),
+        ] {
+            let track = Track {
+                path: path.into(),
+                artist: Some(artist.to_owned()),
+                title: Some(title.to_owned()),
+                year: Some(year),
+                ..Default::default()
+            };
+            upsert_track(&conn, &track).expect("Track inserted.");
+            set_rating(&conn, &track, Some(4)).expect("Track rated.");
+        }
+
+        let search = |query: &str| {
+            let results = search_tracks(&conn, &SearchQuery::parse(query)).expect("Searched.");
+            let mut paths = results
+                .0
+                .into_keys()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(
+            search("BEYONCE"),
+            vec!["a.flac"],
+            "Case and diacritics should be ignored."
+        );
+        assert_eq!(
+            search("artist:bjo -live"),
+            vec!["b.flac"],
+            "Words should match as prefixes and be left out."
+        );
+        assert_eq!(
+            search("year:1990..2000 rating:>=4"),
+            vec!["b.flac", "c.flac"],
+            "Numbers should be compared."
+        );
+        assert_eq!(search("").len(), 3, "Empty search should find every track.");
+    }
+
+    #[test]
+    fn matches_tracks_outside_library() {
+        let track = Track {
+            path: "elsewhere.flac".into(),
+            artist: Some("Sigur Rós".to_owned()),
+            title: Some("Hoppípolla (Live)".to_owned()),
+            genre: Some("Post Rock".to_owned()),
+            year: Some(2005),
+            ..Default::default()
+        };
+        let matches = |query: &str| SearchQuery::parse(query).matches(&track);
+
+        assert!(matches("SIGUR ró"), "Case should be folded.");
+        assert!(
+            matches("sigur ros hoppipolla"),
+            "Diacritics of the tags should be folded."
+        );
+        assert!(
+            matches("genre:pöst"),
+            "Diacritics of the search should be folded."
+        );
+        assert!(
+            matches(r#"genre:"post ro" year:2000..2010"#),
+            "Phrases should match the start of their last word."
+        );
+        assert!(!matches("artist:hopp"), "Other tags should not match.");
+        assert!(!matches("sigur -live"), "Excluded words should not match.");
+        assert!(
+            !matches("rating:>=1"),
+            "Unrated tracks should have no stars."
+        );
+        assert!(matches(""), "Empty search should match every track.");
+    }
+}
@@ -0,0 +1,2 @@
+DELETE FROM bookmarks
+WHERE id = :id;
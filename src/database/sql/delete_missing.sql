@@ -0,0 +1 @@
+DELETE FROM tracks WHERE missing_since < :before;
@@ -0,0 +1 @@
+DELETE FROM settings WHERE substr(key, 1, length(:prefix)) = :prefix;
@@ -0,0 +1,2 @@
+DELETE FROM tracks
+WHERE path = :path AND start_time = :start_time;
@@ -0,0 +1,3 @@
+SELECT * FROM bookmarks
+WHERE path = :path AND start_time = :start_time
+ORDER BY position ASC;
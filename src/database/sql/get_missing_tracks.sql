@@ -0,0 +1 @@
+SELECT * FROM tracks WHERE missing_since IS NOT NULL;
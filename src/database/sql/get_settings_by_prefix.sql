@@ -0,0 +1 @@
+SELECT key, value FROM settings WHERE substr(key, 1, length(:prefix)) = :prefix;
@@ -0,0 +1,21 @@
+SELECT
+  tracks.*,
+  COALESCE(stats.play_count, 0) AS play_count,
+  COALESCE(stats.skip_count, 0) AS skip_count,
+  stats.last_played
+FROM tracks
+LEFT JOIN (
+  SELECT
+    track_id,
+    SUM(completed) AS play_count,
+    SUM(NOT completed) AS skip_count,
+    MAX(started_at) AS last_played
+  FROM plays
+  GROUP BY track_id
+) AS stats ON stats.track_id = tracks.id
+WHERE tracks.missing_since IS NULL
+  AND (tracks.path = :path OR substr(tracks.path, 1, length(:prefix)) = :prefix)
+ORDER BY
+  tracks.album ASC,
+  CAST(tracks.disc AS INTEGER) ASC,
+  CAST(tracks.track AS INTEGER) ASC;
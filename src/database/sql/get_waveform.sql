@@ -0,0 +1,2 @@
+SELECT data FROM waveforms
+WHERE path = :path AND start_time = :start_time AND modified IS :modified;
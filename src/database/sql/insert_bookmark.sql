@@ -0,0 +1,3 @@
+INSERT INTO bookmarks(path, start_time, position, name)
+VALUES (:path, :start_time, :position, :name)
+RETURNING id;
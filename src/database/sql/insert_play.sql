@@ -0,0 +1,3 @@
+INSERT INTO plays(track_id, started_at, played, completed)
+SELECT id, :started_at, :played, :completed FROM tracks
+WHERE path = :path AND start_time = :start_time;
@@ -0,0 +1 @@
+UPDATE tracks SET missing_since = COALESCE(missing_since, :now) WHERE path = :path;
@@ -0,0 +1 @@
+UPDATE tracks SET path = :path, missing_since = NULL WHERE path = :from;
@@ -0,0 +1,20 @@
+SELECT tracks.path, tracks.start_time
+FROM tracks
+LEFT JOIN (
+  SELECT track_id, SUM(completed) AS play_count
+  FROM plays
+  GROUP BY track_id
+) AS stats ON stats.track_id = tracks.id
+WHERE tracks.missing_since IS NULL
+  AND (:matched IS NULL OR tracks.id IN (
+    SELECT rowid FROM track_search WHERE track_search MATCH :matched
+  ))
+  AND (:excluded IS NULL OR tracks.id NOT IN (
+    SELECT rowid FROM track_search WHERE track_search MATCH :excluded
+  ))
+  AND (:min_year IS NULL OR tracks.year >= :min_year)
+  AND (:max_year IS NULL OR tracks.year <= :max_year)
+  AND (:min_rating IS NULL OR COALESCE(tracks.rating, 0) >= :min_rating)
+  AND (:max_rating IS NULL OR COALESCE(tracks.rating, 0) <= :max_rating)
+  AND (:min_plays IS NULL OR COALESCE(stats.play_count, 0) >= :min_plays)
+  AND (:max_plays IS NULL OR COALESCE(stats.play_count, 0) <= :max_plays);
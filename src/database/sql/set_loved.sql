@@ -0,0 +1 @@
+UPDATE tracks SET loved = :loved WHERE path = :path AND start_time = :start_time;
@@ -0,0 +1 @@
+UPDATE tracks SET modified = :modified WHERE path = :path;
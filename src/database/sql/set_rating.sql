@@ -0,0 +1 @@
+UPDATE tracks SET rating = :rating, rated_in_app = 1 WHERE path = :path AND start_time = :start_time;
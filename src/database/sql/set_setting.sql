@@ -0,0 +1,4 @@
+INSERT INTO settings(key, value)
+VALUES (:key, :value)
+ON CONFLICT(key) DO UPDATE SET
+  value = excluded.value;
@@ -0,0 +1 @@
+UPDATE tracks SET unplayable = :unplayable WHERE path = :path;
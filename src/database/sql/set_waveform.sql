@@ -0,0 +1,5 @@
+INSERT INTO waveforms(path, start_time, modified, data)
+VALUES (:path, :start_time, :modified, :data)
+ON CONFLICT(path, start_time) DO UPDATE SET
+  modified = excluded.modified,
+  data = excluded.data;
@@ -0,0 +1,6 @@
+UPDATE tracks SET
+  track_gain = :track_gain,
+  track_peak = :track_peak,
+  album_gain = :album_gain,
+  album_peak = :album_peak
+WHERE path = :path AND start_time = :start_time;
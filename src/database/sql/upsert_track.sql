@@ -1,6 +1,7 @@
-INSERT INTO tracks(path, modified, title, artist, genre, album, album_artist, track, track_total, disc, disc_total, duration)
-VALUES (:path, :modified, :title, :artist, :genre, :album, :album_artist, :track, :track_total, :disc, :disc_total, :duration)
-ON CONFLICT(path) DO UPDATE SET
+INSERT INTO tracks(path, start_time, end_time, modified, title, artist, genre, album, album_artist, track, track_total, disc, disc_total, duration, track_gain, track_peak, album_gain, album_peak, bitrate, sample_rate, bit_depth, channels, codec, file_size, unplayable, rating, year)
+VALUES (:path, :start_time, :end_time, :modified, :title, :artist, :genre, :album, :album_artist, :track, :track_total, :disc, :disc_total, :duration, :track_gain, :track_peak, :album_gain, :album_peak, :bitrate, :sample_rate, :bit_depth, :channels, :codec, :file_size, :unplayable, :rating, :year)
+ON CONFLICT(path, start_time) DO UPDATE SET
+  end_time = excluded.end_time,
   modified = excluded.modified,
   title = excluded.title,
   artist = excluded.artist,
@@ -11,5 +12,19 @@ ON CONFLICT(path) DO UPDATE SET
   track_total = excluded.track_total,
   disc = excluded.disc,
   disc_total = excluded.disc_total,
-  duration = excluded.duration
+  duration = excluded.duration,
+  year = excluded.year,
+  track_gain = COALESCE(excluded.track_gain, tracks.track_gain),
+  track_peak = COALESCE(excluded.track_peak, tracks.track_peak),
+  album_gain = COALESCE(excluded.album_gain, tracks.album_gain),
+  album_peak = COALESCE(excluded.album_peak, tracks.album_peak),
+  bitrate = excluded.bitrate,
+  sample_rate = excluded.sample_rate,
+  bit_depth = excluded.bit_depth,
+  channels = excluded.channels,
+  codec = excluded.codec,
+  file_size = excluded.file_size,
+  unplayable = excluded.unplayable,
+  rating = CASE WHEN tracks.rated_in_app THEN tracks.rating ELSE excluded.rating END,
+  missing_since = NULL
 RETURNING id;
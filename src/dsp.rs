@@ -0,0 +1,41 @@
+/// Biquad filter in transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// Creates a filter from unnormalized coefficients, `a[0]` being the output gain.
+    pub fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        let [a0, a1, a2] = a;
+
+        Self {
+            b: b.map(|b| b / a0),
+            a: [a1 / a0, a2 / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// Takes the coefficients of `other` while keeping the state, avoiding clicks on changes.
+    pub fn update(&mut self, other: &Self) {
+        self.b = other.b;
+        self.a = other.a;
+    }
+
+    pub fn reset(&mut self) {
+        self.z = [0.0; 2];
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+
+        let y = b0 * x + self.z[0];
+        self.z[0] = b1 * x - a1 * y + self.z[1];
+        self.z[1] = b2 * x - a2 * y;
+
+        y
+    }
+}
@@ -1,9 +1,14 @@
 mod app;
 mod config;
+mod cue;
 mod database;
+mod dsp;
+mod loudness;
 mod player;
 mod playlist;
 mod track;
 mod ui;
+mod watcher;
+mod waveform;
 
 pub use app::App;
@@ -0,0 +1,464 @@
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rodio::decoder::DecoderError;
+use rodio::{ChannelCount, Sample, SampleRate};
+
+use crate::dsp::Biquad;
+use crate::player::decode_track;
+use crate::track::Track;
+
+/// Blocks quieter than this (in LUFS) never count towards the integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks more than this (in LU) below the absolute gated loudness are ignored.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Replay gain 2.0 reference level in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// Gating blocks are 400 ms long and overlap by 75%, so each one is made of four 100 ms steps.
+const STEPS_PER_BLOCK: usize = 4;
+
+const OVERSAMPLING: usize = 4;
+const INTERPOLATION_TAPS: usize = 12;
+
+/// Shared state of a running loudness analysis, polled by the UI for progress.
+#[derive(Default, Debug)]
+pub struct LoudnessAnalysis {
+    total: AtomicUsize,
+    analyzed: AtomicUsize,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl LoudnessAnalysis {
+    /// Returns the number of analyzed tracks and the total number of tracks to analyze.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.analyzed.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self) {
+        self.analyzed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
+
+/// Result of an EBU R128 measurement.
+#[derive(Default, Debug, Clone)]
+pub struct Loudness {
+    /// Mean square energy of every 400 ms gating block.
+    blocks: Vec<f64>,
+    true_peak: f64,
+}
+
+impl Loudness {
+    /// Combines the measurements of every track of an album, as if they were played back to back.
+    pub fn album<'a>(tracks: impl IntoIterator<Item = &'a Self>) -> Self {
+        tracks
+            .into_iter()
+            .fold(Self::default(), |mut album, track| {
+                album.blocks.extend_from_slice(&track.blocks);
+                album.true_peak = album.true_peak.max(track.true_peak);
+                album
+            })
+    }
+
+    /// Gated integrated loudness in LUFS, `None` for silent or very short inputs.
+    pub fn integrated(&self) -> Option<f64> {
+        let loud = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|energy| energy_to_loudness(*energy) > ABSOLUTE_GATE)
+            .collect::<Vec<_>>();
+        let threshold = energy_to_loudness(mean(&loud)?) + RELATIVE_GATE;
+
+        let gated = loud
+            .into_iter()
+            .filter(|energy| energy_to_loudness(*energy) > threshold)
+            .collect::<Vec<_>>();
+
+        mean(&gated).map(energy_to_loudness)
+    }
+
+    /// Gain in dB needed to reach the replay gain reference level.
+    pub fn gain(&self) -> Option<f32> {
+        self.integrated()
+            .map(|loudness| (REFERENCE_LOUDNESS - loudness) as f32)
+    }
+
+    /// Linear true peak, estimated with 4x oversampling.
+    pub fn peak(&self) -> f32 {
+        self.true_peak as f32
+    }
+}
+
+/// Decodes a track with the playback decoders and measures its loudness.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or decoded.
+pub fn measure(track: &Track) -> Result<Loudness, DecoderError> {
+    let decoder = decode_track(track)?;
+
+    let mut meter = LoudnessMeter::new(decoder.channels(), decoder.sample_rate());
+    for sample in decoder {
+        meter.push(sample);
+    }
+
+    Ok(meter.finish())
+}
+
+/// Loudness meter following ITU-R BS.1770-4, fed with interleaved samples.
+pub struct LoudnessMeter {
+    channels: Vec<Channel>,
+    interpolation: [[f64; INTERPOLATION_TAPS]; OVERSAMPLING],
+    current_channel: usize,
+
+    step_len: usize,
+    step_frames: usize,
+    step_energy: f64,
+    steps: [f64; STEPS_PER_BLOCK],
+    steps_filled: usize,
+
+    loudness: Loudness,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: ChannelCount, sample_rate: SampleRate) -> Self {
+        let channel_count = usize::from(channels.max(1));
+
+        Self {
+            channels: (0..channel_count)
+                .map(|index| Channel {
+                    filters: k_weighting(f64::from(sample_rate)),
+                    weight: channel_weight(channel_count, index),
+                    history: [0.0; INTERPOLATION_TAPS],
+                    history_position: 0,
+                })
+                .collect(),
+            interpolation: interpolation_filter(),
+            current_channel: 0,
+
+            step_len: (sample_rate as usize / 10).max(1),
+            step_frames: 0,
+            step_energy: 0.0,
+            steps: [0.0; STEPS_PER_BLOCK],
+            steps_filled: 0,
+
+            loudness: Loudness::default(),
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        let sample = f64::from(sample);
+        let Some(channel) = self.channels.get_mut(self.current_channel) else {
+            return;
+        };
+
+        let filtered = channel.filters.iter_mut().fold(sample, |x, f| f.process(x));
+        self.step_energy += channel.weight * filtered * filtered;
+
+        let peak = channel.true_peak(sample, &self.interpolation);
+        self.loudness.true_peak = self.loudness.true_peak.max(peak);
+
+        self.current_channel += 1;
+        if self.current_channel == self.channels.len() {
+            self.current_channel = 0;
+            self.step_frames += 1;
+        }
+
+        if self.step_frames == self.step_len {
+            self.steps.rotate_left(1);
+            if let Some(last) = self.steps.last_mut() {
+                *last = self.step_energy;
+            }
+            self.steps_filled = (self.steps_filled + 1).min(STEPS_PER_BLOCK);
+
+            if self.steps_filled == STEPS_PER_BLOCK {
+                self.loudness.blocks.push(
+                    self.steps.iter().sum::<f64>() / (STEPS_PER_BLOCK * self.step_len) as f64,
+                );
+            }
+
+            self.step_frames = 0;
+            self.step_energy = 0.0;
+        }
+    }
+
+    pub fn finish(self) -> Loudness {
+        self.loudness
+    }
+}
+
+struct Channel {
+    filters: [Biquad; 2],
+    weight: f64,
+    history: [f64; INTERPOLATION_TAPS],
+    history_position: usize,
+}
+
+impl Channel {
+    /// Returns the highest absolute value among the sample and the interpolated ones before it.
+    fn true_peak(
+        &mut self,
+        sample: f64,
+        interpolation: &[[f64; INTERPOLATION_TAPS]; OVERSAMPLING],
+    ) -> f64 {
+        self.history_position = (self.history_position + 1) % INTERPOLATION_TAPS;
+        if let Some(slot) = self.history.get_mut(self.history_position) {
+            *slot = sample;
+        }
+
+        interpolation.iter().fold(sample.abs(), |peak, phase| {
+            let value = phase
+                .iter()
+                .enumerate()
+                .map(|(tap, coefficient)| {
+                    let index =
+                        (self.history_position + INTERPOLATION_TAPS - tap) % INTERPOLATION_TAPS;
+                    coefficient * self.history.get(index).copied().unwrap_or_default()
+                })
+                .sum::<f64>();
+
+            peak.max(value.abs())
+        })
+    }
+}
+
+/// K-weighting pre-filter (high shelf) and RLB filter (high pass) for any sample rate.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    // NOTE: Analog prototype parameters matching the BS.1770 48 kHz coefficients.
+    let shelf = {
+        let f0 = 1_681.974_450_955_533;
+        let gain = 3.999_843_853_973_347;
+        let q = 0.707_175_236_955_419_6;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10_f64.powf(gain / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+
+        Biquad::new(
+            [
+                vh + vb * k / q + k * k,
+                2.0 * (k * k - vh),
+                vh - vb * k / q + k * k,
+            ],
+            [
+                1.0 + k / q + k * k,
+                2.0 * (k * k - 1.0),
+                1.0 - k / q + k * k,
+            ],
+        )
+    };
+
+    let high_pass = {
+        let f0 = 38.135_470_876_024_44;
+        let q = 0.500_327_037_323_877_3;
+
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad::new(
+            [a0, -2.0 * a0, a0],
+            [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+        )
+    };
+
+    [shelf, high_pass]
+}
+
+/// Polyphase windowed sinc interpolator used to estimate inter-sample peaks.
+fn interpolation_filter() -> [[f64; INTERPOLATION_TAPS]; OVERSAMPLING] {
+    let len = (INTERPOLATION_TAPS * OVERSAMPLING) as f64;
+    let center = len / 2.0;
+
+    let mut phases = [[0.0; INTERPOLATION_TAPS]; OVERSAMPLING];
+    for (phase, coefficients) in phases.iter_mut().enumerate() {
+        for (tap, coefficient) in coefficients.iter_mut().enumerate() {
+            let n = (tap * OVERSAMPLING + phase) as f64;
+            let x = (n - center) / OVERSAMPLING as f64;
+
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * n / len).cos();
+
+            *coefficient = sinc * window;
+        }
+    }
+
+    phases
+}
+
+/// Channel weights for the usual 5.1 layout, the LFE channel is left out.
+fn channel_weight(channels: usize, index: usize) -> f64 {
+    match (channels, index) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::f64::consts::PI;
+    use std::fs;
+
+    use super::{Loudness, LoudnessMeter, measure};
+    use crate::track::Track;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Stereo 1 kHz sine at `level` dBFS, the same in both channels.
+    fn sine(level: f64, seconds: u32) -> Vec<f32> {
+        let amplitude = 10_f64.powf(level / 20.0);
+
+        (0..SAMPLE_RATE * seconds)
+            .flat_map(|frame| {
+                let sample = amplitude
+                    * (2.0 * PI * 1_000.0 * f64::from(frame) / f64::from(SAMPLE_RATE)).sin();
+                [sample as f32; 2]
+            })
+            .collect()
+    }
+
+    fn meter(samples: &[f32]) -> Loudness {
+        let mut meter = LoudnessMeter::new(2, SAMPLE_RATE);
+        for sample in samples {
+            meter.push(*sample);
+        }
+
+        meter.finish()
+    }
+
+    #[test]
+    fn measures_reference_sine() {
+        let loudness = meter(&sine(-23.0, 5));
+
+        let integrated = loudness.integrated().expect("Loudness measured.");
+        assert!(
+            (integrated + 23.0).abs() < 0.1,
+            "Sine at -23 dBFS should be -23 LUFS, not {integrated}."
+        );
+        assert!(
+            (loudness.gain().expect("Gain computed.") - 5.0).abs() < 0.1,
+            "Gain should reach the reference level."
+        );
+        assert!(
+            (f64::from(loudness.peak()) - 10_f64.powf(-23.0 / 20.0)).abs() < 0.01,
+            "Peak should be the amplitude of the sine."
+        );
+    }
+
+    #[test]
+    fn gates_quiet_blocks() {
+        assert!(
+            meter(&vec![0.0; 2 * 5 * SAMPLE_RATE as usize])
+                .integrated()
+                .is_none(),
+            "Silence should have no loudness."
+        );
+        assert!(
+            meter(
+                sine(-23.0, 1)
+                    .get(..SAMPLE_RATE as usize / 2)
+                    .expect("Quarter of a second.")
+            )
+            .integrated()
+            .is_none(),
+            "Input shorter than a block should have no loudness."
+        );
+
+        let mut samples = sine(-23.0, 5);
+        samples.extend(sine(-50.0, 5));
+        samples.extend(vec![0.0; 2 * 5 * SAMPLE_RATE as usize]);
+
+        let integrated = meter(&samples).integrated().expect("Loudness measured.");
+        assert!(
+            (integrated + 23.0).abs() < 0.2,
+            "Quiet and silent parts should be gated out, not give {integrated}."
+        );
+    }
+
+    /// Track of a 16 bit stereo WAV file at 48 kHz of the samples, in the temporary directory.
+    #[cfg(feature = "wav")]
+    pub(crate) fn wav_track(name: &str, samples: &[f32]) -> Track {
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| ((sample * f32::from(i16::MAX)) as i16).to_le_bytes())
+            .collect();
+        let data_len = u32::try_from(data.len()).expect("Data fits.");
+
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16_u32.to_le_bytes());
+        wav.extend_from_slice(&1_u16.to_le_bytes());
+        wav.extend_from_slice(&2_u16.to_le_bytes());
+        wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE * 4).to_le_bytes());
+        wav.extend_from_slice(&4_u16.to_le_bytes());
+        wav.extend_from_slice(&16_u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav.extend(data);
+
+        let path = std::env::temp_dir().join(format!("ferrum-{}-{name}.wav", std::process::id()));
+        fs::write(&path, wav).expect("File written.");
+
+        Track {
+            path,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn measures_decoded_track() {
+        let track = wav_track("loudness", &sine(-23.0, 5));
+
+        let loudness = measure(&track).expect("Track measured.");
+        let integrated = loudness.integrated().expect("Loudness measured.");
+        assert!(
+            (integrated + 23.0).abs() < 0.1,
+            "Decoded sine should be -23 LUFS, not {integrated}."
+        );
+
+        fs::remove_file(&track.path).ok();
+    }
+}
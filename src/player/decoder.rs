@@ -0,0 +1,70 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use rodio::decoder::DecoderError;
+use rodio::{Decoder, Source};
+
+#[cfg(feature = "opus")]
+use super::opus::OpusDecoder;
+use super::source::Section;
+use crate::track::Track;
+
+/// Opens a music file with the decoder matching its format.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or none of the enabled codecs can decode it.
+pub fn decode_file(path: &Path) -> Result<Box<dyn Source + Send>, DecoderError> {
+    let open = || File::open(path).map_err(|err| DecoderError::IoError(err.to_string()));
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
+
+    #[cfg(feature = "opus")]
+    if extension.as_deref() == Some("opus") {
+        return Ok(Box::new(OpusDecoder::new(BufReader::new(open()?))?));
+    }
+
+    let file = open()?;
+    let len = file
+        .metadata()
+        .map_err(|err| DecoderError::IoError(err.to_string()))?
+        .len();
+
+    let mut builder = Decoder::builder()
+        .with_data(BufReader::new(file))
+        .with_byte_len(len)
+        .with_seekable(true);
+    if let Some(extension) = extension.as_deref() {
+        builder = builder.with_hint(extension);
+    }
+
+    let decoder = builder.build();
+
+    // NOTE: `.ogg` files may hold an Opus stream as well.
+    #[cfg(feature = "opus")]
+    if decoder.is_err()
+        && let Ok(decoder) = OpusDecoder::new(BufReader::new(open()?))
+    {
+        return Ok(Box::new(decoder));
+    }
+
+    Ok(Box::new(decoder?))
+}
+
+/// Opens the file of a track, limited to its range for tracks split by a cue sheet.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or none of the enabled codecs can decode it.
+pub fn decode_track(track: &Track) -> Result<Box<dyn Source + Send>, DecoderError> {
+    let source = decode_file(&track.path)?;
+
+    Ok(match track.range {
+        Some(range) => Box::new(Section::new(source, range.start, range.end)),
+        None => source,
+    })
+}
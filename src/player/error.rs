@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::fmt;
+
+use rodio::decoder::DecoderError;
+
+use crate::track::Track;
+
+/// Reported through `MusicPlayerEvent::PlaybackFailed` when a track cannot be played.
+#[derive(Debug)]
+pub enum PlayerError {
+    /// The file of the track cannot be opened or decoded.
+    Decode(Track, DecoderError),
+}
+
+impl PlayerError {
+    /// The track that failed to play.
+    pub fn track(&self) -> &Track {
+        match self {
+            Self::Decode(track, _) => track,
+        }
+    }
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(track, err) => write!(f, "Cannot play {}: {err}", track.path.display()),
+        }
+    }
+}
+
+impl Error for PlayerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Decode(_, err) => Some(err),
+        }
+    }
+}
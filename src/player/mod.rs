@@ -1,19 +1,52 @@
+use std::sync::Arc;
 use std::sync::mpsc::Sender;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use rodio::{OutputStream, OutputStreamBuilder};
-use souvlaki::MediaMetadata;
+use log::warn;
 
 use crate::playlist::Playlist;
 use crate::track::Track;
 
+mod decoder;
+pub use decoder::decode_track;
+
+mod error;
+pub use error::PlayerError;
+
 mod mpris;
-use mpris::Mpris;
+use mpris::{MediaSession, Mpris, NullMediaSession};
+
+mod output;
+pub use output::output_devices;
+use output::{AudioBackend, AudioOutput, DeviceBackend};
 
 mod sink;
 use sink::Sink;
+pub use sink::{AbLoop, AutomaticEqualizer, Crossfade, CrossfadeCurve, ReplayGainMode};
+
+#[cfg(feature = "opus")]
+mod opus;
 
 mod source;
+pub use source::{
+    Equalizer, EqualizerBand, EqualizerMode, FilterKind, GRAPHIC_FREQUENCIES, PlaybackSpeed,
+    SpeedMode, TapBuffer,
+};
+
+/// How close to the end of the current track the next one is handed to the sink.
+const PRELOAD_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// How long playback fades down before the sleep timer stops it.
+const SLEEP_FADE: Duration = Duration::from_secs(60);
+
+/// When the sleep timer stops playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepTimer {
+    At(Instant),
+    AfterTrack,
+    /// After the last track of the current album, or the current track if it is a single.
+    AfterAlbum,
+}
 
 pub trait GeneralMusicPlayer {
     fn play_track(&mut self, track: &Track);
@@ -36,8 +69,10 @@ pub trait GeneralMusicPlayer {
 
     fn seek(&mut self, position: Duration);
 
+    /// Whether playback is paused, which is only reported once it has faded out.
     fn is_paused(&self) -> bool;
 
+    /// Whether playback is stopped, which is only reported once it has faded out.
     fn is_stopped(&self) -> bool;
 
     fn set_volume(&mut self, value: f32);
@@ -47,15 +82,57 @@ pub trait GeneralMusicPlayer {
     fn position(&self) -> Duration;
 
     fn current_track(&self) -> Option<&Track>;
+
+    /// Sets the A-B loop of the current track, it is cleared when another track starts.
+    fn set_ab_loop(&mut self, ab_loop: AbLoop);
+
+    fn ab_loop(&self) -> AbLoop;
+
+    /// Sets the playback rate, positions and seeking stay in media time.
+    fn set_playback_speed(&mut self, speed: PlaybackSpeed);
+
+    fn playback_speed(&self) -> PlaybackSpeed;
+
+    /// Stops playback at the given time or track boundary, `None` cancels the timer.
+    fn set_sleep_timer(&mut self, sleep_timer: Option<SleepTimer>);
+
+    fn sleep_timer(&self) -> Option<SleepTimer>;
+
+    /// Time left until the sleep timer stops playback, `None` while it is not known yet.
+    fn sleep_timer_remaining(&self) -> Option<Duration>;
+
+    fn set_crossfade(&mut self, crossfade: Option<Crossfade>);
+
+    /// Sets the length of the volume ramps on pause, resume, stop, seek and skip.
+    fn set_transport_fade(&mut self, fade: Duration);
+
+    fn set_replay_gain_mode(&mut self, mode: ReplayGainMode);
+
+    /// Sets the equalizer applied to every track, `None` disables the equalizer.
+    fn set_equalizer(&mut self, equalizer: Option<Equalizer>);
+
+    fn set_automatic_equalizer(&mut self, automatic_equalizer: AutomaticEqualizer);
+
+    /// Moves playback to the named output device, `None` being the system default.
+    fn set_output_device(&mut self, device: Option<String>);
 }
 
 pub enum MusicPlayerEvent {
     Tick,
 
     PlaybackStarted,
+    /// The current track started again where it was after the output was reopened.
+    PlaybackResumed,
     PlaybackProgress,
+    PlaybackAdvanced,
     PlaybackStopped,
     PlaybackEnded,
+    /// Playback moved to the position, after a seek or a jump of the A-B loop.
+    PlaybackSeeked(Duration),
+    /// Boxed since the failed track makes the error much larger than the other events.
+    PlaybackFailed(Box<PlayerError>),
+
+    OutputDeviceLost,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -68,52 +145,318 @@ pub enum MusicPlayerStatus {
 pub struct MusicPlayer {
     player_tx: Sender<MusicPlayerEvent>,
 
-    #[expect(dead_code)]
-    stream: OutputStream,
+    backend: Box<dyn AudioBackend>,
+    output: Box<dyn AudioOutput>,
+    output_device: Option<String>,
     sink: Sink,
-    mpris: Mpris,
+    media_session: Box<dyn MediaSession>,
     status: MusicPlayerStatus,
 
     playlist: Playlist,
+    queued_track: Option<Track>,
+    crossfade: Option<Crossfade>,
+    sleep_timer: Option<SleepTimer>,
+    /// Played time left until the end of the track when the sleep timer stops after it.
+    sleep_track_remaining: Option<Duration>,
+    /// Tracks that failed to play since the last one that played, to stop skipping at some point.
+    failed_in_row: usize,
 }
 
 impl MusicPlayer {
+    /// Plays to the audio devices and shows in the desktop media controls, either of them falls
+    /// back to a null one if it is not available.
     pub fn new(player_tx: Sender<MusicPlayerEvent>) -> Self {
-        let stream = OutputStreamBuilder::open_default_stream().expect("Audio output stream.");
-        let sink = Sink::new(stream.mixer(), player_tx.clone());
-        let mpris = Mpris::new(player_tx.clone());
+        let media_session: Box<dyn MediaSession> = match Mpris::new(player_tx.clone()) {
+            Ok(mpris) => Box::new(mpris),
+            Err(err) => {
+                warn!("No media controls: {err:?}");
+                Box::new(NullMediaSession)
+            }
+        };
+
+        Self::with_backends(player_tx, Box::new(DeviceBackend), media_session)
+    }
+
+    pub fn with_backends(
+        player_tx: Sender<MusicPlayerEvent>,
+        backend: Box<dyn AudioBackend>,
+        media_session: Box<dyn MediaSession>,
+    ) -> Self {
+        let output = backend.open(None, &player_tx);
+        let sink = Sink::new(output.mixer(), player_tx.clone());
 
         Self {
             player_tx,
 
-            stream,
+            backend,
+            output,
+            output_device: None,
             sink,
-            mpris,
+            media_session,
 
             playlist: Playlist::new(Vec::new()),
+            queued_track: None,
+            crossfade: None,
+            sleep_timer: None,
+            sleep_track_remaining: None,
+            failed_in_row: 0,
             status: MusicPlayerStatus::Stopped,
         }
     }
-}
 
-impl GeneralMusicPlayer for MusicPlayer {
-    fn play_track(&mut self, track: &Track) {
+    fn set_mpris_metadata(&mut self, track: &Track) {
+        self.media_session.set_metadata(track);
+    }
+
+    /// Hand the next playlist track to the sink ahead of time for gapless playback.
+    ///
+    /// Does nothing until the current track is within `PRELOAD_THRESHOLD` of its end, so that
+    /// playlist changes made while playing are still picked up.
+    pub fn preload_next(&mut self) {
+        if self.queued_track.is_some() || self.sink.is_empty() {
+            return;
+        }
+
+        let position = self.position();
+        // NOTE: The threshold is in played time, track positions are in media time.
+        let threshold = (PRELOAD_THRESHOLD
+            + self
+                .crossfade
+                .map(|crossfade| crossfade.duration)
+                .unwrap_or_default())
+        .mul_f32(self.playback_speed().rate);
+
+        if self
+            .current_track()
+            .and_then(|track| track.duration)
+            .is_some_and(|duration| duration.saturating_sub(position) > threshold)
+        {
+            return;
+        }
+
+        let Some(track) = self.playlist.peek_next_track().cloned() else {
+            return;
+        };
+
+        if self.stops_after(&track) {
+            return;
+        }
+
+        // NOTE: Consecutive tracks of the same album are meant to flow into each other.
+        let crossfade = self.crossfade.filter(|_| {
+            !self
+                .current_track()
+                .is_some_and(|current| current.is_followed_by(&track))
+        });
+
+        if let Ok(source) = decode_track(&track) {
+            self.sink.add(source, crossfade, &track);
+            self.queued_track = Some(track);
+        }
+    }
+
+    /// Whether the sleep timer stops playback before `next` would start.
+    fn stops_after(&self, next: &Track) -> bool {
+        match self.sleep_timer {
+            Some(SleepTimer::AfterTrack) => true,
+            Some(SleepTimer::AfterAlbum) => !self
+                .current_track()
+                .is_some_and(|current| current.is_followed_by(next)),
+            Some(SleepTimer::At(_)) | None => false,
+        }
+    }
+
+    /// Whether the sleep timer stops playback at the end of the current track.
+    fn stops_after_current_track(&mut self) -> bool {
+        match self.playlist.peek_next_track().cloned() {
+            Some(next) => self.stops_after(&next),
+            None => matches!(
+                self.sleep_timer,
+                Some(SleepTimer::AfterTrack | SleepTimer::AfterAlbum)
+            ),
+        }
+    }
+
+    /// Fades playback down over the last `SLEEP_FADE` before the sleep timer and stops it once
+    /// the time is up, the track boundaries are handled by `finish_track` and `advance`.
+    pub fn update_sleep_timer(&mut self) {
+        self.sleep_track_remaining = if self.stops_after_current_track() {
+            self.current_track()
+                .and_then(|track| track.duration)
+                .map(|duration| {
+                    self.playback_speed()
+                        .wall_time(duration.saturating_sub(self.position()))
+                })
+        } else {
+            None
+        };
+
+        let remaining = self.sleep_timer_remaining();
+
+        self.sink.set_sleep_gain(remaining.map_or(1.0, |remaining| {
+            (remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32()).min(1.0)
+        }));
+
+        if matches!(self.sleep_timer, Some(SleepTimer::At(_)))
+            && remaining.is_some_and(|remaining| remaining.is_zero())
+        {
+            self.stop();
+        }
+    }
+
+    /// Keeps the media controls, the sleep timer and the next track up to date as playback goes.
+    pub fn update_progress(&mut self) {
+        self.mpris_update_progress();
+        self.update_sleep_timer();
+        self.preload_next();
+    }
+
+    /// Plays the next track once the current one has ended, unless the sleep timer stops here.
+    pub fn finish_track(&mut self) {
+        if self.stops_after_current_track() {
+            self.stop();
+        } else {
+            self.play_next();
+        }
+    }
+
+    /// Plays the next track after `failed` could not be played.
+    ///
+    /// Stops once every track of the playlist has failed in a row, or if the next track is the
+    /// one that failed.
+    pub fn skip_failed_track(&mut self, failed: &Track) {
+        self.failed_in_row += 1;
+
+        if self.failed_in_row < self.playlist.tracks().len()
+            && self
+                .playlist
+                .peek_next_track()
+                .is_some_and(|next| next != failed)
+        {
+            self.play_next();
+        } else {
+            self.failed_in_row = 0;
+            self.stop();
+        }
+    }
+
+    /// Replaces the playlist copies of `track` after it was edited in the library, such as rated,
+    /// and shows the edit in the media controls if it is playing.
+    pub fn update_track(&mut self, track: &Track) {
+        self.playlist.update_track(track);
+
+        if !self.is_stopped() && self.current_track() == Some(track) {
+            self.set_mpris_metadata(track);
+        }
+    }
+
+    /// Samples sent to the output, for visualizers.
+    pub fn tap(&self) -> Arc<TapBuffer> {
+        self.sink.tap()
+    }
+
+    /// Reopens the output device and carries on playing the current track where it was.
+    ///
+    /// Falls back to the default device if the chosen one is gone, and to a null output if
+    /// there is no device at all.
+    pub fn reopen_output(&mut self) {
+        let position = self.position();
+        let paused = self.sink.is_pause_requested();
+        let ab_loop = self.ab_loop();
+        let track = if self.sink.is_empty() {
+            None
+        } else {
+            self.current_track().cloned()
+        };
+
         self.sink.stop();
+        self.queued_track = None;
 
-        if let Ok(file) = std::fs::File::open(track.path.as_path()) {
-            self.mpris.set_metadata(MediaMetadata {
-                album: track.album.as_deref(),
-                title: track.title.as_deref(),
-                artist: track.artist.as_deref(),
-                duration: track.duration,
-                cover_url: None,
-            });
-            self.sink
-                .add(rodio::Decoder::try_from(file).expect("Audio samples."));
-            self.sink.play();
+        // NOTE: Dropping the previous output drops its sounds, even if its device is gone.
+        self.output = self
+            .backend
+            .open(self.output_device.as_deref(), &self.player_tx);
+        self.sink.set_output(self.output.mixer());
 
-            self.status = MusicPlayerStatus::Playing;
+        if let Some(track) = track
+            && self.start_track(&track)
+        {
+            self.seek(position);
+            self.set_ab_loop(ab_loop);
 
+            if paused {
+                self.pause();
+            }
+
+            self.player_tx.send(MusicPlayerEvent::PlaybackResumed).ok();
+        }
+    }
+
+    /// Plays `track` from its start, returns `false` if it failed to play.
+    fn start_track(&mut self, track: &Track) -> bool {
+        self.sink.stop();
+        self.queued_track = None;
+
+        match decode_track(track) {
+            Ok(source) => {
+                self.set_mpris_metadata(track);
+                self.sink.set_ab_loop(AbLoop::default());
+                self.sink.add(source, None, track);
+                self.sink.play();
+
+                self.status = MusicPlayerStatus::Playing;
+                self.failed_in_row = 0;
+                self.update_sleep_timer();
+
+                true
+            }
+            Err(err) => {
+                self.status = MusicPlayerStatus::Stopped;
+
+                self.player_tx
+                    .send(MusicPlayerEvent::PlaybackFailed(Box::new(
+                        PlayerError::Decode(track.clone(), err),
+                    )))
+                    .ok();
+
+                false
+            }
+        }
+    }
+
+    /// Move the playlist along with the sink once it has started the queued track.
+    ///
+    /// Falls back to playing the next track from scratch if the playlist has changed since the
+    /// track was queued.
+    pub fn advance(&mut self) {
+        let queued_track = self.queued_track.take();
+
+        // NOTE: The sleep timer may have been set after the next track was queued.
+        if queued_track
+            .as_ref()
+            .is_some_and(|queued_track| self.stops_after(queued_track))
+        {
+            self.stop();
+            return;
+        }
+
+        match self.playlist.next_track().cloned() {
+            Some(track) if queued_track.as_ref() == Some(&track) => {
+                self.set_mpris_metadata(&track);
+                self.sink.set_ab_loop(AbLoop::default());
+                self.status = MusicPlayerStatus::Playing;
+
+                self.player_tx.send(MusicPlayerEvent::PlaybackStarted).ok();
+            }
+            Some(track) => self.play_track(&track),
+            None => self.stop(),
+        }
+    }
+}
+
+impl GeneralMusicPlayer for MusicPlayer {
+    fn play_track(&mut self, track: &Track) {
+        if self.start_track(track) {
             self.player_tx.send(MusicPlayerEvent::PlaybackStarted).ok();
         }
     }
@@ -172,12 +515,18 @@ impl GeneralMusicPlayer for MusicPlayer {
     #[inline]
     fn stop(&mut self) {
         self.sink.stop();
+        self.queued_track = None;
         self.status = MusicPlayerStatus::Stopped;
+        // NOTE: The sleep gain is kept so that a sleep fade does not jump back up while the stop
+        // fades out, it is reset when the next track starts.
+        self.sleep_timer = None;
+        self.sleep_track_remaining = None;
     }
 
     #[inline]
     fn toggle(&mut self) {
-        if self.is_paused() {
+        // NOTE: Go by the request, the pause only shows once playback has faded out.
+        if self.sink.is_pause_requested() {
             self.play();
         } else {
             self.pause();
@@ -197,12 +546,12 @@ impl GeneralMusicPlayer for MusicPlayer {
 
     #[inline]
     fn is_stopped(&self) -> bool {
-        self.sink.is_empty()
+        self.sink.is_stopped()
     }
 
     #[inline]
     fn set_volume(&mut self, value: f32) {
-        self.mpris.set_volume(value.clamp(0.0, 1.0) as f64);
+        self.media_session.set_volume(value.clamp(0.0, 1.0) as f64);
         self.sink.set_volume(value.clamp(0.0, 1.2));
     }
 
@@ -220,4 +569,476 @@ impl GeneralMusicPlayer for MusicPlayer {
     fn current_track(&self) -> Option<&Track> {
         self.playlist.current_track()
     }
+
+    #[inline]
+    fn set_ab_loop(&mut self, ab_loop: AbLoop) {
+        self.sink.set_ab_loop(ab_loop);
+    }
+
+    #[inline]
+    fn ab_loop(&self) -> AbLoop {
+        self.sink.ab_loop()
+    }
+
+    #[inline]
+    fn set_playback_speed(&mut self, speed: PlaybackSpeed) {
+        self.media_session.set_rate(f64::from(speed.rate));
+        self.sink.set_speed(speed);
+    }
+
+    #[inline]
+    fn playback_speed(&self) -> PlaybackSpeed {
+        self.sink.speed()
+    }
+
+    fn set_sleep_timer(&mut self, sleep_timer: Option<SleepTimer>) {
+        self.sleep_timer = sleep_timer;
+        self.update_sleep_timer();
+    }
+
+    #[inline]
+    fn sleep_timer(&self) -> Option<SleepTimer> {
+        self.sleep_timer
+    }
+
+    fn sleep_timer_remaining(&self) -> Option<Duration> {
+        match self.sleep_timer? {
+            SleepTimer::At(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+            SleepTimer::AfterTrack | SleepTimer::AfterAlbum => self.sleep_track_remaining,
+        }
+    }
+
+    #[inline]
+    fn set_crossfade(&mut self, crossfade: Option<Crossfade>) {
+        self.crossfade = crossfade;
+    }
+
+    #[inline]
+    fn set_transport_fade(&mut self, fade: Duration) {
+        self.sink.set_fade(fade);
+    }
+
+    #[inline]
+    fn set_replay_gain_mode(&mut self, mode: ReplayGainMode) {
+        self.sink.set_replay_gain_mode(mode);
+    }
+
+    #[inline]
+    fn set_equalizer(&mut self, equalizer: Option<Equalizer>) {
+        self.sink.set_equalizer(equalizer);
+    }
+
+    #[inline]
+    fn set_automatic_equalizer(&mut self, automatic_equalizer: AutomaticEqualizer) {
+        self.sink.set_automatic_equalizer(automatic_equalizer);
+    }
+
+    fn set_output_device(&mut self, device: Option<String>) {
+        if self.output_device == device {
+            return;
+        }
+
+        self.output_device = device;
+        self.reopen_output();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::{Duration, Instant};
+
+    use super::output::NullBackend;
+    use super::{
+        AbLoop, GeneralMusicPlayer as _, MusicPlayer, MusicPlayerEvent, NullMediaSession, Playlist,
+        SleepTimer,
+    };
+    use crate::playlist::PlaylistMode;
+    use crate::track::Track;
+
+    /// How many times faster than real time the null output plays.
+    const SPEED: f64 = 20.0;
+
+    /// How long to wait for an event before failing, in real time.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    const SAMPLE_RATE: u32 = 8_000;
+
+    /// Writes a silent mono WAV file to the temporary directory.
+    fn silent_track(name: &str, seconds: u32) -> Track {
+        let path = std::env::temp_dir().join(format!(
+            "ferrum-music-player-{}-{name}.wav",
+            std::process::id()
+        ));
+        let data_len = SAMPLE_RATE * seconds * 2;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16_u32.to_le_bytes());
+        // NOTE: PCM, mono, sample rate, byte rate, block align, bits per sample.
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&1_u16.to_le_bytes());
+        bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        bytes.extend_from_slice(&2_u16.to_le_bytes());
+        bytes.extend_from_slice(&16_u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+
+        fs::write(&path, bytes).expect("Test track written.");
+
+        Track {
+            path,
+            duration: Some(Duration::from_secs(seconds.into())),
+            ..Default::default()
+        }
+    }
+
+    fn player(tracks: Vec<Track>) -> (MusicPlayer, Receiver<MusicPlayerEvent>) {
+        let (player_tx, player_rx) = mpsc::channel();
+        let mut player = MusicPlayer::with_backends(
+            player_tx,
+            Box::new(NullBackend::new(SPEED)),
+            Box::new(NullMediaSession),
+        );
+
+        *player.playlist_mut() = Playlist::new(tracks);
+
+        (player, player_rx)
+    }
+
+    /// Handles events the way the app does until one matches `until`.
+    ///
+    /// Returns `false` if no event matched within `TIMEOUT`.
+    fn run_until(
+        player: &mut MusicPlayer,
+        player_rx: &Receiver<MusicPlayerEvent>,
+        until: impl Fn(&MusicPlayerEvent) -> bool,
+    ) -> bool {
+        let deadline = Instant::now() + TIMEOUT;
+
+        while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+            let Ok(event) = player_rx.recv_timeout(timeout) else {
+                break;
+            };
+            let matched = until(&event);
+
+            match event {
+                MusicPlayerEvent::PlaybackProgress => player.update_progress(),
+                MusicPlayerEvent::PlaybackAdvanced => player.advance(),
+                MusicPlayerEvent::PlaybackEnded => player.finish_track(),
+                MusicPlayerEvent::PlaybackFailed(err) => player.skip_failed_track(err.track()),
+                MusicPlayerEvent::PlaybackSeeked(position) => player.mpris_seeked(position),
+                MusicPlayerEvent::Tick
+                | MusicPlayerEvent::PlaybackStarted
+                | MusicPlayerEvent::PlaybackResumed
+                | MusicPlayerEvent::PlaybackStopped
+                | MusicPlayerEvent::OutputDeviceLost => {}
+            }
+
+            if matched {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn is_started(event: &MusicPlayerEvent) -> bool {
+        matches!(event, MusicPlayerEvent::PlaybackStarted)
+    }
+
+    fn is_ended(event: &MusicPlayerEvent) -> bool {
+        matches!(event, MusicPlayerEvent::PlaybackEnded)
+    }
+
+    fn album_track(name: &str, album: &str, number: &str) -> Track {
+        Track {
+            album: Some(album.to_owned()),
+            track: Some(number.to_owned()),
+            ..silent_track(name, 1)
+        }
+    }
+
+    #[test]
+    fn advances_to_queued_track() {
+        let (mut player, player_rx) = player(vec![
+            silent_track("advance-first", 1),
+            silent_track("advance-second", 1),
+        ]);
+
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "First track should start."
+        );
+        assert!(
+            run_until(&mut player, &player_rx, |event| matches!(
+                event,
+                MusicPlayerEvent::PlaybackAdvanced
+            )),
+            "Queued track should follow without an end of playback."
+        );
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Queued track should be reported as started."
+        );
+        assert_eq!(
+            player.playlist().current_track_index(),
+            1,
+            "Playlist should move along with the sink."
+        );
+    }
+
+    #[test]
+    fn stops_at_end_of_playlist() {
+        let (mut player, player_rx) = player(vec![silent_track("end", 1)]);
+
+        player.playlist_mut().set_mode(PlaylistMode::NoRepeat);
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, |event| matches!(
+                event,
+                MusicPlayerEvent::PlaybackEnded
+            )),
+            "Playback should end with the last track."
+        );
+        assert!(
+            player.is_stopped(),
+            "Player should stop without next track."
+        );
+    }
+
+    #[test]
+    fn stop_is_reported() {
+        let (mut player, player_rx) = player(vec![silent_track("stop", 10)]);
+
+        // NOTE: Nothing gets queued, so only the current track is left to stop.
+        player.playlist_mut().set_mode(PlaylistMode::NoRepeat);
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Track should start."
+        );
+
+        player.stop();
+        assert!(
+            run_until(&mut player, &player_rx, |event| matches!(
+                event,
+                MusicPlayerEvent::PlaybackStopped
+            )),
+            "Stop should be reported."
+        );
+        assert!(player.is_stopped(), "Player should be stopped.");
+    }
+
+    #[test]
+    fn seek_moves_position() {
+        let target = Duration::from_secs(5);
+        let (mut player, player_rx) = player(vec![silent_track("seek", 10)]);
+
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Track should start."
+        );
+
+        player.seek(target);
+        assert!(
+            (0..8).any(|_| {
+                run_until(&mut player, &player_rx, |event| {
+                    matches!(event, MusicPlayerEvent::PlaybackProgress)
+                }) && player.position() >= target
+            }),
+            "Position should move to the seek target."
+        );
+    }
+
+    #[test]
+    fn skips_unplayable_track() {
+        let (mut player, player_rx) = player(vec![
+            Track {
+                path: PathBuf::from("missing.wav"),
+                ..Default::default()
+            },
+            silent_track("skip", 1),
+        ]);
+
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, |event| matches!(
+                event,
+                MusicPlayerEvent::PlaybackFailed(_)
+            )),
+            "Missing file should fail to play."
+        );
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Next track should start instead."
+        );
+        assert_eq!(
+            player.playlist().current_track_index(),
+            1,
+            "Failed track should be skipped."
+        );
+    }
+
+    #[test]
+    fn sleeps_after_track() {
+        let (mut player, player_rx) = player(vec![
+            silent_track("sleep-track-first", 1),
+            silent_track("sleep-track-second", 1),
+        ]);
+
+        player.play();
+        player.set_sleep_timer(Some(SleepTimer::AfterTrack));
+        assert!(
+            run_until(&mut player, &player_rx, is_ended),
+            "Playback should end with the current track."
+        );
+        assert!(player.is_stopped(), "Player should stop after the track.");
+        assert_eq!(
+            player.playlist().current_track_index(),
+            0,
+            "Next track should not be played."
+        );
+    }
+
+    #[test]
+    fn sleeps_after_album() {
+        let (mut player, player_rx) = player(vec![
+            album_track("sleep-album-first", "First", "1"),
+            album_track("sleep-album-second", "First", "2"),
+            album_track("sleep-album-other", "Second", "1"),
+        ]);
+
+        player.play();
+        player.set_sleep_timer(Some(SleepTimer::AfterAlbum));
+        assert!(
+            run_until(&mut player, &player_rx, is_ended),
+            "Playback should end with the album."
+        );
+        assert!(player.is_stopped(), "Player should stop after the album.");
+        assert_eq!(
+            player.playlist().current_track_index(),
+            1,
+            "Album should be played to its last track."
+        );
+    }
+
+    #[test]
+    fn sleeps_at_time() {
+        let (mut player, player_rx) = player(vec![silent_track("sleep-at", 60)]);
+
+        player.play();
+        player.set_sleep_timer(Some(SleepTimer::At(
+            Instant::now() + Duration::from_millis(200),
+        )));
+        assert!(
+            run_until(&mut player, &player_rx, |event| matches!(
+                event,
+                MusicPlayerEvent::PlaybackStopped
+            )),
+            "Playback should stop at the sleep time."
+        );
+        assert!(player.is_stopped(), "Player should be stopped.");
+    }
+
+    #[test]
+    fn fades_before_sleep() {
+        let (mut player, player_rx) = player(vec![silent_track("sleep-fade", 30)]);
+
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Track should start."
+        );
+
+        player.set_sleep_timer(Some(SleepTimer::At(
+            Instant::now() + Duration::from_secs(120),
+        )));
+        assert!(
+            (player.sink.sleep_gain() - 1.0).abs() < f32::EPSILON,
+            "Playback should not fade before the last minute."
+        );
+
+        player.set_sleep_timer(Some(SleepTimer::At(
+            Instant::now() + Duration::from_secs(30),
+        )));
+        assert!(
+            (0.45..=0.5).contains(&player.sink.sleep_gain()),
+            "Playback should be half faded half a minute before the sleep time."
+        );
+
+        // NOTE: The whole 30 s track is left to play, less what played since it started.
+        player.set_sleep_timer(Some(SleepTimer::AfterTrack));
+        assert!(
+            (0.4..=0.5).contains(&player.sink.sleep_gain()),
+            "Playback should fade over the rest of the track."
+        );
+
+        player.set_sleep_timer(None);
+        assert!(
+            (player.sink.sleep_gain() - 1.0).abs() < f32::EPSILON,
+            "Fade should be undone without sleep timer."
+        );
+    }
+
+    #[test]
+    fn resumes_on_reopened_output() {
+        let (mut player, player_rx) = player(vec![silent_track("reopen", 10)]);
+
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Track should start."
+        );
+
+        let started = Cell::new(false);
+        player.set_output_device(Some(String::from("other")));
+        assert!(
+            run_until(&mut player, &player_rx, |event| {
+                started.set(started.get() || is_started(event));
+                matches!(event, MusicPlayerEvent::PlaybackResumed)
+            }),
+            "Moving to another device should resume the track."
+        );
+        assert!(
+            !started.get(),
+            "Resumed track should not be reported as a new start."
+        );
+    }
+
+    #[test]
+    fn reports_seeks_and_loop_jumps() {
+        let (mut player, player_rx) = player(vec![silent_track("seeked", 10)]);
+        let seeked_to = |target: Duration| move |event: &MusicPlayerEvent| matches!(event, MusicPlayerEvent::PlaybackSeeked(position) if *position == target);
+
+        player.play();
+        assert!(
+            run_until(&mut player, &player_rx, is_started),
+            "Track should start."
+        );
+
+        player.seek(Duration::from_secs(5));
+        assert!(
+            run_until(&mut player, &player_rx, seeked_to(Duration::from_secs(5))),
+            "Seek should be reported with its position."
+        );
+
+        player.set_ab_loop(AbLoop {
+            a: Some(Duration::from_secs(1)),
+            b: Some(Duration::from_secs(2)),
+        });
+        assert!(
+            run_until(&mut player, &player_rx, seeked_to(Duration::from_secs(1))),
+            "Jump back to the start of the loop should be reported."
+        );
+    }
 }
@@ -1,61 +1,468 @@
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::time::Duration;
 
 use log::info;
-use souvlaki::{
-    MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition, PlatformConfig,
+use souvlaki::{MediaControlEvent, MediaPlayback, MediaPosition, SeekDirection};
+use zbus::blocking::{Connection, ConnectionBuilder, InterfaceRef};
+use zbus::zvariant::{ObjectPath, Value};
+use zbus::{SignalContext, dbus_interface};
+
+use crate::player::{
+    GeneralMusicPlayer as _, MusicPlayer, MusicPlayerEvent, MusicPlayerStatus, PlaybackSpeed,
 };
+use crate::track::{MAX_RATING, Track};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.org.ferrum.Player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// Id of the track in the metadata, there is no track list so it is always the same.
+const TRACK_ID: &str = "/org/ferrum/Player/CurrentTrack";
+
+/// Media controls integration of the desktop, such as MPRIS.
+pub trait MediaSession: Send {
+    fn try_recv_event(&self) -> Option<MediaControlEvent>;
+
+    fn set_metadata(&mut self, track: &Track);
+
+    fn set_volume(&mut self, volume: f64);
+
+    fn update_progress(&mut self, state: MediaPlayback);
+
+    /// Media time played per second.
+    fn set_rate(&mut self, rate: f64);
+
+    /// Tells that playback jumped to `position` rather than playing up to it.
+    fn seeked(&mut self, position: Duration);
+}
+
+/// Media session that is not shown anywhere and never receives events.
+pub struct NullMediaSession;
+
+impl MediaSession for NullMediaSession {
+    fn try_recv_event(&self) -> Option<MediaControlEvent> {
+        None
+    }
+
+    fn set_metadata(&mut self, _track: &Track) {}
 
-use crate::player::{GeneralMusicPlayer as _, MusicPlayer, MusicPlayerEvent, MusicPlayerStatus};
+    fn set_volume(&mut self, _volume: f64) {}
+
+    fn update_progress(&mut self, _state: MediaPlayback) {}
+
+    fn set_rate(&mut self, _rate: f64) {}
+
+    fn seeked(&mut self, _position: Duration) {}
+}
 
+/// MPRIS service on the session bus.
+///
+/// It is served here rather than by souvlaki, whose metadata only has the title, album, artist,
+/// cover and length, so that the rating of the track is published as well.
 pub(super) struct Mpris {
-    controls: MediaControls,
+    connection: Connection,
     controls_rx: Receiver<MediaControlEvent>,
 }
 
 impl Mpris {
-    pub fn new(player_tx: Sender<MusicPlayerEvent>) -> Self {
-        let mut controls = MediaControls::new(PlatformConfig {
-            dbus_name: "org.ferrum.Player",
-            display_name: "Ferrum Player",
-            hwnd: None,
+    /// # Errors
+    ///
+    /// Returns an error if the media controls are not available, such as without a D-Bus session.
+    pub fn new(player_tx: Sender<MusicPlayerEvent>) -> zbus::Result<Self> {
+        let (controls_tx, controls_rx) = mpsc::sync_channel(32);
+        let events = Events {
+            controls_tx,
+            player_tx,
+        };
+
+        let connection = ConnectionBuilder::session()?
+            .serve_at(
+                OBJECT_PATH,
+                AppInterface {
+                    events: events.clone(),
+                },
+            )?
+            .serve_at(
+                OBJECT_PATH,
+                PlayerInterface {
+                    events,
+                    metadata: Metadata::default(),
+                    playback: MediaPlayback::Stopped,
+                    volume: 1.0,
+                    rate: 1.0,
+                },
+            )?
+            .name(BUS_NAME)?
+            .build()?;
+
+        Ok(Self {
+            connection,
+            controls_rx,
         })
-        .expect("Media controls");
+    }
 
-        let (controls_tx, controls_rx) = mpsc::sync_channel(32);
+    fn player(&self) -> Option<InterfaceRef<PlayerInterface>> {
+        self.connection
+            .object_server()
+            .interface::<_, PlayerInterface>(OBJECT_PATH)
+            .ok()
+    }
+}
+
+impl MediaSession for Mpris {
+    fn try_recv_event(&self) -> Option<MediaControlEvent> {
+        self.controls_rx.try_recv().ok()
+    }
+
+    fn set_metadata(&mut self, track: &Track) {
+        let Some(player) = self.player() else {
+            return;
+        };
+        let mut interface = player.get_mut();
+
+        interface.metadata = Metadata::from(track);
+        zbus::block_on(interface.metadata_changed(player.signal_context())).ok();
+    }
+
+    fn set_volume(&mut self, volume: f64) {
+        let Some(player) = self.player() else {
+            return;
+        };
+        let mut interface = player.get_mut();
+
+        interface.volume = volume;
+        zbus::block_on(interface.volume_changed(player.signal_context())).ok();
+    }
+
+    fn update_progress(&mut self, state: MediaPlayback) {
+        let Some(player) = self.player() else {
+            return;
+        };
+        let mut interface = player.get_mut();
 
-        controls
-            .attach(move |event| {
-                controls_tx.send(event).ok();
-                player_tx.send(MusicPlayerEvent::Tick).ok();
-            })
-            .ok();
+        interface.playback = state;
+        zbus::block_on(interface.playback_status_changed(player.signal_context())).ok();
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        let Some(player) = self.player() else {
+            return;
+        };
+        let mut interface = player.get_mut();
+
+        interface.rate = rate;
+        zbus::block_on(interface.rate_changed(player.signal_context())).ok();
+    }
+
+    fn seeked(&mut self, position: Duration) {
+        let Some(player) = self.player() else {
+            return;
+        };
+        let mut interface = player.get_mut();
+
+        // NOTE: Position is not announced with the other properties, clients only hear of it
+        // through this signal.
+        interface.playback = match interface.playback {
+            MediaPlayback::Playing { .. } => MediaPlayback::Playing {
+                progress: Some(MediaPosition(position)),
+            },
+            MediaPlayback::Paused { .. } => MediaPlayback::Paused {
+                progress: Some(MediaPosition(position)),
+            },
+            MediaPlayback::Stopped => MediaPlayback::Stopped,
+        };
+        zbus::block_on(PlayerInterface::seeked(
+            player.signal_context(),
+            i64::try_from(position.as_micros()).unwrap_or(i64::MAX),
+        ))
+        .ok();
+    }
+}
+
+/// Hands the events of the media controls over to the player.
+#[derive(Clone)]
+struct Events {
+    controls_tx: SyncSender<MediaControlEvent>,
+    player_tx: Sender<MusicPlayerEvent>,
+}
 
+impl Events {
+    fn send(&self, event: MediaControlEvent) {
+        // NOTE: Events are dropped rather than blocking the bus while the player is busy.
+        self.controls_tx.try_send(event).ok();
+        self.player_tx.send(MusicPlayerEvent::Tick).ok();
+    }
+}
+
+/// Track shown in the media controls.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Metadata {
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration: Option<Duration>,
+    rating: Option<u8>,
+}
+
+impl From<&Track> for Metadata {
+    fn from(track: &Track) -> Self {
         Self {
-            controls,
-            controls_rx,
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            duration: track.duration,
+            rating: track.rating,
         }
     }
+}
 
-    pub fn try_recv_event(&self) -> Option<MediaControlEvent> {
-        self.controls_rx.try_recv().ok()
+impl Metadata {
+    /// Metadata as MPRIS puts it, the rating being `xesam:userRating` from 0 to 1.
+    fn to_dict(&self) -> HashMap<&'static str, Value<'_>> {
+        let mut dict = HashMap::new();
+
+        dict.insert(
+            "mpris:trackid",
+            Value::new(ObjectPath::from_str_unchecked(TRACK_ID)),
+        );
+        if let Some(duration) = self.duration {
+            dict.insert(
+                "mpris:length",
+                Value::new(i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)),
+            );
+        }
+        if let Some(title) = self.title.as_deref() {
+            dict.insert("xesam:title", Value::new(title));
+        }
+        if let Some(artist) = self.artist.as_deref() {
+            dict.insert("xesam:artist", Value::new(vec![artist]));
+        }
+        if let Some(album) = self.album.as_deref() {
+            dict.insert("xesam:album", Value::new(album));
+        }
+        if let Some(rating) = self.rating {
+            dict.insert(
+                "xesam:userRating",
+                Value::new(f64::from(rating) / f64::from(MAX_RATING)),
+            );
+        }
+
+        dict
+    }
+}
+
+struct AppInterface {
+    events: Events,
+}
+
+#[expect(
+    clippy::unused_self,
+    reason = "D-Bus methods and properties are methods of the interface"
+)]
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl AppInterface {
+    fn raise(&self) {
+        self.events.send(MediaControlEvent::Raise);
+    }
+
+    fn quit(&self) {
+        self.events.send(MediaControlEvent::Quit);
+    }
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        true
     }
 
-    pub fn set_metadata(&mut self, metadata: MediaMetadata<'_>) {
-        self.controls.set_metadata(metadata).ok();
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        true
     }
 
-    pub fn set_volume(&mut self, volume: f64) {
-        self.controls.set_volume(volume).ok();
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
     }
 
-    pub fn update_progress(&mut self, state: MediaPlayback) {
-        self.controls.set_playback(state).ok();
+    #[dbus_interface(property)]
+    fn identity(&self) -> &'static str {
+        "Ferrum Player"
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct PlayerInterface {
+    events: Events,
+    metadata: Metadata,
+    playback: MediaPlayback,
+    volume: f64,
+    rate: f64,
+}
+
+#[expect(
+    clippy::unused_self,
+    reason = "D-Bus methods and properties are methods of the interface"
+)]
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+    fn next(&self) {
+        self.events.send(MediaControlEvent::Next);
+    }
+
+    fn previous(&self) {
+        self.events.send(MediaControlEvent::Previous);
+    }
+
+    fn pause(&self) {
+        self.events.send(MediaControlEvent::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.events.send(MediaControlEvent::Toggle);
+    }
+
+    fn stop(&self) {
+        self.events.send(MediaControlEvent::Stop);
+    }
+
+    fn play(&self) {
+        self.events.send(MediaControlEvent::Play);
+    }
+
+    /// Seeks by `offset` microseconds.
+    fn seek(&self, offset: i64) {
+        let direction = if offset < 0 {
+            SeekDirection::Backward
+        } else {
+            SeekDirection::Forward
+        };
+
+        self.events.send(MediaControlEvent::SeekBy(
+            direction,
+            Duration::from_micros(offset.unsigned_abs()),
+        ));
+    }
+
+    /// Seeks to `position` microseconds, positions past the end of the track are ignored.
+    fn set_position(&self, _track_id: ObjectPath<'_>, position: i64) {
+        let Ok(position) = u64::try_from(position).map(Duration::from_micros) else {
+            return;
+        };
+
+        if self
+            .metadata
+            .duration
+            .is_none_or(|duration| position <= duration)
+        {
+            self.events
+                .send(MediaControlEvent::SetPosition(MediaPosition(position)));
+        }
+    }
+
+    fn open_uri(&self, uri: String) {
+        self.events.send(MediaControlEvent::OpenUri(uri));
+    }
+
+    /// Position in microseconds that playback jumped to.
+    #[dbus_interface(signal)]
+    async fn seeked(context: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &'static str {
+        match self.playback {
+            MediaPlayback::Playing { .. } => "Playing",
+            MediaPlayback::Paused { .. } => "Paused",
+            MediaPlayback::Stopped => "Stopped",
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<&str, Value<'_>> {
+        self.metadata.to_dict()
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    #[dbus_interface(property)]
+    fn set_volume(&self, volume: f64) {
+        self.events.send(MediaControlEvent::SetVolume(volume));
+    }
+
+    /// Position in microseconds.
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        match self.playback {
+            MediaPlayback::Playing {
+                progress: Some(MediaPosition(position)),
+            }
+            | MediaPlayback::Paused {
+                progress: Some(MediaPosition(position)),
+            } => i64::try_from(position.as_micros()).unwrap_or(i64::MAX),
+            _ => 0,
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        f64::from(PlaybackSpeed::MIN_RATE)
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        f64::from(PlaybackSpeed::MAX_RATE)
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
     }
 }
 
 impl MusicPlayer {
     pub fn mpris_event(&self) -> Option<MediaControlEvent> {
-        self.mpris.try_recv_event()
+        self.media_session.try_recv_event()
     }
 
     pub fn mpris_handle(&mut self, event: &MediaControlEvent) {
@@ -68,18 +475,30 @@ impl MusicPlayer {
             MediaControlEvent::Toggle => self.toggle(),
             MediaControlEvent::Stop => self.stop(),
             MediaControlEvent::SetPosition(MediaPosition(value)) => self.seek(*value),
+            MediaControlEvent::SeekBy(direction, offset) => {
+                let position = self.position();
+
+                self.seek(match direction {
+                    SeekDirection::Forward => position + *offset,
+                    SeekDirection::Backward => position.saturating_sub(*offset),
+                });
+            }
             _ => {
                 info!("MPRIS event received but not implemented.");
             }
         }
 
-        if self.is_stopped() {
+        if self.sink.is_empty() {
             self.status = MusicPlayerStatus::Stopped;
         }
     }
 
+    pub fn mpris_seeked(&mut self, position: Duration) {
+        self.media_session.seeked(position);
+    }
+
     pub fn mpris_update_progress(&mut self) {
-        self.mpris.update_progress(match self.status {
+        self.media_session.update_progress(match self.status {
             MusicPlayerStatus::Playing => MediaPlayback::Playing {
                 progress: Some(MediaPosition(self.position())),
             },
@@ -90,3 +509,41 @@ impl MusicPlayer {
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use zbus::zvariant::Value;
+
+    use super::Metadata;
+    use crate::track::Track;
+
+    #[test]
+    fn publishes_rating() {
+        let mut track = Track {
+            title: Some("Title".to_owned()),
+            rating: Some(4),
+            ..Default::default()
+        };
+
+        let metadata = Metadata::from(&track);
+        let dict = metadata.to_dict();
+        assert_eq!(
+            dict.get("xesam:userRating"),
+            Some(&Value::F64(0.8)),
+            "Rating should be published from 0 to 1."
+        );
+        assert_eq!(
+            dict.get("xesam:title"),
+            Some(&Value::from("Title")),
+            "Title should be published."
+        );
+
+        track.rating = None;
+        assert!(
+            !Metadata::from(&track)
+                .to_dict()
+                .contains_key("xesam:userRating"),
+            "Unrated track should have no rating."
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use audiopus::coder::Decoder;
+use audiopus::packet::{Packet, nb_samples};
+use audiopus::{Channels, MutSignals, SampleRate as OpusSampleRate};
+use ogg::PacketReader;
+use rodio::decoder::DecoderError;
+use rodio::source::SeekError;
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+/// Opus streams are always decoded at 48 kHz.
+const SAMPLE_RATE: SampleRate = 48_000;
+
+/// Samples per channel of the longest possible Opus packet (120 ms).
+const MAX_FRAME_LEN: usize = 5_760;
+
+/// Decoding starts this many samples before a seek target so that the decoder has converged
+/// by then (RFC 7845, section 4.6).
+const SEEK_PRE_ROLL: u64 = 3_840;
+
+/// How far from the end of the file the last page is searched for.
+const LAST_PAGE_SEARCH_LEN: i64 = 64 * 1024;
+
+/// Decoder for Ogg Opus files, symphonia has no Opus support yet.
+///
+/// Only mono and stereo streams (channel mapping family 0) are supported.
+pub struct OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    reader: PacketReader<R>,
+    decoder: Decoder,
+    serial: u32,
+    channels: ChannelCount,
+    output_gain: i32,
+    pre_skip: u64,
+    total_duration: Option<Duration>,
+
+    /// Packets read ahead while seeking, decoded before reading further.
+    pending: VecDeque<ogg::Packet>,
+    /// Granule position of the next decoded sample.
+    granule: u64,
+    /// Samples per channel still to drop, for the pre-skip and after seeking.
+    skip: u64,
+    buffer: Vec<Sample>,
+    buffer_position: usize,
+}
+
+impl<R> OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    /// Reads the Opus headers and the length of the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DecoderError::UnrecognizedFormat` if the data is not a supported Opus stream.
+    pub fn new(data: R) -> Result<Self, DecoderError> {
+        let mut reader = PacketReader::new(data);
+
+        let head = reader
+            .read_packet()
+            .map_err(|err| DecoderError::IoError(err.to_string()))?
+            .ok_or(DecoderError::UnrecognizedFormat)?;
+        let header = OpusHeader::parse(&head.data).ok_or(DecoderError::UnrecognizedFormat)?;
+        let serial = head.stream_serial();
+
+        let total_duration = last_granule(&mut reader, serial).map(|granule| {
+            Duration::from_secs_f64(
+                granule.saturating_sub(header.pre_skip) as f64 / f64::from(SAMPLE_RATE),
+            )
+        });
+
+        // NOTE: Back to the start, past the identification and comment headers.
+        reader
+            .seek_bytes(SeekFrom::Start(0))
+            .map_err(|err| DecoderError::IoError(err.to_string()))?;
+        for _ in 0..2 {
+            reader
+                .read_packet()
+                .map_err(|err| DecoderError::IoError(err.to_string()))?;
+        }
+
+        Ok(Self {
+            reader,
+            decoder: new_decoder(header.channels, header.output_gain)?,
+            serial,
+            channels: header.channels,
+            output_gain: header.output_gain,
+            pre_skip: header.pre_skip,
+            total_duration,
+
+            pending: VecDeque::new(),
+            granule: 0,
+            skip: header.pre_skip,
+            buffer: Vec::new(),
+            buffer_position: 0,
+        })
+    }
+
+    /// Decodes the next packet into the buffer, returns `false` at the end of the stream.
+    fn decode_packet(&mut self) -> bool {
+        let packet = match self.pending.pop_front() {
+            Some(packet) => packet,
+            None => match self.reader.read_packet() {
+                Ok(Some(packet)) => packet,
+                _ => return false,
+            },
+        };
+
+        self.buffer.clear();
+        self.buffer_position = 0;
+
+        if packet.stream_serial() != self.serial {
+            return true;
+        }
+
+        let channels = usize::from(self.channels);
+        self.buffer.resize(MAX_FRAME_LEN * channels, 0.0);
+
+        let decoded = Packet::try_from(packet.data.as_slice()).and_then(|input| {
+            self.decoder.decode_float(
+                Some(input),
+                MutSignals::try_from(self.buffer.as_mut_slice())?,
+                false,
+            )
+        });
+        let Ok(mut len) = decoded.map(|len| len as u64) else {
+            // NOTE: Skip corrupt packets instead of ending playback.
+            self.buffer.clear();
+            return true;
+        };
+
+        // NOTE: The granule position of the last page marks where the audio really ends.
+        if packet.last_in_stream() {
+            len = len.min(packet.absgp_page().saturating_sub(self.granule));
+        }
+
+        let skip = self.skip.min(len);
+        self.skip -= skip;
+        self.granule += len;
+
+        self.buffer.truncate(len as usize * channels);
+        self.buffer_position = skip as usize * channels;
+
+        true
+    }
+}
+
+impl<R> Iterator for OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        loop {
+            if let Some(sample) = self.buffer.get(self.buffer_position) {
+                self.buffer_position += 1;
+                return Some(*sample);
+            }
+
+            if !self.decode_packet() {
+                return None;
+            }
+        }
+    }
+}
+
+impl<R> Source for OpusDecoder<R>
+where
+    R: Read + Seek,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        SAMPLE_RATE
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let target = (pos.as_secs_f64() * f64::from(SAMPLE_RATE)) as u64 + self.pre_skip;
+
+        self.reader
+            .seek_absgp(Some(self.serial), target.saturating_sub(SEEK_PRE_ROLL))
+            .map_err(|err| SeekError::Other(Box::new(err)))?;
+
+        // NOTE: Pages only store the granule position of their end, read the first page ahead
+        // to find out where it starts.
+        self.pending.clear();
+        let mut page_len = 0;
+        let mut page_end = None;
+
+        while page_end.is_none() {
+            let Some(packet) = self
+                .reader
+                .read_packet()
+                .map_err(|err| SeekError::Other(Box::new(err)))?
+            else {
+                break;
+            };
+
+            if packet.stream_serial() != self.serial {
+                continue;
+            }
+
+            page_len += Packet::try_from(packet.data.as_slice())
+                .and_then(|input| nb_samples(input, OpusSampleRate::Hz48000))
+                .map_or(0, |len| len as u64);
+            if packet.last_in_page() {
+                page_end = Some(packet.absgp_page());
+            }
+
+            self.pending.push_back(packet);
+        }
+
+        self.decoder = new_decoder(self.channels, self.output_gain)
+            .map_err(|err| SeekError::Other(Box::new(err)))?;
+        self.granule = page_end.map_or(target, |end| end.saturating_sub(page_len));
+        self.skip = target.saturating_sub(self.granule);
+        self.buffer.clear();
+        self.buffer_position = 0;
+
+        Ok(())
+    }
+}
+
+/// Fields of the identification header used for decoding.
+struct OpusHeader {
+    channels: ChannelCount,
+    pre_skip: u64,
+    /// Gain in Q7.8 dB applied to the decoded samples.
+    output_gain: i32,
+}
+
+impl OpusHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if !data.starts_with(b"OpusHead") {
+            return None;
+        }
+
+        let channels = ChannelCount::from(*data.get(9)?);
+        let mapping_family = *data.get(18)?;
+
+        if !(1..=2).contains(&channels) || mapping_family != 0 {
+            return None;
+        }
+
+        Some(Self {
+            channels,
+            pre_skip: u64::from(u16::from_le_bytes(data.get(10..12)?.try_into().ok()?)),
+            output_gain: i32::from(i16::from_le_bytes(data.get(16..18)?.try_into().ok()?)),
+        })
+    }
+}
+
+fn new_decoder(channels: ChannelCount, output_gain: i32) -> Result<Decoder, DecoderError> {
+    let decoder = Decoder::new(
+        OpusSampleRate::Hz48000,
+        if channels == 1 {
+            Channels::Mono
+        } else {
+            Channels::Stereo
+        },
+    )
+    .map_err(|_err| DecoderError::UnrecognizedFormat)?;
+
+    decoder
+        .set_gain(output_gain)
+        .map_err(|_err| DecoderError::UnrecognizedFormat)?;
+
+    Ok(decoder)
+}
+
+/// Returns the granule position of the last page of the stream, which is its length.
+fn last_granule<R>(reader: &mut PacketReader<R>, serial: u32) -> Option<u64>
+where
+    R: Read + Seek,
+{
+    reader
+        .seek_bytes(SeekFrom::End(-LAST_PAGE_SEARCH_LEN))
+        .or_else(|_err| reader.seek_bytes(SeekFrom::Start(0)))
+        .ok()?;
+
+    let mut granule = None;
+    while let Ok(Some(packet)) = reader.read_packet() {
+        // NOTE: Pages without a packet ending there have no granule position.
+        if packet.stream_serial() == serial && packet.absgp_page() != u64::MAX {
+            granule = Some(packet.absgp_page());
+        }
+    }
+
+    granule
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    use audiopus::coder::Encoder;
+    use audiopus::{Application, Channels, SampleRate as OpusSampleRate};
+    use ogg::{PacketWriteEndInfo, PacketWriter};
+    use rodio::Source as _;
+
+    use super::{OpusDecoder, SAMPLE_RATE};
+
+    const SERIAL: u32 = 1;
+    const PRE_SKIP: u64 = 312;
+    /// Samples per channel of the 20 ms packets of the test stream.
+    const FRAME_LEN: usize = 960;
+    const FRAMES: usize = 100;
+    /// Samples left out of the last packet by the granule position of the last page.
+    const TRIMMED: u64 = 500;
+    const LEN: u64 = (FRAMES * FRAME_LEN) as u64 - TRIMMED;
+
+    /// Mono stream of 2 s, silent for the first second and a 1 kHz sine for the next.
+    fn stream() -> Cursor<Vec<u8>> {
+        let encoder = Encoder::new(OpusSampleRate::Hz48000, Channels::Mono, Application::Audio)
+            .expect("Encoder created.");
+        let mut writer = PacketWriter::new(Cursor::new(Vec::new()));
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(1);
+        head.extend_from_slice(
+            &u16::try_from(PRE_SKIP)
+                .expect("Pre-skip fits.")
+                .to_le_bytes(),
+        );
+        head.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0_i16.to_le_bytes());
+        head.push(0);
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&0_u32.to_le_bytes());
+        tags.extend_from_slice(&0_u32.to_le_bytes());
+        for header in [head, tags] {
+            writer
+                .write_packet(
+                    header.into_boxed_slice(),
+                    SERIAL,
+                    PacketWriteEndInfo::EndPage,
+                    0,
+                )
+                .expect("Header written.");
+        }
+
+        for frame in 0..FRAMES {
+            let input: Vec<f32> = (0..FRAME_LEN)
+                .map(|index| {
+                    let time = (frame * FRAME_LEN + index) as f32 / SAMPLE_RATE as f32;
+                    if time < 1.0 {
+                        0.0
+                    } else {
+                        0.5 * (2.0 * PI * 1_000.0 * time).sin()
+                    }
+                })
+                .collect();
+            let mut output = vec![0; 4_000];
+            let len = encoder
+                .encode_float(&input, &mut output)
+                .expect("Frame encoded.");
+            output.truncate(len);
+
+            let (end, granule) = if frame + 1 == FRAMES {
+                (PacketWriteEndInfo::EndStream, PRE_SKIP + LEN)
+            } else if (frame + 1) % 10 == 0 {
+                (
+                    PacketWriteEndInfo::EndPage,
+                    PRE_SKIP + ((frame + 1) * FRAME_LEN) as u64,
+                )
+            } else {
+                (
+                    PacketWriteEndInfo::NormalPacket,
+                    PRE_SKIP + ((frame + 1) * FRAME_LEN) as u64,
+                )
+            };
+            writer
+                .write_packet(output.into_boxed_slice(), SERIAL, end, granule)
+                .expect("Packet written.");
+        }
+
+        let mut data = writer.into_inner();
+        data.set_position(0);
+
+        data
+    }
+
+    fn rms(decoder: &mut OpusDecoder<Cursor<Vec<u8>>>, len: usize) -> f32 {
+        (decoder.take(len).map(|sample| sample * sample).sum::<f32>() / len as f32).sqrt()
+    }
+
+    #[test]
+    fn drops_pre_skip_and_trims_end() {
+        let decoder = OpusDecoder::new(stream()).expect("Stream opened.");
+
+        assert_eq!(
+            decoder.total_duration(),
+            Some(Duration::from_secs_f64(LEN as f64 / f64::from(SAMPLE_RATE))),
+            "Length should leave out the pre-skip."
+        );
+        assert_eq!(
+            decoder.count() as u64,
+            LEN,
+            "Pre-skip and samples past the last granule position should be dropped."
+        );
+    }
+
+    #[test]
+    fn seeks_to_sample() {
+        let mut decoder = OpusDecoder::new(stream()).expect("Stream opened.");
+
+        decoder
+            .try_seek(Duration::from_millis(1_500))
+            .expect("Seeked forward.");
+        assert!(
+            rms(&mut decoder, FRAME_LEN) > 0.2,
+            "Sine should be heard right after seeking into it."
+        );
+
+        decoder
+            .try_seek(Duration::from_millis(500))
+            .expect("Seeked backward.");
+        assert!(
+            rms(&mut decoder, FRAME_LEN) < 0.01,
+            "Silence should be heard right after seeking into it."
+        );
+        assert_eq!(
+            decoder.count() as u64,
+            LEN - u64::from(SAMPLE_RATE) / 2 - FRAME_LEN as u64,
+            "Seeking should land on the exact sample."
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use rodio::cpal::traits::HostTrait as _;
+use rodio::mixer::Mixer;
+use rodio::{ChannelCount, DeviceTrait as _, OutputStream, OutputStreamBuilder, SampleRate};
+use rodio::{StreamError, cpal};
+
+use super::MusicPlayerEvent;
+
+const NULL_CHANNELS: ChannelCount = 2;
+const NULL_SAMPLE_RATE: SampleRate = 44_100;
+
+/// Returns the names of the output devices of the default host.
+pub fn output_devices() -> Vec<String> {
+    cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Opens the outputs the player sends its samples to.
+pub trait AudioBackend: Send {
+    /// Opens the named output device, `None` being the default one.
+    ///
+    /// `MusicPlayerEvent::OutputDeviceLost` is sent if the output goes away later on.
+    fn open(
+        &self,
+        device: Option<&str>,
+        player_tx: &Sender<MusicPlayerEvent>,
+    ) -> Box<dyn AudioOutput>;
+}
+
+/// An opened output, which plays the sounds added to its mixer until it is dropped.
+pub trait AudioOutput: Send {
+    fn mixer(&self) -> &Mixer;
+}
+
+/// Plays to the devices of the default host.
+pub struct DeviceBackend;
+
+impl AudioBackend for DeviceBackend {
+    /// Opens the named output device, or the default one if it is `None` or cannot be found.
+    ///
+    /// Falls back to a null output when no device can be opened at all.
+    fn open(
+        &self,
+        device: Option<&str>,
+        player_tx: &Sender<MusicPlayerEvent>,
+    ) -> Box<dyn AudioOutput> {
+        let host = cpal::default_host();
+        let device = device
+            .and_then(|name| {
+                host.output_devices()
+                    .ok()?
+                    .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+            })
+            .or_else(|| host.default_output_device());
+
+        let stream = device.ok_or(StreamError::NoDevice).and_then(|device| {
+            let player_tx = player_tx.clone();
+
+            OutputStreamBuilder::from_device(device)?
+                .with_error_callback(move |err| {
+                    if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                        player_tx.send(MusicPlayerEvent::OutputDeviceLost).ok();
+                    }
+                })
+                .open_stream_or_fallback()
+        });
+
+        match stream {
+            Ok(mut stream) => {
+                stream.log_on_drop(false);
+                Box::new(stream)
+            }
+            Err(err) => {
+                warn!("No audio output device, playing to a null output: {err:?}");
+                NullBackend::default().open(None, player_tx)
+            }
+        }
+    }
+}
+
+impl AudioOutput for OutputStream {
+    fn mixer(&self) -> &Mixer {
+        Self::mixer(self)
+    }
+}
+
+/// Plays to a null output, for machines without any audio device and for tests.
+pub struct NullBackend {
+    /// How many times faster than real time samples are consumed.
+    speed: f64,
+}
+
+impl NullBackend {
+    pub fn new(speed: f64) -> Self {
+        Self { speed }
+    }
+}
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn open(
+        &self,
+        _device: Option<&str>,
+        _player_tx: &Sender<MusicPlayerEvent>,
+    ) -> Box<dyn AudioOutput> {
+        Box::new(NullOutput::new(self.speed))
+    }
+}
+
+/// Consumes samples at a steady pace without playing them.
+struct NullOutput {
+    mixer: Mixer,
+    stopped: Arc<AtomicBool>,
+}
+
+impl NullOutput {
+    fn new(speed: f64) -> Self {
+        let (mixer, mut source) = rodio::mixer::mixer(NULL_CHANNELS, NULL_SAMPLE_RATE);
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        thread::spawn({
+            let stopped = stopped.clone();
+
+            move || {
+                let start = Instant::now();
+                let rate = f64::from(NULL_SAMPLE_RATE) * f64::from(NULL_CHANNELS) * speed;
+                let mut played = 0_u64;
+
+                while !stopped.load(Ordering::Relaxed) {
+                    let due = (start.elapsed().as_secs_f64() * rate) as u64;
+
+                    while played < due {
+                        source.next();
+                        played += 1;
+                    }
+
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+        });
+
+        Self { mixer, stopped }
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+}
+
+impl Drop for NullOutput {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
@@ -1,23 +1,394 @@
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::FRAC_PI_2;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
 use std::time::Duration;
 
 use log::warn;
 use parking_lot::Mutex;
-use rodio::Source;
 use rodio::mixer::Mixer;
-use rodio::queue;
+use rodio::source::{SeekError, Zero};
+use rodio::{ChannelCount, Sample, SampleRate, Source};
 
 use super::MusicPlayerEvent;
-use super::source::DoneCallback;
+use super::source::{
+    DoneCallback, Equalize, Equalizer, PlaybackSpeed, Ramp, Speed, Tap, TapBuffer,
+};
+use crate::track::{ReplayGain, Track};
+
+/// Number of samples played between checks for the start of a crossfade.
+const CROSSFADE_CHECK_INTERVAL: usize = 512;
+
+/// Number of samples of silence played while the queue is empty.
+const SILENCE_LENGTH: usize = 512;
+
+/// Shape of the volume curves used while two tracks overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossfadeCurve {
+    Linear,
+    EqualPower,
+}
+
+impl CrossfadeCurve {
+    /// Gains of the outgoing and the incoming sound at `progress` (`0.0` to `1.0`) of the fade.
+    fn gains(self, progress: f32) -> (f32, f32) {
+        match self {
+            Self::Linear => (1.0 - progress, progress),
+            Self::EqualPower => ((progress * FRAC_PI_2).cos(), (progress * FRAC_PI_2).sin()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crossfade {
+    pub duration: Duration,
+    pub curve: CrossfadeCurve,
+}
+
+/// Which replay gain values are used to normalize the loudness of tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    /// Amplitude factor for `replay_gain`, lowered if needed so that the peak does not clip.
+    ///
+    /// Falls back to the values of the other mode when the preferred ones are missing.
+    fn factor(self, replay_gain: &ReplayGain) -> f32 {
+        let track = replay_gain
+            .track_gain
+            .map(|gain| (gain, replay_gain.track_peak));
+        let album = replay_gain
+            .album_gain
+            .map(|gain| (gain, replay_gain.album_peak));
+
+        let Some((gain, peak)) = (match self {
+            Self::Off => None,
+            Self::Track => track.or(album),
+            Self::Album => album.or(track),
+        }) else {
+            return 1.0;
+        };
+
+        let factor = 10_f32.powf(gain / 20.0);
+
+        peak.filter(|peak| *peak > 0.0)
+            .map_or(factor, |peak| factor.min(1.0 / peak))
+    }
+}
+
+/// Points of the A-B loop, playback jumps back to `a` whenever it passes `b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AbLoop {
+    pub a: Option<Duration>,
+    pub b: Option<Duration>,
+}
+
+impl AbLoop {
+    /// Both points, `None` until they are set and in order.
+    pub fn range(self) -> Option<(Duration, Duration)> {
+        let (a, b) = (self.a?, self.b?);
+
+        (a < b).then_some((a, b))
+    }
+}
+
+/// Equalizer presets picked automatically for specific tracks or genres.
+#[derive(Debug, Clone, Default)]
+pub struct AutomaticEqualizer {
+    pub tracks: HashMap<PathBuf, Equalizer>,
+    /// Keyed by lowercase genre.
+    pub genres: HashMap<String, Equalizer>,
+}
+
+impl AutomaticEqualizer {
+    /// Track presets take precedence over genre presets.
+    fn get(&self, path: &Path, genre: Option<&str>) -> Option<&Equalizer> {
+        self.tracks.get(path).or_else(|| self.genres.get(genre?))
+    }
+}
 
 struct Controls {
+    /// Requested pause, playback is only `paused` once it has faded out.
     pause: AtomicBool,
+    paused: AtomicBool,
     stopped: AtomicBool,
+    /// Sounds with a lower id have been stopped, they fade out and are skipped while the ones
+    /// added since play.
+    stopped_before: AtomicUsize,
+    pending: AtomicUsize,
+    /// Id of the sound that reports position and takes seek requests.
+    active: AtomicUsize,
+    next_id: AtomicUsize,
     volume: Mutex<f32>,
+    /// Lowered while the sleep timer fades playback down.
+    sleep_gain: Mutex<f32>,
+    replay_gain: Mutex<ReplayGainMode>,
+    /// `None` while the equalizer is disabled.
+    equalizer: Mutex<Option<Equalizer>>,
+    automatic_equalizer: Mutex<AutomaticEqualizer>,
     position: Mutex<Duration>,
     seek: Mutex<Option<Duration>>,
+    ab_loop: Mutex<AbLoop>,
+    speed: Mutex<PlaybackSpeed>,
+    /// Length of the volume ramps on pause, resume, stop and seek.
+    fade: Mutex<Duration>,
+}
+
+struct Sound {
+    source: Box<dyn Source + Send>,
+    /// Time left to play, kept up to date by the source itself.
+    remaining: Arc<Mutex<Option<Duration>>>,
+    /// Crossfade from the previous sound into this one.
+    crossfade: Option<Crossfade>,
+}
+
+impl Sound {
+    fn silence() -> Self {
+        Self {
+            source: Box::new(Zero::new_samples(1, 44100, SILENCE_LENGTH)),
+            remaining: Arc::default(),
+            crossfade: None,
+        }
+    }
+}
+
+struct Fade {
+    outgoing: Sound,
+    curve: CrossfadeCurve,
+    elapsed: usize,
+    length: usize,
+}
+
+/// The input of the queue, sounds appended here are played one after the other.
+struct QueueInput {
+    next_sounds: Mutex<VecDeque<Sound>>,
+    keep_alive_if_empty: AtomicBool,
+}
+
+impl QueueInput {
+    /// Adds a sound to the end of the queue.
+    fn append<S>(
+        &self,
+        source: S,
+        remaining: Arc<Mutex<Option<Duration>>>,
+        crossfade: Option<Crossfade>,
+    ) where
+        S: Source + Send + 'static,
+    {
+        self.next_sounds.lock().push_back(Sound {
+            source: Box::new(source),
+            remaining,
+            crossfade,
+        });
+    }
+
+    fn set_keep_alive_if_empty(&self, keep_alive_if_empty: bool) {
+        self.keep_alive_if_empty
+            .store(keep_alive_if_empty, Ordering::Release);
+    }
+
+    /// Removes all the sounds that have not started yet.
+    fn clear(&self) {
+        // NOTE: Drop outside of the lock, sounds report their end when dropped.
+        let sounds = std::mem::take(&mut *self.next_sounds.lock());
+
+        drop(sounds);
+    }
+}
+
+/// The output of the queue.
+///
+/// Unlike `rodio::queue` this mixes the end of the current sound with the start of the next one
+/// when the next one asks for a crossfade.
+struct QueueOutput {
+    current: Sound,
+    fade: Option<Fade>,
+    samples_played: usize,
+
+    input: Arc<QueueInput>,
+}
+
+fn queue(keep_alive_if_empty: bool) -> (Arc<QueueInput>, QueueOutput) {
+    let input = Arc::new(QueueInput {
+        next_sounds: Mutex::new(VecDeque::new()),
+        keep_alive_if_empty: AtomicBool::new(keep_alive_if_empty),
+    });
+
+    let output = QueueOutput {
+        current: Sound::silence(),
+        fade: None,
+        samples_played: 0,
+
+        input: input.clone(),
+    };
+
+    (input, output)
+}
+
+impl QueueOutput {
+    /// Starts mixing the next sound in if the current one is about to end.
+    ///
+    /// Both sounds must share the same format since they are mixed sample by sample, otherwise
+    /// the next sound simply follows the current one.
+    fn try_start_crossfade(&mut self) {
+        let channels = self.current.source.channels();
+        let sample_rate = self.current.source.sample_rate();
+
+        if self.fade.is_some() || !self.samples_played.is_multiple_of(usize::from(channels)) {
+            return;
+        }
+
+        let Some(remaining) = *self.current.remaining.lock() else {
+            return;
+        };
+
+        let mut next_sounds = self.input.next_sounds.lock();
+
+        let Some(crossfade) = next_sounds.front().and_then(|next| {
+            next.crossfade.filter(|_| {
+                next.source.channels() == channels && next.source.sample_rate() == sample_rate
+            })
+        }) else {
+            return;
+        };
+
+        let length =
+            (remaining.as_secs_f64() * f64::from(sample_rate)) as usize * usize::from(channels);
+
+        if remaining > crossfade.duration || length == 0 {
+            return;
+        }
+
+        let Some(next) = next_sounds.pop_front() else {
+            return;
+        };
+
+        drop(next_sounds);
+
+        self.fade = Some(Fade {
+            outgoing: std::mem::replace(&mut self.current, next),
+            curve: crossfade.curve,
+            elapsed: 0,
+            length,
+        });
+        self.samples_played = 0;
+    }
+
+    /// Called when `current` is empty, returns `false` if the queue should stop.
+    fn go_next(&mut self) -> bool {
+        let next = self.input.next_sounds.lock().pop_front();
+
+        self.current = match next {
+            Some(next) => next,
+            None if self.input.keep_alive_if_empty.load(Ordering::Acquire) => Sound::silence(),
+            None => return false,
+        };
+        self.samples_played = 0;
+
+        true
+    }
+}
+
+impl Iterator for QueueOutput {
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        loop {
+            if let Some(fade) = self.fade.as_mut() {
+                let incoming = self.current.source.next();
+                let outgoing = fade.outgoing.source.next();
+                let (outgoing_gain, incoming_gain) =
+                    fade.curve.gains(fade.elapsed as f32 / fade.length as f32);
+
+                fade.elapsed += 1;
+
+                if outgoing.is_none() || fade.elapsed >= fade.length {
+                    // NOTE: Dropping the outgoing sound reports its end even when it is cut short.
+                    self.fade = None;
+                }
+
+                if incoming.is_some() || outgoing.is_some() {
+                    self.samples_played += 1;
+
+                    return Some(
+                        outgoing.unwrap_or_default() * outgoing_gain
+                            + incoming.unwrap_or_default() * incoming_gain,
+                    );
+                }
+            }
+
+            if let Some(sample) = self.current.source.next() {
+                self.samples_played += 1;
+
+                if self.samples_played.is_multiple_of(CROSSFADE_CHECK_INTERVAL) {
+                    self.try_start_crossfade();
+                }
+
+                return Some(sample);
+            }
+
+            if !self.go_next() {
+                return None;
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.current.source.size_hint().0, None)
+    }
+}
+
+impl Source for QueueOutput {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        // NOTE: Same as `rodio::queue`, the boundary between two sounds must be a span boundary.
+        if let Some(len) = self.current.source.current_span_len() {
+            if len != 0 {
+                return Some(len);
+            } else if self.input.keep_alive_if_empty.load(Ordering::Acquire)
+                && self.input.next_sounds.lock().is_empty()
+            {
+                return Some(SILENCE_LENGTH);
+            }
+        }
+
+        let (lower_bound, _) = self.current.source.size_hint();
+
+        if lower_bound > 0 {
+            return Some(lower_bound);
+        }
+
+        Some(SILENCE_LENGTH)
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.current.source.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.current.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.current.source.try_seek(pos)
+    }
 }
 
 /// Handle to a device that outputs sounds.
@@ -26,100 +397,228 @@ struct Controls {
 pub(super) struct Sink {
     player_tx: Sender<MusicPlayerEvent>,
 
-    queue: Arc<queue::SourcesQueueInput>,
+    queue: Arc<QueueInput>,
     controls: Arc<Controls>,
-
-    sleep_until_end: Mutex<Option<Receiver<()>>>,
+    tap: Arc<TapBuffer>,
 }
 
 impl Sink {
     pub fn new(mixer: &Mixer, player_tx: Sender<MusicPlayerEvent>) -> Self {
-        // TODO: Create custom queue to support source modification (e.g., crossfade)
-        let (queue, source) = queue::queue(true);
+        let (queue, source) = queue(true);
+        let tap = Arc::new(TapBuffer::default());
 
-        mixer.add(source);
+        mixer.add(Tap::new(source, tap.clone()));
 
         Self {
             player_tx,
 
             controls: Arc::new(Controls {
                 pause: AtomicBool::new(false),
+                paused: AtomicBool::new(false),
                 stopped: AtomicBool::new(true),
+                stopped_before: AtomicUsize::new(0),
+                pending: AtomicUsize::new(0),
+                active: AtomicUsize::new(0),
+                next_id: AtomicUsize::new(0),
 
                 seek: Mutex::new(None),
                 volume: Mutex::new(1.0),
+                sleep_gain: Mutex::new(1.0),
+                replay_gain: Mutex::new(ReplayGainMode::Off),
+                equalizer: Mutex::new(None),
+                automatic_equalizer: Mutex::new(AutomaticEqualizer::default()),
                 position: Mutex::new(Duration::ZERO),
+                ab_loop: Mutex::new(AbLoop::default()),
+                speed: Mutex::new(PlaybackSpeed::default()),
+                fade: Mutex::new(Duration::ZERO),
             }),
             queue,
-
-            sleep_until_end: Mutex::new(None),
+            tap,
         }
     }
 
     /// Add sound to sink and play if stopped or else add to queue.
-    pub fn add<S>(&self, source: S)
+    ///
+    /// Queued sound starts right after the last sample of the current one, or overlaps its end
+    /// when `crossfade` is given. The boundary is reported with
+    /// `MusicPlayerEvent::PlaybackAdvanced` instead of `PlaybackEnded`.
+    ///
+    /// Sound added after a stop starts once the stopped sound has faded out, without waiting
+    /// for it here.
+    pub fn add<S>(&self, source: S, crossfade: Option<Crossfade>, track: &Track)
     where
         S: Source + Send + 'static,
     {
-        // NOTE: Wait for the queue to flush then resume stopped playback
+        let replay_gain = track.replay_gain;
+        let path = track.path.clone();
+        let genre = track
+            .genre
+            .as_deref()
+            .map(|genre| genre.trim().to_lowercase());
+
+        let id = self.controls.next_id.fetch_add(1, Ordering::SeqCst);
+
+        // NOTE: Stopped sounds still fading out are not active anymore, so they report nothing.
         if self.controls.stopped.load(Ordering::SeqCst) {
-            self.sleep_until_end();
+            *self.controls.position.lock() = Duration::ZERO;
+            self.controls.active.store(id, Ordering::SeqCst);
             self.controls.stopped.store(false, Ordering::SeqCst);
         }
 
+        self.controls.pending.fetch_add(1, Ordering::SeqCst);
+
+        let remaining = Arc::new(Mutex::new(None));
+
         let player_tx = self.player_tx.clone();
         let controls = self.controls.clone();
-        let source = source
-            .track_position()
+        let source = Ramp::new(Speed::new(Equalize::new(source.track_position())))
             .pausable(false)
             .amplify(1.0)
             .skippable()
-            .periodic_access(Duration::from_millis(5), move |s| {
-                if controls.stopped.load(Ordering::SeqCst) {
-                    s.skip();
-                    *controls.position.lock() = Duration::ZERO;
-                }
-
-                let amplify = s.inner_mut();
-                amplify.set_factor(*controls.volume.lock());
-
-                let pausable = amplify.inner_mut();
-                pausable.set_paused(controls.pause.load(Ordering::SeqCst));
-
-                let track_position = pausable.inner_mut();
-                *controls.position.lock() = track_position.get_pos();
-
-                if let Some(err) = controls
-                    .seek
-                    .lock()
-                    .take()
-                    .and_then(|seek| s.try_seek(seek).err())
-                {
-                    warn!("Seek error: {err:?}");
+            .periodic_access(Duration::from_millis(5), {
+                let remaining = remaining.clone();
+                let seek_tx = self.player_tx.clone();
+
+                move |s| {
+                    let active = controls.active.load(Ordering::SeqCst) == id;
+                    let stopped = id < controls.stopped_before.load(Ordering::SeqCst);
+                    let pause = controls.pause.load(Ordering::SeqCst);
+                    let seek = active && controls.seek.lock().is_some();
+                    let fade = *controls.fade.lock();
+
+                    let amplify = s.inner_mut();
+                    amplify.set_factor(
+                        *controls.volume.lock()
+                            * *controls.sleep_gain.lock()
+                            * controls.replay_gain.lock().factor(&replay_gain),
+                    );
+
+                    let pausable = amplify.inner_mut();
+
+                    // NOTE: Fade out before pausing, stopping or seeking and back in afterwards.
+                    let (settled, silent) = {
+                        let ramp = pausable.inner_mut();
+                        ramp.set_target(if stopped || pause || seek { 0.0 } else { 1.0 }, fade);
+
+                        (ramp.is_settled(), ramp.is_silent())
+                    };
+
+                    pausable.set_paused(pause && silent);
+
+                    if active && settled {
+                        controls.paused.store(pause, Ordering::SeqCst);
+                    }
+
+                    let speed = pausable.inner_mut().inner_mut();
+                    let playback_speed = *controls.speed.lock();
+                    speed.set_speed(playback_speed);
+
+                    let equalize = speed.inner_mut();
+                    {
+                        let equalizer = controls.equalizer.lock();
+                        let automatic_equalizer = controls.automatic_equalizer.lock();
+
+                        // NOTE: Automatic presets only apply while the equalizer is enabled.
+                        equalize.set_equalizer(equalizer.as_ref().map(|equalizer| {
+                            automatic_equalizer
+                                .get(&path, genre.as_deref())
+                                .unwrap_or(equalizer)
+                        }));
+                    }
+
+                    let track_position = equalize.inner_mut();
+                    let position = track_position.get_pos();
+
+                    // NOTE: Crossfades are timed in played time, positions are in media time.
+                    *remaining.lock() = s.total_duration().map(|duration| {
+                        playback_speed.wall_time(duration.saturating_sub(position))
+                    });
+
+                    if stopped && silent {
+                        s.skip();
+                    }
+
+                    // NOTE: Only the oldest sound reports while sounds overlap in a crossfade.
+                    if !active {
+                        return;
+                    }
+
+                    *controls.position.lock() = position;
+
+                    if let Some((a, b)) = controls.ab_loop.lock().range()
+                        && position >= b
+                    {
+                        *controls.seek.lock() = Some(a);
+                    }
+
+                    if !silent {
+                        return;
+                    }
+
+                    if let Some(seek) = controls.seek.lock().take() {
+                        match s.try_seek(seek) {
+                            Ok(()) => {
+                                seek_tx.send(MusicPlayerEvent::PlaybackSeeked(seek)).ok();
+                            }
+                            Err(err) => warn!("Seek error: {err:?}"),
+                        }
+                    }
                 }
             })
             .periodic_access(Duration::from_millis(500), move |_| {
                 player_tx.send(MusicPlayerEvent::PlaybackProgress).ok();
             });
 
+        let source = DoneCallback::new(source, self.on_done(id));
+
+        self.queue.append(source, remaining, crossfade);
+    }
+
+    /// Reports the end of the sound `id` and hands playback over to the next one, if any.
+    fn on_done(&self, id: usize) -> impl FnOnce() + Send + 'static {
         let controls = self.controls.clone();
         let player_tx = self.player_tx.clone();
-        let source = DoneCallback::new(source, move || {
-            if controls.stopped.load(Ordering::SeqCst) {
+
+        move || {
+            let pending = controls
+                .pending
+                .fetch_sub(1, Ordering::SeqCst)
+                .saturating_sub(1);
+
+            if id < controls.stopped_before.load(Ordering::SeqCst) {
                 player_tx.send(MusicPlayerEvent::PlaybackStopped).ok();
+            } else if pending > 0 {
+                // NOTE: Next source in the queue continues right after this sample.
+                *controls.position.lock() = Duration::ZERO;
+                controls.active.store(id + 1, Ordering::SeqCst);
+                player_tx.send(MusicPlayerEvent::PlaybackAdvanced).ok();
             } else {
+                controls.stopped.store(true, Ordering::SeqCst);
                 player_tx.send(MusicPlayerEvent::PlaybackEnded).ok();
             }
+        }
+    }
 
-            controls.stopped.store(true, Ordering::SeqCst);
-        });
+    /// Moves the sink to another mixer, keeping its controls.
+    ///
+    /// Sounds still queued on the previous mixer are left behind, stop the sink first.
+    pub fn set_output(&mut self, mixer: &Mixer) {
+        self.queue.set_keep_alive_if_empty(false);
 
-        *self.sleep_until_end.lock() = Some(self.queue.append_with_signal(source));
+        let (queue, source) = queue(true);
+        mixer.add(Tap::new(source, self.tap.clone()));
+        self.queue = queue;
     }
 
     #[inline]
     pub fn stop(&self) {
         self.controls.stopped.store(true, Ordering::SeqCst);
+        self.controls.stopped_before.store(
+            self.controls.next_id.load(Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+        // NOTE: Queued sources are dropped and report their end as stopped.
+        self.queue.clear();
     }
 
     #[inline]
@@ -137,11 +636,23 @@ impl Sink {
         self.controls.pause.store(true, Ordering::SeqCst);
     }
 
+    /// Whether playback has faded out after a pause, see `is_pause_requested`.
     #[inline]
     pub fn is_paused(&self) -> bool {
+        self.controls.paused.load(Ordering::SeqCst)
+    }
+
+    /// Whether playback is paused or fading out to pause.
+    #[inline]
+    pub fn is_pause_requested(&self) -> bool {
         self.controls.pause.load(Ordering::SeqCst)
     }
 
+    #[inline]
+    pub fn set_fade(&self, fade: Duration) {
+        *self.controls.fade.lock() = fade;
+    }
+
     #[inline]
     pub fn volume(&self) -> f32 {
         *self.controls.volume.lock()
@@ -152,21 +663,74 @@ impl Sink {
         *self.controls.volume.lock() = value;
     }
 
+    #[cfg(test)]
+    #[inline]
+    pub fn sleep_gain(&self) -> f32 {
+        *self.controls.sleep_gain.lock()
+    }
+
+    #[inline]
+    pub fn set_sleep_gain(&self, gain: f32) {
+        *self.controls.sleep_gain.lock() = gain;
+    }
+
+    #[inline]
+    pub fn set_replay_gain_mode(&self, mode: ReplayGainMode) {
+        *self.controls.replay_gain.lock() = mode;
+    }
+
+    #[inline]
+    pub fn set_equalizer(&self, equalizer: Option<Equalizer>) {
+        *self.controls.equalizer.lock() = equalizer;
+    }
+
+    #[inline]
+    pub fn set_automatic_equalizer(&self, automatic_equalizer: AutomaticEqualizer) {
+        *self.controls.automatic_equalizer.lock() = automatic_equalizer;
+    }
+
+    #[inline]
+    pub fn ab_loop(&self) -> AbLoop {
+        *self.controls.ab_loop.lock()
+    }
+
+    #[inline]
+    pub fn set_ab_loop(&self, ab_loop: AbLoop) {
+        *self.controls.ab_loop.lock() = ab_loop;
+    }
+
+    /// Samples sent to the output, for visualizers.
+    #[inline]
+    pub fn tap(&self) -> Arc<TapBuffer> {
+        self.tap.clone()
+    }
+
+    #[inline]
+    pub fn speed(&self) -> PlaybackSpeed {
+        *self.controls.speed.lock()
+    }
+
+    #[inline]
+    pub fn set_speed(&self, speed: PlaybackSpeed) {
+        *self.controls.speed.lock() = speed;
+    }
+
+    /// Position in the current track in media time, whatever the playback speed.
     #[inline]
     pub fn position(&self) -> Duration {
         *self.controls.position.lock()
     }
 
+    /// Whether playback has ended or a stop was requested, see `is_stopped`.
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.controls.stopped.load(Ordering::SeqCst)
     }
 
+    /// Whether playback has ended or the last sound has faded out after a stop.
     #[inline]
-    pub fn sleep_until_end(&self) {
-        if let Some(sleep_until_end) = self.sleep_until_end.lock().take() {
-            sleep_until_end.recv().ok();
-        }
+    pub fn is_stopped(&self) -> bool {
+        self.is_empty() && self.controls.pending.load(Ordering::SeqCst) == 0
     }
 }
 
@@ -174,6 +738,93 @@ impl Drop for Sink {
     #[inline]
     fn drop(&mut self) {
         self.queue.set_keep_alive_if_empty(false);
-        self.controls.stopped.store(true, Ordering::Relaxed);
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_1_SQRT_2;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use parking_lot::Mutex;
+    use rodio::buffer::SamplesBuffer;
+
+    use super::{Crossfade, CrossfadeCurve, SILENCE_LENGTH, queue};
+
+    const SAMPLE_RATE: u32 = 1_000;
+
+    /// Samples of each track, the first crossfade check comes halfway through the first one.
+    const TRACK_LENGTH: usize = 1_024;
+
+    /// Samples left of the first track at the first crossfade check.
+    const OVERLAP: usize = 512;
+
+    /// Plays a track of `1.0` followed by a track of `0.5` that fades in over its end.
+    fn crossfade(curve: CrossfadeCurve) -> Vec<f32> {
+        let (input, output) = queue(false);
+
+        // NOTE: Kept up to date by the sink while playing, here it is only read once at the
+        // first check.
+        input.append(
+            SamplesBuffer::new(1, SAMPLE_RATE, vec![1.0; TRACK_LENGTH]),
+            Arc::new(Mutex::new(Some(Duration::from_millis(OVERLAP as u64)))),
+            None,
+        );
+        input.append(
+            SamplesBuffer::new(1, SAMPLE_RATE, vec![0.5; TRACK_LENGTH]),
+            Arc::default(),
+            Some(Crossfade {
+                duration: Duration::from_secs(1),
+                curve,
+            }),
+        );
+
+        // NOTE: The silence played before the first track is skipped.
+        output.skip(SILENCE_LENGTH).collect()
+    }
+
+    #[test]
+    fn mixes_tracks_while_crossfading() {
+        for curve in [CrossfadeCurve::Linear, CrossfadeCurve::EqualPower] {
+            let samples = crossfade(curve);
+            let fade_start = TRACK_LENGTH - OVERLAP;
+
+            assert_eq!(
+                samples.len(),
+                2 * TRACK_LENGTH - OVERLAP,
+                "Tracks should overlap with {curve:?}."
+            );
+            assert!(
+                samples
+                    .get(..fade_start)
+                    .expect("Start of the first track.")
+                    .iter()
+                    .all(|sample| (sample - 1.0).abs() < f32::EPSILON),
+                "First track should play alone until the crossfade with {curve:?}."
+            );
+            assert!(
+                samples
+                    .get(TRACK_LENGTH..)
+                    .expect("End of the second track.")
+                    .iter()
+                    .all(|sample| (sample - 0.5).abs() < f32::EPSILON),
+                "Second track should play alone after the crossfade with {curve:?}."
+            );
+
+            let (outgoing_gain, incoming_gain) = match curve {
+                CrossfadeCurve::Linear => (0.5, 0.5),
+                CrossfadeCurve::EqualPower => (FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            };
+            let middle = samples
+                .get(fade_start + OVERLAP / 2)
+                .expect("Middle of the crossfade.");
+
+            assert!(
+                (middle - (outgoing_gain + incoming_gain * 0.5)).abs() < 1e-3,
+                "Both tracks should be mixed halfway through the crossfade with {curve:?}."
+            );
+        }
     }
 }
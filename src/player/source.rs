@@ -1,7 +1,23 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicUsize, Ordering};
 use std::time::Duration;
 
 use rodio::source::SeekError;
-use rodio::{ChannelCount, SampleRate, Source};
+use rodio::{ChannelCount, Sample, SampleRate, Source};
+
+use crate::dsp::Biquad;
+
+/// Center frequencies of the graphic equalizer bands in Hz.
+pub const GRAPHIC_FREQUENCIES: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1_000.0, 2_000.0, 4_000.0, 8_000.0, 16_000.0,
+];
+
+/// About one octave wide, so that neighbouring graphic bands blend into each other.
+const GRAPHIC_Q: f32 = 1.41;
 
 pub(super) struct DoneCallback<I, F>
 where
@@ -23,6 +39,19 @@ where
     }
 }
 
+impl<I, F> Drop for DoneCallback<I, F>
+where
+    F: FnOnce(),
+{
+    fn drop(&mut self) {
+        // NOTE: Sources dropped before they are exhausted (e.g., cleared from the queue or cut
+        // short by a crossfade) still report their end.
+        if let Some(callback) = self.callback.take() {
+            callback();
+        }
+    }
+}
+
 impl<I, F> Iterator for DoneCallback<I, F>
 where
     I: Source,
@@ -78,3 +107,1320 @@ where
         self.input.try_seek(pos)
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+impl FilterKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Peaking => "peaking",
+            Self::LowShelf => "low_shelf",
+            Self::HighShelf => "high_shelf",
+        }
+    }
+}
+
+/// A single filter of the equalizer, `gain` is in dB and `frequency` in Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqualizerBand {
+    pub kind: FilterKind,
+    pub frequency: f32,
+    pub gain: f32,
+    pub q: f32,
+}
+
+impl EqualizerBand {
+    /// Filter coefficients following the Audio EQ Cookbook.
+    fn biquad(&self, sample_rate: SampleRate) -> Biquad {
+        let sample_rate = f64::from(sample_rate);
+        // NOTE: Keep the center away from Nyquist where the filters become unstable.
+        let frequency = f64::from(self.frequency).clamp(10.0, sample_rate * 0.45);
+
+        let a = 10_f64.powf(f64::from(self.gain) / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * f64::from(self.q.max(0.01)));
+
+        match self.kind {
+            FilterKind::Peaking => Biquad::new(
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            FilterKind::LowShelf => {
+                let shelf = 2.0 * a.sqrt() * alpha;
+
+                Biquad::new(
+                    [
+                        a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                        a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                    ],
+                    [
+                        (a + 1.0) + (a - 1.0) * cos + shelf,
+                        -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                        (a + 1.0) + (a - 1.0) * cos - shelf,
+                    ],
+                )
+            }
+            FilterKind::HighShelf => {
+                let shelf = 2.0 * a.sqrt() * alpha;
+
+                Biquad::new(
+                    [
+                        a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                    ],
+                    [
+                        (a + 1.0) - (a - 1.0) * cos + shelf,
+                        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                        (a + 1.0) - (a - 1.0) * cos - shelf,
+                    ],
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EqualizerMode {
+    /// Gains in dB of the bands at `GRAPHIC_FREQUENCIES`.
+    Graphic([f32; GRAPHIC_FREQUENCIES.len()]),
+    Parametric(Vec<EqualizerBand>),
+}
+
+/// Equalizer settings, `preamp` is in dB and applied before the filters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equalizer {
+    pub preamp: f32,
+    pub mode: EqualizerMode,
+}
+
+impl Default for Equalizer {
+    fn default() -> Self {
+        Self {
+            preamp: 0.0,
+            mode: EqualizerMode::Graphic([0.0; GRAPHIC_FREQUENCIES.len()]),
+        }
+    }
+}
+
+impl Equalizer {
+    /// Returns the filters to apply.
+    ///
+    /// Flat bands are kept, they pass samples through untouched, so that the number of filters
+    /// stays the same while gains are dragged and the filters can be updated in place.
+    pub fn bands(&self) -> Vec<EqualizerBand> {
+        match &self.mode {
+            EqualizerMode::Graphic(gains) => GRAPHIC_FREQUENCIES
+                .iter()
+                .zip(gains)
+                .map(|(frequency, gain)| EqualizerBand {
+                    kind: FilterKind::Peaking,
+                    frequency: *frequency,
+                    gain: *gain,
+                    q: GRAPHIC_Q,
+                })
+                .collect(),
+            EqualizerMode::Parametric(bands) => bands.clone(),
+        }
+    }
+}
+
+/// Serializes as `graphic;<preamp>;<gain>,...` or `parametric;<preamp>;<kind>:<frequency>:<gain>:<q>,...`.
+impl fmt::Display for Equalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mode {
+            EqualizerMode::Graphic(gains) => {
+                let gains = gains.map(|gain| gain.to_string()).join(",");
+
+                write!(f, "graphic;{};{gains}", self.preamp)
+            }
+            EqualizerMode::Parametric(bands) => {
+                let bands = bands
+                    .iter()
+                    .map(|band| {
+                        format!(
+                            "{}:{}:{}:{}",
+                            band.kind.as_str(),
+                            band.frequency,
+                            band.gain,
+                            band.q
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                write!(f, "parametric;{};{bands}", self.preamp)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseEqualizerError;
+
+impl FromStr for Equalizer {
+    type Err = ParseEqualizerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, ';');
+        let (Some(mode), Some(preamp), Some(bands)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseEqualizerError);
+        };
+
+        let preamp = preamp.parse().map_err(|_err| ParseEqualizerError)?;
+        let values = bands.split(',').filter(|value| !value.is_empty());
+
+        let mode = match mode {
+            "graphic" => {
+                let mut gains = [0.0; GRAPHIC_FREQUENCIES.len()];
+                let values = values
+                    .map(|value| value.parse::<f32>().map_err(|_err| ParseEqualizerError))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if values.len() != gains.len() {
+                    return Err(ParseEqualizerError);
+                }
+                gains.copy_from_slice(&values);
+
+                EqualizerMode::Graphic(gains)
+            }
+            "parametric" => EqualizerMode::Parametric(
+                values
+                    .map(|value| {
+                        let mut fields = value.split(':');
+                        let kind = match fields.next() {
+                            Some("peaking") => FilterKind::Peaking,
+                            Some("low_shelf") => FilterKind::LowShelf,
+                            Some("high_shelf") => FilterKind::HighShelf,
+                            _ => return Err(ParseEqualizerError),
+                        };
+                        let mut number = || {
+                            fields
+                                .next()
+                                .and_then(|field| field.parse::<f32>().ok())
+                                .ok_or(ParseEqualizerError)
+                        };
+
+                        Ok(EqualizerBand {
+                            kind,
+                            frequency: number()?,
+                            gain: number()?,
+                            q: number()?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(ParseEqualizerError),
+        };
+
+        Ok(Self { preamp, mode })
+    }
+}
+
+/// Filters the input through an `Equalizer`, which can be changed while playing.
+pub(super) struct Equalize<I> {
+    input: I,
+    equalizer: Option<Equalizer>,
+    preamp: f32,
+    /// Band filters of every channel.
+    filters: Vec<Vec<Biquad>>,
+    channel: usize,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+}
+
+impl<I> Equalize<I>
+where
+    I: Source,
+{
+    pub fn new(input: I) -> Self {
+        Self {
+            channels: input.channels(),
+            sample_rate: input.sample_rate(),
+
+            input,
+            equalizer: None,
+            preamp: 1.0,
+            filters: Vec::new(),
+            channel: 0,
+        }
+    }
+
+    /// Sets the equalizer, `None` passes samples through untouched.
+    ///
+    /// Filters are only recomputed when the equalizer or the input format has changed. They are
+    /// updated in place, keeping their state so that changes do not click, unless the number of
+    /// bands or channels changed.
+    pub fn set_equalizer(&mut self, equalizer: Option<&Equalizer>) {
+        let channels = self.input.channels();
+        let sample_rate = self.input.sample_rate();
+
+        if self.equalizer.as_ref() == equalizer
+            && self.channels == channels
+            && self.sample_rate == sample_rate
+        {
+            return;
+        }
+
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+
+        // NOTE: Filters are kept while the equalizer is disabled, they are not used meanwhile.
+        let Some(equalizer) = equalizer else {
+            self.equalizer = None;
+            return;
+        };
+
+        let filters = equalizer
+            .bands()
+            .iter()
+            .map(|band| band.biquad(sample_rate))
+            .collect::<Vec<_>>();
+
+        if self.filters.len() == usize::from(channels)
+            && self
+                .filters
+                .first()
+                .is_some_and(|first| first.len() == filters.len())
+        {
+            for channel_filters in &mut self.filters {
+                for (filter, new) in channel_filters.iter_mut().zip(&filters) {
+                    filter.update(new);
+                }
+            }
+        } else {
+            self.filters = vec![filters; usize::from(channels)];
+            self.channel = 0;
+        }
+
+        self.preamp = 10_f32.powf(equalizer.preamp / 20.0);
+        self.equalizer = Some(equalizer.clone());
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Iterator for Equalize<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+        let channel = self.channel;
+
+        self.channel = (self.channel + 1) % usize::from(self.channels.max(1));
+
+        if self.equalizer.is_none() {
+            return Some(sample);
+        }
+
+        let Some(filters) = self.filters.get_mut(channel) else {
+            return Some(sample);
+        };
+
+        let output = filters
+            .iter_mut()
+            .fold(f64::from(sample * self.preamp), |x, filter| {
+                filter.process(x)
+            });
+
+        Some(output as Sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Equalize<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+
+        // NOTE: Samples before the seek must not ring into the ones after it.
+        for filter in self.filters.iter_mut().flatten() {
+            filter.reset();
+        }
+        self.channel = 0;
+
+        Ok(())
+    }
+}
+
+/// Plays part of its input as if it was the whole source, for tracks split by a cue sheet.
+pub(super) struct Section<I> {
+    input: I,
+    start: Duration,
+    end: Option<Duration>,
+    /// Samples left before `end`, `None` plays to the end of the input.
+    remaining: Option<u64>,
+}
+
+impl<I> Section<I>
+where
+    I: Source,
+{
+    pub fn new(input: I, start: Duration, end: Option<Duration>) -> Self {
+        let mut section = Self {
+            input,
+            start,
+            end,
+            remaining: None,
+        };
+
+        if section.input.try_seek(start).is_err() {
+            // NOTE: Decoders that cannot seek have to go through the samples before `start`.
+            for _ in 0..section.samples(start) {
+                section.input.next();
+            }
+        }
+        section.remaining = end.map(|end| section.samples(end.saturating_sub(start)));
+
+        section
+    }
+
+    /// Number of interleaved samples played in `duration`.
+    fn samples(&self, duration: Duration) -> u64 {
+        let frames = (duration.as_secs_f64() * f64::from(self.input.sample_rate())).round() as u64;
+
+        frames * u64::from(self.input.channels())
+    }
+}
+
+impl<I> Iterator for Section<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.checked_sub(1)?;
+        }
+
+        self.input.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.input.size_hint();
+
+        match self
+            .remaining
+            .and_then(|remaining| usize::try_from(remaining).ok())
+        {
+            Some(remaining) => (
+                lower.min(remaining),
+                Some(upper.map_or(remaining, |upper| upper.min(remaining))),
+            ),
+            None => (lower, upper),
+        }
+    }
+}
+
+impl<I> Source for Section<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        let remaining = self
+            .remaining
+            .and_then(|remaining| usize::try_from(remaining).ok());
+
+        match (self.input.current_span_len(), remaining) {
+            (Some(len), Some(remaining)) => Some(len.min(remaining)),
+            (len, remaining) => len.or(remaining),
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.end
+            .or_else(|| self.input.total_duration())
+            .map(|end| end.saturating_sub(self.start))
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let position = self.start + pos;
+
+        self.input.try_seek(position)?;
+        self.remaining = self
+            .end
+            .map(|end| self.samples(end.saturating_sub(position)));
+
+        Ok(())
+    }
+}
+
+/// How the playback rate is changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpeedMode {
+    /// Plays samples faster or slower, the pitch changes with the rate.
+    Resample,
+    /// Drops or repeats short overlapping slices of audio, the pitch is kept.
+    #[default]
+    TimeStretch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackSpeed {
+    /// Media time played per second, from `0.5` to `2.0`.
+    pub rate: f32,
+    pub mode: SpeedMode,
+}
+
+impl Default for PlaybackSpeed {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            mode: SpeedMode::default(),
+        }
+    }
+}
+
+impl PlaybackSpeed {
+    pub const MIN_RATE: f32 = 0.5;
+    pub const MAX_RATE: f32 = 2.0;
+
+    /// Converts media time to the time it takes to play it.
+    pub fn wall_time(self, media_time: Duration) -> Duration {
+        media_time.div_f32(self.rate)
+    }
+
+    fn is_normal(self) -> bool {
+        (self.rate - 1.0).abs() < f32::EPSILON
+    }
+}
+
+/// Changes the playback rate while keeping the format of the input, so that the output can
+/// still be crossfaded with other sounds.
+pub(super) struct Speed<I> {
+    input: I,
+    speed: PlaybackSpeed,
+    channels: usize,
+
+    /// Samples read from the input but not used yet, played first when the speed changes.
+    pending: VecDeque<Sample>,
+    output: VecDeque<Sample>,
+    resample: Resample,
+    stretch: Option<TimeStretch>,
+}
+
+impl<I> Speed<I>
+where
+    I: Source,
+{
+    pub fn new(input: I) -> Self {
+        Self {
+            channels: usize::from(input.channels().max(1)),
+
+            input,
+            speed: PlaybackSpeed::default(),
+
+            pending: VecDeque::new(),
+            output: VecDeque::new(),
+            resample: Resample::default(),
+            stretch: None,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: PlaybackSpeed) {
+        let speed = PlaybackSpeed {
+            rate: speed
+                .rate
+                .clamp(PlaybackSpeed::MIN_RATE, PlaybackSpeed::MAX_RATE),
+            ..speed
+        };
+
+        if self.speed == speed {
+            return;
+        }
+
+        // NOTE: Keep the buffered input when only the rate changes to avoid audible jumps.
+        if self.speed.mode != speed.mode || speed.is_normal() {
+            self.reset();
+        }
+
+        self.speed = speed;
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    fn reset(&mut self) {
+        let mut unplayed = self
+            .stretch
+            .take()
+            .map(TimeStretch::into_unplayed)
+            .unwrap_or_else(|| std::mem::take(&mut self.resample).into_unplayed());
+
+        // NOTE: Buffered input is older than the samples still pending.
+        unplayed.extend(self.pending.drain(..));
+        self.pending = unplayed.into();
+    }
+
+    fn next_input(&mut self) -> Option<Sample> {
+        self.pending.pop_front().or_else(|| self.input.next())
+    }
+
+    fn next_frame(&mut self) -> Option<Vec<Sample>> {
+        (0..self.channels).map(|_| self.next_input()).collect()
+    }
+
+    /// Adds at least one frame to the output, returns `false` at the end of the input.
+    fn fill(&mut self) -> bool {
+        match self.speed.mode {
+            SpeedMode::Resample => {
+                if self.resample.next.is_empty() {
+                    let (Some(previous), Some(next)) = (self.next_frame(), self.next_frame())
+                    else {
+                        return false;
+                    };
+
+                    self.resample.previous = previous;
+                    self.resample.next = next;
+                }
+
+                while self.resample.fraction >= 1.0 {
+                    let Some(frame) = self.next_frame() else {
+                        return false;
+                    };
+
+                    self.resample.previous = std::mem::replace(&mut self.resample.next, frame);
+                    self.resample.fraction -= 1.0;
+                }
+
+                let fraction = self.resample.fraction as f32;
+                self.output.extend(
+                    self.resample
+                        .previous
+                        .iter()
+                        .zip(&self.resample.next)
+                        .map(|(previous, next)| previous + (next - previous) * fraction),
+                );
+                self.resample.fraction += f64::from(self.speed.rate);
+
+                true
+            }
+            SpeedMode::TimeStretch => {
+                let mut stretch = self
+                    .stretch
+                    .take()
+                    .unwrap_or_else(|| TimeStretch::new(self.channels, self.input.sample_rate()));
+
+                while !stretch.is_ready() {
+                    let Some(frame) = self.next_frame() else {
+                        break;
+                    };
+                    stretch.push(&frame);
+                }
+
+                let filled = stretch.process(self.speed.rate, &mut self.output);
+                self.stretch = Some(stretch);
+
+                filled
+            }
+        }
+    }
+}
+
+impl<I> Iterator for Speed<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if self.speed.is_normal() {
+            return self.output.pop_front().or_else(|| self.next_input());
+        }
+
+        if self.output.is_empty() && !self.fill() {
+            // NOTE: Whatever is left is shorter than a frame of the stretched output.
+            return self.output.pop_front();
+        }
+
+        self.output.pop_front()
+    }
+}
+
+impl<I> Source for Speed<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        // NOTE: Buffered samples break the span boundaries of the input.
+        if self.speed.is_normal() && self.pending.is_empty() {
+            self.input.current_span_len()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+
+        self.pending.clear();
+        self.output.clear();
+        self.resample = Resample::default();
+        self.stretch = None;
+
+        Ok(())
+    }
+}
+
+/// Linear interpolation between two input frames.
+#[derive(Default)]
+struct Resample {
+    previous: Vec<Sample>,
+    next: Vec<Sample>,
+    /// Position of the next output frame between `previous` and `next`.
+    fraction: f64,
+}
+
+impl Resample {
+    fn into_unplayed(self) -> Vec<Sample> {
+        self.next
+    }
+}
+
+/// Waveform similarity overlap-add (WSOLA) time stretching.
+///
+/// Windowed slices of the input are added together every `hop` frames of output, while the
+/// position in the input moves by `hop * rate` frames. Each slice is shifted by up to
+/// `tolerance` frames to line up with the end of the previous one, which avoids phasing.
+struct TimeStretch {
+    channels: usize,
+    frame_len: usize,
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+
+    /// Interleaved input frames, starting at the oldest frame still needed.
+    input: Vec<Sample>,
+    /// Nominal position of the next slice in `input`, in frames.
+    position: f64,
+    /// Position in `input` right after the end of the previous slice, in frames.
+    continuation: Option<usize>,
+    /// Sum of the slices not output yet, `frame_len` frames long.
+    overlap: Vec<Sample>,
+}
+
+impl TimeStretch {
+    /// Length of the slices.
+    const FRAME_LENGTH: Duration = Duration::from_millis(40);
+    /// How far slices may be moved to match the previous one.
+    const TOLERANCE: Duration = Duration::from_millis(8);
+
+    fn new(channels: usize, sample_rate: SampleRate) -> Self {
+        let frames =
+            |duration: Duration| (duration.as_secs_f64() * f64::from(sample_rate)).round() as usize;
+
+        let hop = (frames(Self::FRAME_LENGTH) / 2).max(1);
+        let frame_len = hop * 2;
+
+        Self {
+            channels,
+            frame_len,
+            hop,
+            tolerance: frames(Self::TOLERANCE),
+            // NOTE: Periodic Hann windows overlapping by half add up to exactly one.
+            window: (0..frame_len)
+                .map(|index| {
+                    0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / frame_len as f32).cos()
+                })
+                .collect(),
+
+            input: Vec::new(),
+            position: 0.0,
+            continuation: None,
+            overlap: vec![0.0; frame_len * channels],
+        }
+    }
+
+    fn push(&mut self, frame: &[Sample]) {
+        self.input.extend_from_slice(frame);
+    }
+
+    fn input_frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// Whether enough input is buffered for the next slice wherever it ends up.
+    fn is_ready(&self) -> bool {
+        self.input_frames() >= self.position as usize + self.tolerance + self.frame_len
+    }
+
+    /// Adds the next `hop` frames to `output`, returns `false` once the input is used up.
+    fn process(&mut self, rate: f32, output: &mut VecDeque<Sample>) -> bool {
+        let frames = self.input_frames();
+        let nominal = self.position.round() as usize;
+
+        if nominal >= frames {
+            return false;
+        }
+
+        let position = self.best_position(nominal);
+        let channels = self.channels;
+
+        for index in 0..self.frame_len {
+            let weight = self.window.get(index).copied().unwrap_or_default();
+
+            for channel in 0..channels {
+                let sample = self
+                    .input
+                    .get((position + index) * channels + channel)
+                    .copied()
+                    .unwrap_or_default();
+
+                if let Some(sum) = self.overlap.get_mut(index * channels + channel) {
+                    *sum += sample * weight;
+                }
+            }
+        }
+
+        output.extend(self.overlap.drain(..self.hop * channels));
+        self.overlap.resize(self.frame_len * channels, 0.0);
+
+        let continuation = position + self.hop;
+        self.position += self.hop as f64 * f64::from(rate);
+
+        // NOTE: Drop the input that no slice can reach anymore.
+        let unused = (self.position as usize)
+            .saturating_sub(self.tolerance)
+            .min(continuation);
+        self.input
+            .drain(..(unused * channels).min(self.input.len()));
+        self.position -= unused as f64;
+        self.continuation = Some(continuation - unused);
+
+        true
+    }
+
+    /// Finds the slice around `nominal` most similar to the continuation of the previous one.
+    fn best_position(&self, nominal: usize) -> usize {
+        let Some(continuation) = self.continuation else {
+            return nominal;
+        };
+
+        let frames = self.input_frames();
+        let mono = |frame: usize| -> f32 {
+            self.input
+                .get(frame * self.channels..(frame + 1) * self.channels)
+                .map_or(0.0, |frame| frame.iter().sum())
+        };
+
+        let start = nominal.saturating_sub(self.tolerance);
+        let end = (nominal + self.tolerance).min(frames.saturating_sub(self.hop));
+
+        (start..=end.max(start))
+            .map(|candidate| {
+                // NOTE: Every other frame is enough to tell the best match.
+                let similarity = (0..self.hop)
+                    .step_by(2)
+                    .map(|index| mono(continuation + index) * mono(candidate + index))
+                    .sum::<f32>();
+
+                (candidate, similarity)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(nominal, |(candidate, _)| candidate)
+    }
+
+    /// Input that has not been played yet, from the position of the next slice.
+    fn into_unplayed(mut self) -> Vec<Sample> {
+        let start = (self.position as usize * self.channels).min(self.input.len());
+
+        self.input.split_off(start)
+    }
+}
+
+/// Moves the volume gradually towards a target, so that pausing, seeking and stopping do not
+/// cut the sound in the middle of a wave and click.
+pub(super) struct Ramp<I> {
+    input: I,
+    gain: f32,
+    target: f32,
+    /// Gain change per sample.
+    step: f32,
+}
+
+impl<I> Ramp<I>
+where
+    I: Source,
+{
+    pub fn new(input: I) -> Self {
+        Self {
+            input,
+            gain: 1.0,
+            target: 1.0,
+            step: 0.0,
+        }
+    }
+
+    /// Ramps from the current gain to `target` over `duration`, a zero duration jumps right to it.
+    ///
+    /// Does nothing if the ramp is already heading there, so that it can be called repeatedly.
+    pub fn set_target(&mut self, target: f32, duration: Duration) {
+        if (self.target - target).abs() < f32::EPSILON {
+            return;
+        }
+
+        let samples = duration.as_secs_f32()
+            * self.input.sample_rate() as f32
+            * f32::from(self.input.channels());
+
+        self.target = target;
+        if samples < 1.0 {
+            self.gain = target;
+            self.step = 0.0;
+        } else {
+            self.step = (target - self.gain).abs() / samples;
+        }
+    }
+
+    /// Whether the gain has reached its target.
+    #[inline]
+    pub fn is_settled(&self) -> bool {
+        (self.gain - self.target).abs() < f32::EPSILON
+    }
+
+    /// Whether the gain has reached zero.
+    #[inline]
+    pub fn is_silent(&self) -> bool {
+        self.gain < f32::EPSILON && self.is_settled()
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Iterator for Ramp<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        let sample = self.input.next()?;
+
+        if !self.is_settled() {
+            self.gain = if self.gain < self.target {
+                (self.gain + self.step).min(self.target)
+            } else {
+                (self.gain - self.step).max(self.target)
+            };
+        }
+
+        Some(sample * self.gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Ramp<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+/// Ring buffer holding the latest samples sent to the output, read by the visualizer.
+///
+/// The audio thread never waits on readers: samples are written to atomics and old ones are
+/// overwritten, so a reader may see a few samples from the next lap while copying.
+pub struct TapBuffer {
+    samples: Box<[AtomicU32]>,
+    /// Total number of samples written, the next one goes to `written % samples.len()`.
+    written: AtomicUsize,
+    channels: AtomicU16,
+    sample_rate: AtomicU32,
+}
+
+impl Default for TapBuffer {
+    fn default() -> Self {
+        Self {
+            samples: (0..Self::LENGTH).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            channels: AtomicU16::new(2),
+            sample_rate: AtomicU32::new(44_100),
+        }
+    }
+}
+
+impl TapBuffer {
+    const LENGTH: usize = 16_384;
+
+    /// Largest number of frames `latest` can return.
+    pub const MAX_FRAMES: usize = Self::LENGTH / 8;
+
+    #[inline]
+    fn push(&self, sample: Sample) {
+        let written = self.written.load(Ordering::Relaxed);
+
+        if let Some(slot) = self.samples.get(written % Self::LENGTH) {
+            slot.store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.written
+            .store(written.wrapping_add(1), Ordering::Release);
+    }
+
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Returns the last `frames` frames written (at most `MAX_FRAMES`), mixed down to mono.
+    pub fn latest(&self, frames: usize) -> Vec<f32> {
+        let channels = usize::from(self.channels.load(Ordering::Relaxed).max(1));
+        let written = self.written.load(Ordering::Acquire);
+        // NOTE: Start on a frame boundary so that channels are not mixed up.
+        let end = written - written % channels;
+        let frames = frames.min(Self::MAX_FRAMES).min(end / channels);
+
+        (0..frames)
+            .map(|frame| {
+                let start = end - (frames - frame) * channels;
+
+                (start..start + channels)
+                    .filter_map(|index| self.samples.get(index % Self::LENGTH))
+                    .map(|slot| f32::from_bits(slot.load(Ordering::Relaxed)))
+                    .sum::<f32>()
+                    / channels as f32
+            })
+            .collect()
+    }
+}
+
+/// Copies the samples passing through into a `TapBuffer`, without delaying them.
+pub(super) struct Tap<I> {
+    input: I,
+    buffer: Arc<TapBuffer>,
+    /// Samples until the format is read again.
+    until_format: usize,
+}
+
+impl<I> Tap<I>
+where
+    I: Source,
+{
+    /// Number of samples between updates of the format stored in the buffer.
+    const FORMAT_INTERVAL: usize = 1_024;
+
+    pub fn new(input: I, buffer: Arc<TapBuffer>) -> Self {
+        Self {
+            input,
+            buffer,
+            until_format: 0,
+        }
+    }
+}
+
+impl<I> Iterator for Tap<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if self.until_format == 0 {
+            self.buffer
+                .channels
+                .store(self.input.channels(), Ordering::Relaxed);
+            self.buffer
+                .sample_rate
+                .store(self.input.sample_rate(), Ordering::Relaxed);
+            self.until_format = Self::FORMAT_INTERVAL;
+        }
+        self.until_format -= 1;
+
+        let sample = self.input.next()?;
+        self.buffer.push(sample);
+
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Tap<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::{
+        Equalizer, EqualizerBand, EqualizerMode, FilterKind, GRAPHIC_FREQUENCIES, PlaybackSpeed,
+        Ramp, Speed, SpeedMode,
+    };
+    use crate::config::BUILTIN_EQUALIZER_PRESETS;
+
+    #[test]
+    fn presets_round_trip() {
+        let parametric = Equalizer {
+            preamp: -3.5,
+            mode: EqualizerMode::Parametric(vec![
+                EqualizerBand {
+                    kind: FilterKind::LowShelf,
+                    frequency: 80.0,
+                    gain: 4.0,
+                    q: 0.7,
+                },
+                EqualizerBand {
+                    kind: FilterKind::Peaking,
+                    frequency: 2_500.5,
+                    gain: -2.25,
+                    q: 1.41,
+                },
+            ]),
+        };
+
+        for equalizer in BUILTIN_EQUALIZER_PRESETS
+            .iter()
+            .map(|preset| &preset.equalizer)
+            .chain([&parametric, &Equalizer::default()])
+        {
+            assert_eq!(
+                equalizer.to_string().parse::<Equalizer>().as_ref(),
+                Ok(equalizer),
+                "{equalizer} should parse back to itself."
+            );
+        }
+
+        assert!(
+            "graphic;0;1,2".parse::<Equalizer>().is_err(),
+            "Graphic equalizer should need every band."
+        );
+        assert_eq!(
+            Equalizer::default().bands().len(),
+            GRAPHIC_FREQUENCIES.len(),
+            "Flat bands should be kept."
+        );
+    }
+
+    #[test]
+    fn ramps_to_target() {
+        // NOTE: 10 ms are 10 samples at 1 kHz.
+        let mut ramp = Ramp::new(SamplesBuffer::new(1, 1_000, vec![1.0; 40]));
+
+        ramp.set_target(0.0, Duration::from_millis(10));
+        let faded: Vec<_> = ramp.by_ref().take(10).collect();
+        assert!(
+            faded.is_sorted_by(|a, b| a > b),
+            "Gain should go down sample by sample."
+        );
+        assert!(
+            faded.first().is_some_and(|sample| *sample < 1.0),
+            "Fade should start right away."
+        );
+        assert!(ramp.is_silent(), "Gain should reach zero after the fade.");
+        assert_eq!(ramp.next(), Some(0.0), "Silent ramp should stay silent.");
+
+        ramp.set_target(1.0, Duration::from_millis(10));
+        ramp.next();
+        ramp.set_target(1.0, Duration::from_millis(100));
+        assert_eq!(
+            ramp.by_ref().take(9).last(),
+            Some(1.0),
+            "Ramp heading to its target should not start over."
+        );
+
+        ramp.set_target(0.0, Duration::ZERO);
+        assert!(ramp.is_silent(), "Zero duration should jump to the target.");
+    }
+
+    /// A second of a 440 Hz sine at 48 kHz, played at `rate`.
+    fn play_at(rate: f32, mode: SpeedMode) -> Vec<f32> {
+        let input: Vec<f32> = (0..48_000)
+            .map(|index| (2.0 * std::f32::consts::PI * 440.0 * index as f32 / 48_000.0).sin())
+            .collect();
+
+        let mut speed = Speed::new(SamplesBuffer::new(1, 48_000, input));
+        speed.set_speed(PlaybackSpeed { rate, mode });
+
+        speed.collect()
+    }
+
+    /// Frequency of a sine by its rising zero crossings.
+    fn frequency(samples: &[f32]) -> f32 {
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| matches!(pair, [previous, next] if *previous < 0.0 && *next >= 0.0))
+            .count();
+
+        crossings as f32 * 48_000.0 / samples.len() as f32
+    }
+
+    #[test]
+    fn scales_length_by_rate() {
+        for mode in [SpeedMode::Resample, SpeedMode::TimeStretch] {
+            for rate in [0.5, 1.5] {
+                let len = play_at(rate, mode).len() as f32;
+                let expected = 48_000.0 / rate;
+
+                // NOTE: The end may be off by up to a 40 ms slice of the time stretching.
+                assert!(
+                    (len - expected).abs() < 1_920.0,
+                    "{mode:?} at {rate} should give {expected} samples, not {len}."
+                );
+            }
+        }
+
+        let normal = play_at(1.0, SpeedMode::TimeStretch);
+        assert_eq!(
+            normal.len(),
+            48_000,
+            "Normal speed should keep every sample."
+        );
+        assert!(
+            (frequency(&normal) - 440.0).abs() < 440.0 * 0.01,
+            "Normal speed should keep the pitch."
+        );
+    }
+
+    #[test]
+    fn keeps_pitch_when_stretching() {
+        for rate in [0.5, 2.0] {
+            let stretched = frequency(&play_at(rate, SpeedMode::TimeStretch));
+            assert!(
+                (stretched - 440.0).abs() < 440.0 * 0.03,
+                "Time stretching at {rate} should keep the pitch, not give {stretched} Hz."
+            );
+
+            let resampled = frequency(&play_at(rate, SpeedMode::Resample));
+            assert!(
+                (resampled - 440.0 * rate).abs() < 440.0 * rate * 0.03,
+                "Resampling at {rate} should change the pitch, not give {resampled} Hz."
+            );
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::fs;
 use std::io::Write as _;
 use std::path::PathBuf;
+use std::time::Duration;
 use std::{collections::HashMap, path::Path};
 
 use log::warn;
@@ -10,11 +11,15 @@ use rand::seq::SliceRandom as _;
 use crate::{
     config::get_default_app_dir_config,
     database::{Database, get_all_tracks},
-    track::{Track, read_track_metadata},
+    track::{Track, TrackRange, read_tracks},
 };
 
 pub type PlaylistId = String;
 
+/// Extended M3U option, as written by VLC, for where an entry starts in its file, so that the
+/// tracks of a file split by a cue sheet can be told apart.
+const START_TIME_OPTION: &str = "#EXTVLCOPT:start-time=";
+
 #[derive(Debug)]
 pub enum PlaylistMode {
     NoRepeat,
@@ -31,6 +36,7 @@ pub struct Playlist {
     id: Option<PlaylistId>,
     current_index: usize,
     previous_index: Vec<usize>,
+    next_index: Option<usize>,
 }
 
 impl Default for Playlist {
@@ -42,6 +48,7 @@ impl Default for Playlist {
             id: None,
             current_index: 0,
             previous_index: Vec::new(),
+            next_index: None,
         }
     }
 }
@@ -61,6 +68,7 @@ impl Playlist {
             id: None,
             current_index: 0,
             previous_index: Vec::new(),
+            next_index: None,
         }
     }
 
@@ -76,12 +84,12 @@ impl Playlist {
     pub fn new_from_file(path: &Path) -> std::io::Result<Self> {
         // NOTE: Try to get library metadata from the database so that we can get track metadata
         // from library instead of trying to read from the file directly.
-        let tracks: HashMap<PathBuf, Track> = match Database::new() {
+        let tracks: HashMap<(PathBuf, Duration), Track> = match Database::new() {
             Ok(database) => get_all_tracks(&database.get_connection())
                 .map(|tracks| {
                     tracks
                         .into_iter()
-                        .map(|track| (track.path.clone(), track))
+                        .map(|track| ((track.path.clone(), track.start()), track))
                         .collect()
                 })
                 .unwrap_or_default(),
@@ -90,30 +98,15 @@ impl Playlist {
 
         let content = fs::read_to_string(path)?;
 
-        let mut playlist_tracks = Vec::new();
-
-        for line in content.lines() {
-            let path = Path::new(line);
-
-            if let Some(track) = tracks.get(path) {
-                playlist_tracks.push(track.to_owned());
-            } else {
-                match read_track_metadata(path) {
-                    Ok(metadata) => playlist_tracks.push(metadata),
-                    Err(err) => {
-                        warn!(
-                            "Unable to read track metadata from '{:?}' - {err:?}",
-                            path.display(),
-                        );
-
-                        playlist_tracks.push(Track {
-                            path: path.to_owned(),
-                            ..Default::default()
-                        });
-                    }
-                }
-            }
-        }
+        let playlist_tracks = parse_entries(&content)
+            .into_iter()
+            .map(|(path, start)| {
+                tracks
+                    .get(&(path.clone(), start))
+                    .cloned()
+                    .unwrap_or_else(|| read_entry(path, start))
+            })
+            .collect();
 
         let mut playlist = Self::new(playlist_tracks);
 
@@ -138,6 +131,7 @@ impl Playlist {
         }
 
         self.previous_index = Vec::new();
+        self.next_index = None;
         self.current_index = index.clamp(0, self.tracks.len() - 1);
     }
 
@@ -149,32 +143,57 @@ impl Playlist {
         self.tracks.get(self.current_index)
     }
 
+    /// Returns the track that `next_track` will move to without moving to it.
+    ///
+    /// The chosen index is kept until `next_track` is called or the playlist changes, so that
+    /// random mode picks the same track that was handed to the player ahead of time.
+    pub fn peek_next_track(&mut self) -> Option<&Track> {
+        if self.next_index.is_none() {
+            self.next_index = self.upcoming_index();
+        }
+
+        self.tracks.get(self.next_index?)
+    }
+
     pub fn next_track(&mut self) -> Option<&Track> {
+        let next_index = self.next_index.take().or_else(|| self.upcoming_index());
+
         match self.mode {
             PlaylistMode::NoRepeat => {
                 self.previous_index = Vec::new();
-                None
-            }
-            PlaylistMode::Repeat => {
-                self.current_index += 1;
-
-                if self.current_index >= self.tracks.len() {
-                    self.current_index = 0;
-                }
-
-                self.current_track()
             }
-            PlaylistMode::RepeatSingle => self.current_track(),
             PlaylistMode::Random => {
-                let mut rng = rand::rng();
                 self.previous_index.push(self.current_index);
-                self.current_index = rng.random_range(0..self.tracks.len());
-                self.current_track()
             }
+            PlaylistMode::Repeat | PlaylistMode::RepeatSingle => {}
+        }
+
+        self.current_index = next_index?;
+        self.current_track()
+    }
+
+    fn upcoming_index(&self) -> Option<usize> {
+        if self.tracks.is_empty() {
+            return None;
+        }
+
+        match self.mode {
+            PlaylistMode::NoRepeat => None,
+            PlaylistMode::Repeat => {
+                if self.current_index + 1 >= self.tracks.len() {
+                    Some(0)
+                } else {
+                    Some(self.current_index + 1)
+                }
+            }
+            PlaylistMode::RepeatSingle => Some(self.current_index),
+            PlaylistMode::Random => Some(rand::rng().random_range(0..self.tracks.len())),
         }
     }
 
     pub fn previous_track(&mut self) -> Option<&Track> {
+        self.next_index = None;
+
         if let Some(previous_index) = self.previous_index.pop() {
             self.current_index = previous_index;
         } else {
@@ -190,6 +209,7 @@ impl Playlist {
 
     pub fn set_mode(&mut self, mode: PlaylistMode) {
         self.mode = mode;
+        self.next_index = None;
     }
 
     pub fn mode(&self) -> &PlaylistMode {
@@ -202,38 +222,111 @@ impl Playlist {
 
     pub fn shuffle(&mut self) {
         self.tracks.shuffle(&mut rand::rng());
+        self.next_index = None;
     }
 
     pub fn clear(&mut self) {
         self.tracks = Vec::new();
         self.previous_index = Vec::new();
+        self.next_index = None;
         self.current_index = 0;
     }
 
     pub fn push(&mut self, track: Track) {
         self.tracks.push(track);
+        self.next_index = None;
     }
 
     pub fn append(&mut self, mut tracks: Vec<Track>) {
         self.tracks.append(&mut tracks);
+        self.next_index = None;
+    }
+
+    /// Replaces every copy of `track` with it.
+    pub fn update_track(&mut self, track: &Track) {
+        for existing in self.tracks.iter_mut().filter(|existing| *existing == track) {
+            existing.clone_from(track);
+        }
     }
 
     pub fn save(&self) {
-        let mut content = String::new();
+        let file_path = get_default_app_dir_config().join("playlist.m3u");
+
+        if let Ok(file) = &mut fs::File::create(file_path) {
+            file.write_all(self.to_m3u().as_bytes()).ok();
+        }
+    }
 
-        self.tracks.iter().for_each(|track| {
-            if let Some(path) = track.path.to_str() {
-                content.push_str(path);
-                content.push('\n');
+    /// Writes the tracks as an extended M3U playlist, see `START_TIME_OPTION`.
+    fn to_m3u(&self) -> String {
+        let mut content = String::from("#EXTM3U\n");
+
+        for track in &self.tracks {
+            let Some(path) = track.path.to_str() else {
+                continue;
+            };
+
+            // NOTE: Offsets are kept in milliseconds, as in the library.
+            let start = track.start().as_millis();
+            if start > 0 {
+                content.push_str(&format!(
+                    "{START_TIME_OPTION}{}.{:03}\n",
+                    start / 1000,
+                    start % 1000
+                ));
             }
-        });
 
-        let file_path = get_default_app_dir_config().join("playlist.m3u");
+            content.push_str(path);
+            content.push('\n');
+        }
 
-        if let Ok(file) = &mut fs::File::create(file_path) {
-            file.write_all(content.into_bytes().as_slice()).ok();
+        content
+    }
+}
+
+/// Paths of the entries of a playlist, along with where they start in their file.
+fn parse_entries(content: &str) -> Vec<(PathBuf, Duration)> {
+    let mut entries = Vec::new();
+    let mut start = None;
+
+    for line in content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if let Some(seconds) = line.strip_prefix(START_TIME_OPTION) {
+            start = seconds
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite())
+                .map(|seconds| Duration::from_millis((seconds.max(0.0) * 1000.0).round() as u64));
+        } else if !line.starts_with('#') {
+            entries.push((PathBuf::from(line), start.take().unwrap_or_default()));
         }
     }
+
+    entries
+}
+
+/// Reads a playlist entry that is not in the library from its file.
+fn read_entry(path: PathBuf, start: Duration) -> Track {
+    let tracks = read_tracks(&path).unwrap_or_else(|err| {
+        warn!(
+            "Unable to read track metadata from '{:?}' - {err:?}",
+            path.display(),
+        );
+        Vec::new()
+    });
+
+    tracks
+        .into_iter()
+        .find(|track| track.start().as_millis() == start.as_millis())
+        .unwrap_or_else(|| Track {
+            range: (!start.is_zero()).then_some(TrackRange { start, end: None }),
+            path,
+            ..Default::default()
+        })
 }
 
 impl PartialEq for Playlist {
@@ -261,4 +354,70 @@ mod test {
 
         playlist.save();
     }
+
+    #[test]
+    fn keeps_start_of_tracks_split_by_cue_sheet() {
+        let track = |path: &str, start: Option<f64>| Track {
+            path: PathBuf::from(path),
+            range: start.map(|start| TrackRange {
+                start: Duration::from_secs_f64(start),
+                end: None,
+            }),
+            ..Default::default()
+        };
+        let playlist = Playlist::new(vec![
+            track("/music/single.flac", None),
+            // NOTE: Cue sheets count frames of 1/75 second.
+            track("/music/album.flac", Some(195.0 + 37.0 / 75.0)),
+            track("/music/album.flac", Some(0.0)),
+        ]);
+
+        assert_eq!(
+            parse_entries(&playlist.to_m3u()),
+            vec![
+                (PathBuf::from("/music/single.flac"), Duration::ZERO),
+                (
+                    PathBuf::from("/music/album.flac"),
+                    Duration::from_millis(195_493)
+                ),
+                (PathBuf::from("/music/album.flac"), Duration::ZERO),
+            ],
+            "Entries should keep where they start in their file."
+        );
+    }
+
+    #[test]
+    fn peek_next_track_matches_next_track() {
+        let mut playlist = Playlist::new(
+            (0..16)
+                .map(|i| Track {
+                    path: PathBuf::from(format!("{i}.flac")),
+                    ..Default::default()
+                })
+                .collect(),
+        );
+
+        for mode in [
+            PlaylistMode::Repeat,
+            PlaylistMode::RepeatSingle,
+            PlaylistMode::Random,
+        ] {
+            playlist.set_mode(mode);
+
+            for _ in 0..32 {
+                let peeked = playlist.peek_next_track().cloned();
+                assert_eq!(
+                    peeked.as_ref(),
+                    playlist.next_track(),
+                    "Next track should be the peeked track."
+                );
+            }
+        }
+
+        playlist.set_mode(PlaylistMode::NoRepeat);
+        assert!(
+            playlist.peek_next_track().is_none(),
+            "No repeat mode has no next track."
+        );
+    }
 }
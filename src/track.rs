@@ -1,5 +1,6 @@
 use std::{
     ffi::OsStr,
+    fs::File,
     path::{Path, PathBuf},
     result::Result,
     time::{Duration, SystemTime},
@@ -7,15 +8,95 @@ use std::{
 
 use chrono::{DateTime, Local};
 use lofty::{
-    config::ParseOptions,
+    config::{ParseOptions, WriteOptions},
     error::LoftyError,
-    file::{AudioFile as _, TaggedFileExt as _},
+    file::{AudioFile as _, FileType, TaggedFileExt as _},
+    flac::FlacFile,
+    id3::v2::{Frame, PopularimeterFrame},
+    mpeg::MpegFile,
+    ogg::{OpusFile, VorbisComments, VorbisFile},
     picture::PictureType,
     probe::Probe,
-    tag::ItemKey,
+    properties::FileProperties,
+    tag::{ItemKey, Tag, TagExt as _},
 };
 use walkdir::WalkDir;
 
+use crate::config::FolderFilter;
+use crate::cue::CueSheet;
+
+/// File extensions of the formats enabled with the codec features.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    #[cfg(feature = "flac")]
+    "flac",
+    #[cfg(feature = "mp3")]
+    "mp3",
+    #[cfg(feature = "wav")]
+    "wav",
+    #[cfg(any(feature = "vorbis", feature = "opus"))]
+    "ogg",
+    #[cfg(feature = "vorbis")]
+    "oga",
+    #[cfg(feature = "opus")]
+    "opus",
+    #[cfg(any(feature = "aac", feature = "alac"))]
+    "m4a",
+];
+
+/// Highest star rating of a track.
+pub const MAX_RATING: u8 = 5;
+
+/// `POPM` ratings written for each number of stars, as most players read them.
+const POPM_RATINGS: [u8; MAX_RATING as usize + 1] = [0, 1, 64, 128, 196, 255];
+
+/// Email of the `POPM` frames written, the one most players read ratings from.
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+
+/// Difference between the replay gain 2.0 reference level (-18 LUFS) and the R128 one (-23 LUFS).
+const R128_REFERENCE_OFFSET: f32 = 5.0;
+
+/// Loudness normalization values, gains are in dB relative to the replay gain reference level
+/// and peaks are linear sample amplitudes.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+/// Technical properties of the audio stream of a track and of its file.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct AudioProperties {
+    /// Overall bitrate in kbps.
+    pub bitrate: Option<u32>,
+    /// Sample rate in Hz.
+    pub sample_rate: Option<u32>,
+    /// Only known for PCM based codecs.
+    pub bit_depth: Option<u8>,
+    pub channels: Option<u8>,
+    pub codec: Option<String>,
+    /// Size of the file in bytes.
+    pub file_size: Option<u64>,
+}
+
+impl AudioProperties {
+    /// Whether the codec is lossless, `None` if the codec is not known.
+    pub fn is_lossless(&self) -> Option<bool> {
+        self.codec
+            .as_deref()
+            .map(|codec| matches!(codec, "FLAC" | "ALAC" | "PCM"))
+    }
+}
+
+/// Part of a file played as a track of its own, for albums ripped to a single file.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackRange {
+    pub start: Duration,
+    /// `None` for the last track, which plays to the end of the file.
+    pub end: Option<Duration>,
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct Track {
     pub path: PathBuf,
@@ -30,9 +111,31 @@ pub struct Track {
     pub disc_total: Option<String>,
     pub track: Option<String>,
     pub track_total: Option<String>,
+    /// Year of release, from the year or recording date tag.
+    pub year: Option<u32>,
+    pub replay_gain: ReplayGain,
+    /// Set for tracks split from a file by a cue sheet, `None` plays the whole file.
+    pub range: Option<TrackRange>,
+    pub properties: AudioProperties,
+    /// Set once the file failed to be read or decoded, until it is modified.
+    pub unplayable: bool,
+    /// Times the track was played to its end.
+    pub play_count: u32,
+    /// Times the track was left before its end.
+    pub skip_count: u32,
+    pub last_played: Option<DateTime<Local>>,
+    /// Stars from 1 to `MAX_RATING`, `None` for unrated tracks.
+    pub rating: Option<u8>,
+    /// Only kept in the database, there is no common tag for it.
+    pub loved: bool,
 }
 
 impl Track {
+    /// Where the track starts in its file.
+    pub fn start(&self) -> Duration {
+        self.range.map(|range| range.start).unwrap_or_default()
+    }
+
     pub fn read_front_cover(&self) -> Result<Option<Vec<u8>>, LoftyError> {
         let path = self.path.as_path();
 
@@ -42,11 +145,44 @@ impl Track {
                 .map(|pic| pic.data().to_owned())
         }))
     }
+
+    /// Whether `next` is the track right after this one on the same album.
+    pub fn is_followed_by(&self, next: &Self) -> bool {
+        let number = |value: Option<&str>| value?.trim().parse::<u32>().ok();
+
+        if self.album.is_none()
+            || self.album != next.album
+            || self.album_artist != next.album_artist
+        {
+            return false;
+        }
+
+        match (
+            number(self.disc.as_deref()),
+            number(next.disc.as_deref()),
+            number(self.track.as_deref()),
+            number(next.track.as_deref()),
+        ) {
+            (disc, next_disc, Some(track), Some(next_track)) if disc == next_disc => {
+                next_track == track + 1
+            }
+            (Some(disc), Some(next_disc), Some(_), Some(1)) => next_disc == disc + 1,
+            _ => false,
+        }
+    }
+}
+
+/// Named position within a track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bookmark {
+    pub id: i64,
+    pub name: String,
+    pub position: Duration,
 }
 
 impl PartialEq for Track {
     fn eq(&self, other: &Self) -> bool {
-        self.path == other.path
+        self.path == other.path && self.start() == other.start()
     }
 }
 
@@ -59,29 +195,36 @@ impl AsRef<Self> for Track {
 /// Scans the given path for music files.
 ///
 /// This function recursively traverses directories, collecting `Track` for supported
-/// music file types (Currently plan to support `.flac`, `.wav`, `.mp3`).
+/// music file types (see `SUPPORTED_EXTENSIONS`).
 ///
 /// # Arguments
 ///
 /// * `path` - The starting path to scan. This can be a file or a directory.
+/// * `folder` - The library folder the path is in, which decides the files to skip.
 ///
 /// # Returns
 ///
-/// A `Result` which is:
-/// - `Vec<PathBuf>` containing a list of `PathBuf` for all music files found.
-pub fn scan_tracks(path: &Path) -> Vec<PathBuf> {
+/// An iterator of the paths of the music files, which walks the directories as it goes.
+pub fn scan_tracks(path: &Path, folder: &FolderFilter) -> impl Iterator<Item = PathBuf> {
     let walker = WalkDir::new(path)
-        .follow_links(true)
+        .follow_links(folder.follow_symlinks())
         .into_iter()
+        .filter_entry(move |entry| {
+            !entry.file_type().is_dir() || !folder.excludes_dir(entry.path())
+        })
         .filter_map(|e| e.ok())
-        .filter(|entry| {
+        .filter(move |entry| {
             entry.file_type().is_file()
-                && matches!(
-                    entry.path().extension().and_then(OsStr::to_str),
-                    Some("flac" | "mp3" | "wav")
-                )
+                && entry
+                    .path()
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|extension| {
+                        SUPPORTED_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                    })
+                && folder.contains(entry.path())
         });
-    walker.map(|entry| entry.path().to_owned()).collect()
+    walker.map(|entry| entry.path().to_owned())
 }
 
 /// Reads metadata from a music file.
@@ -105,22 +248,17 @@ pub fn read_track_metadata(path: &Path) -> Result<Track, LoftyError> {
             .read_cover_art(false),
     );
     let tagged = probe.read()?;
+    let properties = read_audio_properties(path, tagged.file_type(), tagged.properties());
 
     Ok(tagged.primary_tag().map_or_else(
         || Track {
             path: path.to_owned(),
+            properties: properties.clone(),
             ..Default::default()
         },
         |tag| Track {
             path: path.to_owned(),
-            modified: Some(
-                DateTime::<Local>::from(
-                    path.metadata()
-                        .and_then(|m| m.modified())
-                        .unwrap_or(SystemTime::now()),
-                )
-                .to_rfc3339(),
-            ),
+            modified: Some(file_modified(path)),
             title: tag.get_string(ItemKey::TrackTitle).map(String::from),
             artist: tag.get_string(ItemKey::TrackArtist).map(String::from),
             genre: tag.get_string(ItemKey::Genre).map(String::from),
@@ -130,7 +268,597 @@ pub fn read_track_metadata(path: &Path) -> Result<Track, LoftyError> {
             disc_total: tag.get_string(ItemKey::DiscTotal).map(String::from),
             track: tag.get_string(ItemKey::TrackNumber).map(String::from),
             track_total: tag.get_string(ItemKey::TrackTotal).map(String::from),
+            year: tag
+                .get_string(ItemKey::Year)
+                .or_else(|| tag.get_string(ItemKey::RecordingDate))
+                .and_then(parse_year),
             duration: Some(tagged.properties().duration()),
+            replay_gain: read_replay_gain(path, tag, tagged.file_type()),
+            range: None,
+            properties: properties.clone(),
+            unplayable: false,
+            play_count: 0,
+            skip_count: 0,
+            last_played: None,
+            rating: read_rating(path, tagged.file_type()),
+            loved: false,
         },
     ))
 }
+
+fn read_audio_properties(
+    path: &Path,
+    file_type: FileType,
+    properties: &FileProperties,
+) -> AudioProperties {
+    let codec = match file_type {
+        FileType::Flac => Some("FLAC"),
+        FileType::Mpeg => Some("MP3"),
+        FileType::Wav | FileType::Aiff => Some("PCM"),
+        FileType::Vorbis => Some("Vorbis"),
+        FileType::Opus => Some("Opus"),
+        // NOTE: MP4 files hold either AAC or ALAC, only ALAC has a bit depth.
+        FileType::Mp4 if properties.bit_depth().is_some() => Some("ALAC"),
+        FileType::Aac | FileType::Mp4 => Some("AAC"),
+        _ => None,
+    };
+
+    AudioProperties {
+        bitrate: properties
+            .overall_bitrate()
+            .or_else(|| properties.audio_bitrate()),
+        sample_rate: properties.sample_rate(),
+        bit_depth: properties.bit_depth(),
+        channels: properties.channels(),
+        codec: codec.map(String::from),
+        file_size: path.metadata().map(|metadata| metadata.len()).ok(),
+    }
+}
+
+/// Last modification time of a file as stored in the library, see `modified_time`.
+pub fn file_modified(path: &Path) -> String {
+    DateTime::<Local>::from(modified_time(path)).to_rfc3339()
+}
+
+/// Last modification time of a music file, now if it cannot be read.
+///
+/// Editing the cue sheet of a file changes the tracks read from it, so it counts as modifying
+/// the file.
+pub fn modified_time(path: &Path) -> SystemTime {
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+
+    cue_sheet_paths(path)
+        .iter()
+        .filter_map(|cue_path| modified(cue_path))
+        .chain([modified(path).unwrap_or(SystemTime::now())])
+        .max()
+        .unwrap_or(SystemTime::now())
+}
+
+/// Paths a cue sheet of a music file may have, `album.cue` or `album.flac.cue`.
+fn cue_sheet_paths(path: &Path) -> [PathBuf; 2] {
+    let mut cue_path = path.as_os_str().to_owned();
+    cue_path.push(".cue");
+
+    [path.with_extension("cue"), PathBuf::from(cue_path)]
+}
+
+/// The music files whose tracks change along with `path`, which are the files a cue sheet may
+/// belong to, or `path` itself if it is not a cue sheet.
+pub fn cue_sheet_files(path: &Path) -> Vec<PathBuf> {
+    let is_cue_sheet = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"));
+    if !is_cue_sheet {
+        return vec![path.to_owned()];
+    }
+
+    let stem = path.with_extension("");
+    let is_music_file = stem
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            SUPPORTED_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        });
+    if is_music_file {
+        return vec![stem];
+    }
+
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .map(|extension| {
+            let mut file = stem.as_os_str().to_owned();
+            file.push(".");
+            file.push(extension);
+            PathBuf::from(file)
+        })
+        .filter(|file| file.exists())
+        .collect()
+}
+
+/// Reads the tracks of a music file, which are several if it comes with a cue sheet.
+///
+/// Cue sheets are looked for next to the file, as `album.cue` or `album.flac.cue`, and then
+/// in the `CUESHEET` tag of FLAC files.
+///
+/// # Errors
+///
+/// Returns an error if the music file cannot be read by lofty.
+pub fn read_tracks(path: &Path) -> Result<Vec<Track>, LoftyError> {
+    let track = read_track_metadata(path)?;
+
+    let tracks = read_cue_sheet(path)
+        .map(|cue_sheet| cue_sheet.split(&track))
+        .unwrap_or_default();
+
+    Ok(if tracks.is_empty() {
+        vec![track]
+    } else {
+        tracks
+    })
+}
+
+fn read_cue_sheet(path: &Path) -> Option<CueSheet> {
+    // NOTE: Cue sheets are often written in legacy encodings, keep whatever can be read.
+    let external = cue_sheet_paths(path)
+        .into_iter()
+        .find_map(|cue_path| std::fs::read(cue_path).ok())
+        .map(|content| String::from_utf8_lossy(&content).into_owned());
+
+    let embedded = || {
+        let is_flac = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("flac"));
+        if !is_flac {
+            return None;
+        }
+
+        let flac = FlacFile::read_from(
+            &mut File::open(path).ok()?,
+            ParseOptions::default().read_cover_art(false),
+        )
+        .ok()?;
+        flac.vorbis_comments()?.get("CUESHEET").map(String::from)
+    };
+
+    external
+        .or_else(embedded)
+        .map(|content| CueSheet::parse(&content))
+}
+
+/// Reads `REPLAYGAIN_*` tags, Opus files fall back to `R128_*_GAIN` tags when those are missing.
+fn read_replay_gain(path: &Path, tag: &Tag, file_type: FileType) -> ReplayGain {
+    let gain = |key| parse_gain(tag.get_string(key)?);
+    let peak = |key| tag.get_string(key)?.trim().parse::<f32>().ok();
+
+    let mut replay_gain = ReplayGain {
+        track_gain: gain(ItemKey::ReplayGainTrackGain),
+        track_peak: peak(ItemKey::ReplayGainTrackPeak),
+        album_gain: gain(ItemKey::ReplayGainAlbumGain),
+        album_peak: peak(ItemKey::ReplayGainAlbumPeak),
+    };
+
+    if file_type == FileType::Opus
+        && let Some(opus) = File::open(path)
+            .ok()
+            .and_then(|mut file| OpusFile::read_from(&mut file, ParseOptions::default()).ok())
+    {
+        // NOTE: R128 gains are Q7.8 fixed point numbers relative to -23 LUFS.
+        let r128 = |key| {
+            opus.vorbis_comments()
+                .get(key)
+                .and_then(|value| value.trim().parse::<i16>().ok())
+                .map(|value| f32::from(value) / 256.0 + R128_REFERENCE_OFFSET)
+        };
+
+        replay_gain.track_gain = replay_gain.track_gain.or_else(|| r128("R128_TRACK_GAIN"));
+        replay_gain.album_gain = replay_gain.album_gain.or_else(|| r128("R128_ALBUM_GAIN"));
+    }
+
+    replay_gain
+}
+
+/// Writes replay gain values to the primary tag of a music file, creating the tag if needed.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or written by lofty.
+pub fn write_replay_gain(path: &Path, replay_gain: &ReplayGain) -> Result<(), LoftyError> {
+    // NOTE: Pictures are written back along with the tag, they have to be read.
+    let mut tagged = Probe::open(path)?.options(ParseOptions::default()).read()?;
+
+    if tagged.primary_tag().is_none() {
+        tagged.insert_tag(Tag::new(tagged.primary_tag_type()));
+    }
+
+    if let Some(tag) = tagged.primary_tag_mut() {
+        for (key, value) in [
+            (
+                ItemKey::ReplayGainTrackGain,
+                replay_gain.track_gain.map(|gain| format!("{gain:.2} dB")),
+            ),
+            (
+                ItemKey::ReplayGainTrackPeak,
+                replay_gain.track_peak.map(|peak| format!("{peak:.6}")),
+            ),
+            (
+                ItemKey::ReplayGainAlbumGain,
+                replay_gain.album_gain.map(|gain| format!("{gain:.2} dB")),
+            ),
+            (
+                ItemKey::ReplayGainAlbumPeak,
+                replay_gain.album_peak.map(|peak| format!("{peak:.6}")),
+            ),
+        ] {
+            if let Some(value) = value {
+                tag.insert_text(key, value);
+            }
+        }
+    }
+
+    tagged.save_to_path(path, WriteOptions::default())
+}
+
+/// Reads the `POPM` frame of MP3 files, and the `FMPS_RATING` or `RATING` comment of FLAC,
+/// Vorbis and Opus files.
+fn read_rating(path: &Path, file_type: FileType) -> Option<u8> {
+    let options = ParseOptions::default().read_cover_art(false);
+
+    if file_type == FileType::Mpeg {
+        let mpeg = MpegFile::read_from(&mut File::open(path).ok()?, options).ok()?;
+
+        let ratings: Vec<_> = mpeg
+            .id3v2()?
+            .into_iter()
+            .filter_map(|frame| match frame {
+                Frame::Popularimeter(popularimeter) => Some(popularimeter),
+                _ => None,
+            })
+            .collect();
+
+        // NOTE: Frames of other players may disagree, the one written here wins.
+        let popularimeter = ratings
+            .iter()
+            .find(|popularimeter| popularimeter.email == POPM_EMAIL)
+            .or_else(|| ratings.first())?;
+
+        return match popularimeter.rating {
+            0 => None,
+            1..=31 => Some(1),
+            32..=95 => Some(2),
+            96..=159 => Some(3),
+            160..=223 => Some(4),
+            _ => Some(5),
+        };
+    }
+
+    let comments = read_vorbis_comments(path, file_type, options).ok()??;
+
+    // NOTE: FMPS_RATING goes from 0.0 to 1.0, RATING is either stars or a percentage.
+    let stars = comments
+        .get("FMPS_RATING")
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .map(|value| value * f32::from(MAX_RATING))
+        .or_else(|| {
+            let value = comments.get("RATING")?.trim().parse::<f32>().ok()?;
+
+            Some(if value > f32::from(MAX_RATING) {
+                value / 20.0
+            } else {
+                value
+            })
+        })?
+        .round()
+        .clamp(0.0, f32::from(MAX_RATING)) as u8;
+
+    (stars > 0).then_some(stars)
+}
+
+/// Writes the rating of a file to its tags, as a `POPM` frame for MP3 files and as
+/// `FMPS_RATING` for FLAC, Vorbis and Opus files. Other formats are left as they are.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or written by lofty.
+pub fn write_rating(path: &Path, rating: Option<u8>) -> Result<(), LoftyError> {
+    let Some(file_type) = FileType::from_path(path) else {
+        return Ok(());
+    };
+    let stars = rating.unwrap_or_default().min(MAX_RATING);
+    // NOTE: Pictures are written back along with the tag, they have to be read.
+    let options = ParseOptions::default();
+
+    if file_type == FileType::Mpeg {
+        let mut id3v2 = MpegFile::read_from(&mut File::open(path)?, options)?
+            .id3v2()
+            .cloned()
+            .unwrap_or_default();
+        // NOTE: Ratings of other players are replaced too, the play counter is kept.
+        let mut counter = 0;
+        for frame in &id3v2 {
+            if let Frame::Popularimeter(popularimeter) = frame
+                && popularimeter.email == POPM_EMAIL
+            {
+                counter = popularimeter.counter;
+            }
+        }
+
+        id3v2.retain(|frame| !matches!(frame, Frame::Popularimeter(_)));
+        if stars > 0 {
+            id3v2.insert(Frame::Popularimeter(PopularimeterFrame::new(
+                POPM_EMAIL.into(),
+                POPM_RATINGS
+                    .get(usize::from(stars))
+                    .copied()
+                    .unwrap_or(u8::MAX),
+                counter,
+            )));
+        }
+
+        return id3v2.save_to_path(path, WriteOptions::default());
+    }
+
+    let Some(mut comments) = read_vorbis_comments(path, file_type, options)? else {
+        return Ok(());
+    };
+
+    if stars > 0 {
+        comments.insert(
+            String::from("FMPS_RATING"),
+            format!("{:.1}", f32::from(stars) / f32::from(MAX_RATING)),
+        );
+    } else {
+        // NOTE: Ratings of other players would show up again otherwise.
+        for key in ["FMPS_RATING", "RATING"] {
+            comments.remove(key).for_each(drop);
+        }
+    }
+
+    comments.save_to_path(path, WriteOptions::default())
+}
+
+/// Vorbis comments of FLAC, Vorbis and Opus files, `None` for other formats.
+fn read_vorbis_comments(
+    path: &Path,
+    file_type: FileType,
+    options: ParseOptions,
+) -> Result<Option<VorbisComments>, LoftyError> {
+    let file = &mut File::open(path)?;
+
+    Ok(match file_type {
+        // NOTE: FLAC files without comments get new ones.
+        FileType::Flac => Some(
+            FlacFile::read_from(file, options)?
+                .vorbis_comments()
+                .cloned()
+                .unwrap_or_default(),
+        ),
+        FileType::Vorbis => Some(
+            VorbisFile::read_from(file, options)?
+                .vorbis_comments()
+                .clone(),
+        ),
+        FileType::Opus => Some(
+            OpusFile::read_from(file, options)?
+                .vorbis_comments()
+                .clone(),
+        ),
+        _ => None,
+    })
+}
+
+/// Parses the year of dates such as `2001`, `2001-06-12` or `2001/06`.
+fn parse_year(value: &str) -> Option<u32> {
+    value.trim().get(..4)?.parse().ok()
+}
+
+/// Parses gain values such as `-6.48 dB`.
+pub fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+
+    value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value)
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use lofty::config::WriteOptions;
+    use lofty::file::{FileType, TaggedFileExt as _};
+    use lofty::id3::v2::{Frame, Id3v2Tag, PopularimeterFrame};
+    use lofty::ogg::VorbisComments;
+    use lofty::picture::{MimeType, Picture, PictureType};
+    use lofty::tag::{ItemKey, Tag, TagExt as _, TagType};
+
+    use super::{
+        AudioProperties, POPM_EMAIL, ReplayGain, Track, read_rating, write_rating,
+        write_replay_gain,
+    };
+
+    /// FLAC stream of 44.1 kHz stereo 16 bit audio without any frames, padded for tags.
+    const FLAC: &[u8] = &[
+        b'f', b'L', b'a', b'C', 0x00, 0x00, 0x00, 0x22, 0x10, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x0a, 0xc4, 0x42, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x81, 0x00, 0x00,
+        0x04, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// Header of a 128 kbps 44.1 kHz MPEG-1 Layer III frame, 417 bytes long.
+    const MP3_FRAME: [u8; 4] = [0xff, 0xfb, 0x90, 0x64];
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ferrum-{}-{name}", std::process::id()));
+        fs::write(&path, contents).expect("Temporary file written.");
+
+        path
+    }
+
+    fn mp3() -> Vec<u8> {
+        let mut frame = MP3_FRAME.to_vec();
+        frame.resize(417, 0);
+
+        frame.repeat(4)
+    }
+
+    fn rating(path: &Path) -> Option<u8> {
+        read_rating(path, FileType::from_path(path).expect("Supported file."))
+    }
+
+    #[test]
+    fn tells_lossless_codecs() {
+        let lossless = |codec: Option<&str>| {
+            AudioProperties {
+                codec: codec.map(ToOwned::to_owned),
+                ..Default::default()
+            }
+            .is_lossless()
+        };
+
+        for codec in ["FLAC", "ALAC", "PCM"] {
+            assert_eq!(
+                lossless(Some(codec)),
+                Some(true),
+                "{codec} should be lossless."
+            );
+        }
+        for codec in ["MP3", "AAC", "Vorbis", "Opus"] {
+            assert_eq!(
+                lossless(Some(codec)),
+                Some(false),
+                "{codec} should be lossy."
+            );
+        }
+        assert_eq!(lossless(None), None, "Unknown codec should be neither.");
+    }
+
+    #[test]
+    fn writes_and_reads_vorbis_rating() {
+        let path = temp_file("rating.flac", FLAC);
+
+        assert_eq!(rating(&path), None, "New file should be unrated.");
+        write_rating(&path, Some(4)).expect("Rating written.");
+        assert_eq!(rating(&path), Some(4), "Written rating should be read.");
+        write_rating(&path, None).expect("Rating removed.");
+        assert_eq!(rating(&path), None, "Removed rating should be gone.");
+
+        let mut comments = VorbisComments::default();
+        comments.insert(String::from("RATING"), String::from("60"));
+        comments
+            .save_to_path(&path, WriteOptions::default())
+            .expect("Comments written.");
+        assert_eq!(
+            rating(&path),
+            Some(3),
+            "Percentage ratings of other players should be read."
+        );
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn writes_and_reads_popm_rating() {
+        let path = temp_file("rating.mp3", &mp3());
+
+        assert_eq!(rating(&path), None, "New file should be unrated.");
+        for stars in 1..=5 {
+            write_rating(&path, Some(stars)).expect("Rating written.");
+            assert_eq!(
+                rating(&path),
+                Some(stars),
+                "Written rating should be read back."
+            );
+        }
+        write_rating(&path, None).expect("Rating removed.");
+        assert_eq!(rating(&path), None, "Removed rating should be gone.");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn keeps_cover_when_writing_replay_gain() {
+        let path = temp_file("replay-gain.flac", FLAC);
+        let cover = b"\x89PNG\r\n\x1a\ncover".to_vec();
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.push_picture(Picture::new_unchecked(
+            PictureType::CoverFront,
+            Some(MimeType::Png),
+            None,
+            cover.clone(),
+        ));
+        tag.save_to_path(&path, WriteOptions::default())
+            .expect("Cover written.");
+
+        write_replay_gain(
+            &path,
+            &ReplayGain {
+                track_gain: Some(-6.5),
+                ..Default::default()
+            },
+        )
+        .expect("Replay gain written.");
+
+        let tagged = lofty::read_from_path(&path).expect("File read.");
+        assert_eq!(
+            tagged
+                .primary_tag()
+                .and_then(|tag| tag.get_string(ItemKey::ReplayGainTrackGain)),
+            Some("-6.50 dB"),
+            "Replay gain should be written."
+        );
+
+        let track = Track {
+            path: path.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            track.read_front_cover().expect("Cover read."),
+            Some(cover),
+            "Cover should survive the replay gain write."
+        );
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn prefers_own_popm_rating() {
+        let path = temp_file("rating-players.mp3", &mp3());
+        let popularimeter = |email: &str, rating| {
+            Frame::Popularimeter(PopularimeterFrame::new(email.into(), rating, 0))
+        };
+
+        let mut id3v2 = Id3v2Tag::default();
+        id3v2.insert(popularimeter("other@example.org", 255));
+        id3v2.insert(popularimeter(POPM_EMAIL, 64));
+        id3v2
+            .save_to_path(&path, WriteOptions::default())
+            .expect("Frames written.");
+        assert_eq!(
+            rating(&path),
+            Some(2),
+            "Rating written by the app should win over other players."
+        );
+
+        let mut id3v2 = Id3v2Tag::default();
+        id3v2.insert(popularimeter("other@example.org", 255));
+        id3v2
+            .save_to_path(&path, WriteOptions::default())
+            .expect("Frame written.");
+        assert_eq!(
+            rating(&path),
+            Some(5),
+            "Rating of another player should be read without one of the app."
+        );
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -1,8 +1,23 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use eframe::egui::{self, Color32, Stroke, include_image};
 
-use crate::{player::GeneralMusicPlayer, playlist::PlaylistMode};
+use crate::{
+    player::{AbLoop, GeneralMusicPlayer, PlaybackSpeed, SleepTimer, SpeedMode},
+    playlist::PlaylistMode,
+    track::Bookmark,
+    ui::seek_bar::SeekBar,
+    waveform::Waveform,
+};
+
+/// Lengths offered by the sleep timer, in minutes.
+const SLEEP_TIMER_MINUTES: [u64; 6] = [5, 15, 30, 45, 60, 90];
+
+#[derive(Debug, Clone)]
+pub enum ControlPanelAction {
+    AddBookmark(String, Duration),
+    RemoveBookmark(i64),
+}
 
 #[derive(Clone)]
 struct State {
@@ -10,6 +25,7 @@ struct State {
     duration: f32,
     seek: bool,
     seek_while_playing: bool,
+    bookmark_name: String,
 }
 
 impl Default for State {
@@ -19,6 +35,7 @@ impl Default for State {
             duration: 0.0,
             seek: false,
             seek_while_playing: false,
+            bookmark_name: String::new(),
         }
     }
 }
@@ -35,11 +52,33 @@ impl State {
 
 pub struct ControlPanel<'a, T: GeneralMusicPlayer> {
     player: &'a mut T,
+
+    action: &'a mut Option<ControlPanelAction>,
+    bookmarks: &'a [Bookmark],
+    waveform: Option<&'a Waveform>,
 }
 
 impl<'a, T: GeneralMusicPlayer> ControlPanel<'a, T> {
-    pub fn new(player: &'a mut T) -> Self {
-        Self { player }
+    pub fn new(player: &'a mut T, action: &'a mut Option<ControlPanelAction>) -> Self {
+        Self {
+            player,
+
+            action,
+            bookmarks: &[],
+            waveform: None,
+        }
+    }
+
+    /// Bookmarks of the current track.
+    pub fn bookmarks(mut self, bookmarks: &'a [Bookmark]) -> Self {
+        self.bookmarks = bookmarks;
+        self
+    }
+
+    /// Waveform of the current track, drawn as the seek bar.
+    pub fn waveform(mut self, waveform: Option<&'a Waveform>) -> Self {
+        self.waveform = waveform;
+        self
     }
 }
 
@@ -82,155 +121,147 @@ impl<T: GeneralMusicPlayer> egui::Widget for ControlPanel<'_, T> {
             state.duration = 0.0;
         }
 
-        ui.horizontal(|ui| {
-            let slider_handle = egui::style::HandleShape::Rect { aspect_ratio: 0.5 };
-
-            ui.scope(|ui| {
-                ui.style_mut().visuals.widgets.inactive.weak_bg_fill = Color32::TRANSPARENT;
-                ui.style_mut().spacing.item_spacing = egui::Vec2::new(4., 4.);
-
-                let stop_button = ui.add_enabled(
-                    !self.player.is_stopped(),
-                    egui::Button::new(egui::Image::new(include_image!(
-                        "../../assets/icons/stop.svg"
-                    )))
-                    .stroke(Stroke::NONE),
-                );
-                let skip_backward_button = ui.add_enabled(
-                    !self.player.is_stopped(),
-                    egui::Button::new(egui::Image::new(include_image!(
-                        "../../assets/icons/skip-backward.svg"
-                    )))
-                    .stroke(Stroke::NONE),
-                );
-                let toggle_button = ui.add_enabled(
-                    !self.player.is_stopped(),
-                    egui::Button::new(
-                        if (self.player.is_paused() || self.player.is_stopped())
-                            && !(state.seek && state.seek_while_playing)
-                        {
-                            egui::Image::new(include_image!("../../assets/icons/play.svg"))
-                        } else {
-                            egui::Image::new(include_image!("../../assets/icons/pause.svg"))
-                        },
-                    )
-                    .stroke(Stroke::NONE),
-                );
-                let skip_forward_button = ui.add_enabled(
-                    !self.player.is_stopped(),
-                    egui::Button::new(egui::Image::new(include_image!(
-                        "../../assets/icons/skip-forward.svg"
-                    )))
-                    .stroke(Stroke::NONE),
-                );
-                let mode_button = ui.add(
-                    egui::Button::new(match self.player.playlist().mode() {
-                        PlaylistMode::NoRepeat => {
-                            egui::Image::new(include_image!("../../assets/icons/no-repeat.svg"))
-                        }
-                        PlaylistMode::Repeat => {
-                            egui::Image::new(include_image!("../../assets/icons/repeat.svg"))
-                        }
-                        PlaylistMode::RepeatSingle => {
-                            egui::Image::new(include_image!("../../assets/icons/repeat-one.svg"))
-                        }
-                        PlaylistMode::Random => {
-                            egui::Image::new(include_image!("../../assets/icons/shuffle.svg"))
-                        }
-                    })
-                    .stroke(Stroke::NONE),
-                );
+        let mut response = ui
+            .horizontal(|ui| {
+                let slider_handle = egui::style::HandleShape::Rect { aspect_ratio: 0.5 };
 
-                match (toggle_button.clicked(), self.player.is_paused()) {
-                    (true, true) => {
-                        self.player.play();
+                ui.scope(|ui| {
+                    ui.style_mut().visuals.widgets.inactive.weak_bg_fill = Color32::TRANSPARENT;
+                    ui.style_mut().spacing.item_spacing = egui::Vec2::new(4., 4.);
+
+                    let stop_button = ui.add_enabled(
+                        !self.player.is_stopped(),
+                        egui::Button::new(egui::Image::new(include_image!(
+                            "../../assets/icons/stop.svg"
+                        )))
+                        .stroke(Stroke::NONE),
+                    );
+                    let skip_backward_button = ui.add_enabled(
+                        !self.player.is_stopped(),
+                        egui::Button::new(egui::Image::new(include_image!(
+                            "../../assets/icons/skip-backward.svg"
+                        )))
+                        .stroke(Stroke::NONE),
+                    );
+                    let toggle_button = ui.add_enabled(
+                        !self.player.is_stopped(),
+                        egui::Button::new(
+                            if (self.player.is_paused() || self.player.is_stopped())
+                                && !(state.seek && state.seek_while_playing)
+                            {
+                                egui::Image::new(include_image!("../../assets/icons/play.svg"))
+                            } else {
+                                egui::Image::new(include_image!("../../assets/icons/pause.svg"))
+                            },
+                        )
+                        .stroke(Stroke::NONE),
+                    );
+                    let skip_forward_button = ui.add_enabled(
+                        !self.player.is_stopped(),
+                        egui::Button::new(egui::Image::new(include_image!(
+                            "../../assets/icons/skip-forward.svg"
+                        )))
+                        .stroke(Stroke::NONE),
+                    );
+                    let mode_button = ui.add(
+                        egui::Button::new(match self.player.playlist().mode() {
+                            PlaylistMode::NoRepeat => {
+                                egui::Image::new(include_image!("../../assets/icons/no-repeat.svg"))
+                            }
+                            PlaylistMode::Repeat => {
+                                egui::Image::new(include_image!("../../assets/icons/repeat.svg"))
+                            }
+                            PlaylistMode::RepeatSingle => egui::Image::new(include_image!(
+                                "../../assets/icons/repeat-one.svg"
+                            )),
+                            PlaylistMode::Random => {
+                                egui::Image::new(include_image!("../../assets/icons/shuffle.svg"))
+                            }
+                        })
+                        .stroke(Stroke::NONE),
+                    );
+
+                    if toggle_button.clicked() {
+                        self.player.toggle();
                     }
-                    (true, false) => {
-                        self.player.pause();
+                    if stop_button.clicked() {
+                        self.player.stop();
                     }
-                    _ => {}
-                }
-                if stop_button.clicked() {
-                    self.player.stop();
-                }
-                if skip_backward_button.clicked() {
-                    self.player.play_previous();
-                }
-                if skip_forward_button.clicked() {
-                    self.player.play_next();
-                }
-                if mode_button.clicked() {
-                    let playlist = self.player.playlist_mut();
-
-                    match playlist.mode() {
-                        PlaylistMode::NoRepeat => playlist.set_mode(PlaylistMode::Repeat),
-                        PlaylistMode::Repeat => playlist.set_mode(PlaylistMode::RepeatSingle),
-                        PlaylistMode::RepeatSingle => playlist.set_mode(PlaylistMode::Random),
-                        PlaylistMode::Random => playlist.set_mode(PlaylistMode::NoRepeat),
+                    if skip_backward_button.clicked() {
+                        self.player.play_previous();
                     }
-                }
-            });
-
-            ui.separator();
+                    if skip_forward_button.clicked() {
+                        self.player.play_next();
+                    }
+                    if mode_button.clicked() {
+                        let playlist = self.player.playlist_mut();
 
-            ui.scope(|ui| {
-                ui.spacing_mut().slider_width = 75.0;
-                // TODO: Custom?
-                let volume_slider = ui.add(
-                    egui::Slider::new(&mut state.volume, 0.0..=1.0)
-                        .handle_shape(slider_handle)
-                        .show_value(false)
-                        .step_by(0.02),
-                );
-                if volume_slider.dragged() {
-                    self.player.set_volume(state.volume);
-                } else {
-                    state.volume = self.player.volume();
-                }
-            });
+                        match playlist.mode() {
+                            PlaylistMode::NoRepeat => playlist.set_mode(PlaylistMode::Repeat),
+                            PlaylistMode::Repeat => playlist.set_mode(PlaylistMode::RepeatSingle),
+                            PlaylistMode::RepeatSingle => playlist.set_mode(PlaylistMode::Random),
+                            PlaylistMode::Random => playlist.set_mode(PlaylistMode::NoRepeat),
+                        }
+                    }
+                });
 
-            ui.separator();
-
-            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                // NOTE: Default to 1.0 so slider handle will be at the start.
-                let total_duration = if let Some(track) = &self.player.current_track() {
-                    track.duration.map(|t| t.as_secs_f32()).unwrap_or(1.0)
-                } else {
-                    1.0
-                };
-
-                // TODO: Handle unknown total duration.
-                if !self.player.is_stopped() {
-                    ui.ctx().request_repaint_after(Duration::from_millis(150));
-                    ui.label(format!(
-                        "{:02}:{:02} / {:02}:{:02}",
-                        state.duration.trunc() as u64 / 60,
-                        state.duration.trunc() as u64 % 60,
-                        total_duration.trunc() as u64 / 60,
-                        total_duration.trunc() as u64 % 60
-                    ));
-                } else {
-                    ui.label("--:-- / --:--");
-                }
+                ui.separator();
 
                 ui.scope(|ui| {
-                    ui.spacing_mut().slider_width = ui.available_width();
-                    let duration_slider = ui.add_enabled(
-                        !self.player.is_stopped(),
-                        egui::Slider::new(&mut state.duration, 0.0..=total_duration)
+                    ui.spacing_mut().slider_width = 75.0;
+                    // TODO: Custom?
+                    let volume_slider = ui.add(
+                        egui::Slider::new(&mut state.volume, 0.0..=1.0)
                             .handle_shape(slider_handle)
                             .show_value(false)
-                            .step_by(0.1),
+                            .step_by(0.02),
+                    );
+                    if volume_slider.dragged() {
+                        self.player.set_volume(state.volume);
+                    } else {
+                        state.volume = self.player.volume();
+                    }
+                });
+
+                ui.separator();
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    // NOTE: Default to 1.0 so slider handle will be at the start.
+                    let total_duration = if let Some(track) = &self.player.current_track() {
+                        track.duration.map(|t| t.as_secs_f32()).unwrap_or(1.0)
+                    } else {
+                        1.0
+                    };
+
+                    // TODO: Handle unknown total duration.
+                    if !self.player.is_stopped() {
+                        ui.ctx().request_repaint_after(Duration::from_millis(150));
+                        ui.label(format!(
+                            "{:02}:{:02} / {:02}:{:02}",
+                            state.duration.trunc() as u64 / 60,
+                            state.duration.trunc() as u64 % 60,
+                            total_duration.trunc() as u64 / 60,
+                            total_duration.trunc() as u64 % 60
+                        ));
+                    } else {
+                        ui.label("--:-- / --:--");
+                    }
+
+                    let seek_bar = ui.add_enabled(
+                        !self.player.is_stopped(),
+                        SeekBar::new(&mut state.duration, total_duration).waveform(self.waveform),
                     );
-                    if duration_slider.drag_started() {
+                    if seek_bar.drag_started() {
                         state.seek = true;
                         state.seek_while_playing = !self.player.is_paused();
                     }
-                    if duration_slider.dragged() {
+                    if seek_bar.dragged() {
                         self.player.pause();
                         self.player.seek(Duration::from_secs_f32(state.duration));
+                    } else if seek_bar.clicked() {
+                        self.player.seek(Duration::from_secs_f32(state.duration));
                     }
-                    if duration_slider.drag_stopped() {
+                    if seek_bar.drag_stopped() {
                         state.seek = false;
 
                         if state.seek_while_playing {
@@ -238,10 +269,195 @@ impl<T: GeneralMusicPlayer> egui::Widget for ControlPanel<'_, T> {
                         }
                     }
                 });
-            });
+            })
+            .response;
 
-            state.store(ui.ctx(), id);
-        })
-        .response
+        if !self.player.is_stopped() {
+            response |= ui
+                .horizontal(|ui| {
+                    tools_ui(ui, self.player, self.bookmarks, self.action, &mut state);
+                })
+                .response;
+        }
+
+        state.store(ui.ctx(), id);
+
+        response
+    }
+}
+
+/// Speed, A-B loop, bookmarks of the current track and the sleep timer.
+fn tools_ui<T: GeneralMusicPlayer>(
+    ui: &mut egui::Ui,
+    player: &mut T,
+    bookmarks: &[Bookmark],
+    action: &mut Option<ControlPanelAction>,
+    state: &mut State,
+) {
+    speed_ui(ui, player);
+
+    ui.separator();
+
+    ab_loop_ui(ui, player);
+
+    ui.separator();
+
+    bookmarks_ui(ui, player, bookmarks, action, state);
+
+    ui.separator();
+
+    sleep_timer_ui(ui, player);
+}
+
+fn speed_ui<T: GeneralMusicPlayer>(ui: &mut egui::Ui, player: &mut T) {
+    let mut speed = player.playback_speed();
+    let mut changed = false;
+
+    changed |= ui
+        .add(
+            egui::DragValue::new(&mut speed.rate)
+                .range(PlaybackSpeed::MIN_RATE..=PlaybackSpeed::MAX_RATE)
+                .speed(0.01)
+                .fixed_decimals(2)
+                .suffix("x"),
+        )
+        .on_hover_text("Playback speed")
+        .changed();
+
+    let mut keep_pitch = speed.mode == SpeedMode::TimeStretch;
+    if ui.checkbox(&mut keep_pitch, "Keep pitch").changed() {
+        speed.mode = if keep_pitch {
+            SpeedMode::TimeStretch
+        } else {
+            SpeedMode::Resample
+        };
+        changed = true;
+    }
+
+    if changed {
+        player.set_playback_speed(speed);
+    }
+}
+
+fn ab_loop_ui<T: GeneralMusicPlayer>(ui: &mut egui::Ui, player: &mut T) {
+    let ab_loop = player.ab_loop();
+    let position = player.position();
+
+    let point_label = |name: &str, point: Option<Duration>| {
+        point.map_or_else(
+            || name.to_owned(),
+            |point| format!("{name} {}", format_time(point)),
+        )
+    };
+
+    if ui
+        .selectable_label(ab_loop.a.is_some(), point_label("A", ab_loop.a))
+        .on_hover_text("Set the start of the loop")
+        .clicked()
+    {
+        player.set_ab_loop(AbLoop {
+            a: ab_loop.a.is_none().then_some(position),
+            ..ab_loop
+        });
+    }
+
+    if ui
+        .selectable_label(ab_loop.b.is_some(), point_label("B", ab_loop.b))
+        .on_hover_text("Set the end of the loop")
+        .clicked()
+    {
+        player.set_ab_loop(AbLoop {
+            b: ab_loop.b.is_none().then_some(position),
+            ..ab_loop
+        });
+    }
+
+    if ab_loop != AbLoop::default() && ui.button("Clear loop").clicked() {
+        player.set_ab_loop(AbLoop::default());
     }
 }
+
+fn bookmarks_ui<T: GeneralMusicPlayer>(
+    ui: &mut egui::Ui,
+    player: &mut T,
+    bookmarks: &[Bookmark],
+    action: &mut Option<ControlPanelAction>,
+    state: &mut State,
+) {
+    for bookmark in bookmarks {
+        let button = ui
+            .button(&bookmark.name)
+            .on_hover_text(format_time(bookmark.position));
+
+        if button.clicked() {
+            player.seek(bookmark.position);
+        }
+
+        button.context_menu(|ui| {
+            if ui.button("Remove bookmark").clicked() {
+                *action = Some(ControlPanelAction::RemoveBookmark(bookmark.id));
+            }
+        });
+    }
+
+    ui.add(
+        egui::TextEdit::singleline(&mut state.bookmark_name)
+            .hint_text("Bookmark name")
+            .desired_width(120.0),
+    );
+
+    if ui.button("Add bookmark").clicked() {
+        let position = player.position();
+        let name = match state.bookmark_name.trim() {
+            "" => format_time(position),
+            name => name.to_owned(),
+        };
+
+        *action = Some(ControlPanelAction::AddBookmark(name, position));
+        state.bookmark_name.clear();
+    }
+}
+
+fn sleep_timer_ui<T: GeneralMusicPlayer>(ui: &mut egui::Ui, player: &mut T) {
+    let label = match (player.sleep_timer(), player.sleep_timer_remaining()) {
+        (None, _) => "Sleep timer".to_owned(),
+        (Some(_), Some(remaining)) => format!("Sleep in {}", format_time(remaining)),
+        (Some(SleepTimer::AfterAlbum), None) => "Sleep after album".to_owned(),
+        (Some(_), None) => "Sleep after track".to_owned(),
+    };
+
+    ui.menu_button(label, |ui| {
+        ui.weak("Playback fades out over the last minute.");
+
+        for minutes in SLEEP_TIMER_MINUTES {
+            if ui.button(format!("{minutes} minutes")).clicked() {
+                player.set_sleep_timer(Some(SleepTimer::At(
+                    Instant::now() + Duration::from_secs(minutes * 60),
+                )));
+            }
+        }
+
+        ui.separator();
+
+        if ui.button("After current track").clicked() {
+            player.set_sleep_timer(Some(SleepTimer::AfterTrack));
+        }
+        if ui.button("After current album").clicked() {
+            player.set_sleep_timer(Some(SleepTimer::AfterAlbum));
+        }
+
+        if player.sleep_timer().is_some() {
+            ui.separator();
+
+            if ui.button("Turn off").clicked() {
+                player.set_sleep_timer(None);
+            }
+        }
+    });
+}
+
+fn format_time(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,369 @@
+use eframe::egui;
+
+use crate::config::{BUILTIN_EQUALIZER_PRESETS, EqualizerPreset, Settings};
+use crate::player::{EqualizerBand, EqualizerMode, FilterKind, GRAPHIC_FREQUENCIES};
+
+#[derive(Default, Clone)]
+struct State {
+    preset_name: String,
+    genre: String,
+    genre_preset: String,
+}
+
+impl State {
+    pub fn load(ctx: &egui::Context, id: egui::Id) -> Option<Self> {
+        ctx.data_mut(|d| d.get_persisted(id))
+    }
+
+    pub fn store(self, ctx: &egui::Context, id: egui::Id) {
+        ctx.data_mut(|d| d.insert_persisted(id, self));
+    }
+}
+
+pub struct EqualizerPanel<'a> {
+    settings: &'a mut Settings,
+}
+
+impl<'a> EqualizerPanel<'a> {
+    pub fn new(settings: &'a mut Settings) -> Self {
+        Self { settings }
+    }
+}
+
+impl egui::Widget for EqualizerPanel<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let id = ui.next_auto_id();
+        let mut state = State::load(ui.ctx(), id).unwrap_or_default();
+        let mut changed = false;
+
+        let mut response = ui
+            .vertical(|ui| {
+                changed |= ui
+                    .checkbox(&mut self.settings.equalizer_enabled, "Enable equalizer")
+                    .changed();
+
+                ui.add_enabled_ui(self.settings.equalizer_enabled, |ui| {
+                    changed |= presets_ui(ui, self.settings, &mut state);
+
+                    ui.separator();
+
+                    changed |= equalizer_ui(ui, self.settings);
+
+                    ui.separator();
+
+                    changed |= automatic_ui(ui, self.settings, &mut state);
+                });
+            })
+            .response;
+
+        state.store(ui.ctx(), id);
+
+        if changed {
+            response.mark_changed();
+        }
+
+        response
+    }
+}
+
+fn presets_ui(ui: &mut egui::Ui, settings: &mut Settings, state: &mut State) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Preset");
+
+        let selected = settings
+            .all_equalizer_presets()
+            .find(|preset| preset.equalizer == settings.equalizer)
+            .map_or("Custom", |preset| preset.name.as_str())
+            .to_owned();
+
+        let mut equalizer = None;
+        egui::ComboBox::from_id_salt("equalizer_preset")
+            .selected_text(&selected)
+            .show_ui(ui, |ui| {
+                for preset in settings.all_equalizer_presets() {
+                    if ui
+                        .selectable_label(preset.name == selected, &preset.name)
+                        .clicked()
+                    {
+                        equalizer = Some(preset.equalizer.clone());
+                    }
+                }
+            });
+
+        if let Some(equalizer) = equalizer {
+            settings.equalizer = equalizer;
+            changed = true;
+        }
+
+        if settings
+            .equalizer_presets
+            .iter()
+            .any(|preset| preset.name == selected)
+            && ui.button("Delete").clicked()
+        {
+            settings
+                .equalizer_presets
+                .retain(|preset| preset.name != selected);
+            changed = true;
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut state.preset_name)
+                .hint_text("Preset name")
+                .desired_width(160.0),
+        );
+
+        let name = state.preset_name.trim().to_owned();
+        let builtin = BUILTIN_EQUALIZER_PRESETS
+            .iter()
+            .any(|preset| preset.name == name);
+
+        if ui
+            .add_enabled(
+                !name.is_empty() && !builtin,
+                egui::Button::new("Save preset"),
+            )
+            .clicked()
+        {
+            settings
+                .equalizer_presets
+                .retain(|preset| preset.name != name);
+            settings.equalizer_presets.push(EqualizerPreset {
+                name,
+                equalizer: settings.equalizer.clone(),
+            });
+            state.preset_name.clear();
+            changed = true;
+        }
+    });
+
+    changed
+}
+
+fn equalizer_ui(ui: &mut egui::Ui, settings: &mut Settings) -> bool {
+    let mut changed = false;
+    let equalizer = &mut settings.equalizer;
+
+    ui.horizontal(|ui| {
+        let graphic = matches!(equalizer.mode, EqualizerMode::Graphic(_));
+
+        if ui.selectable_label(graphic, "Graphic").clicked() && !graphic {
+            equalizer.mode = EqualizerMode::Graphic([0.0; GRAPHIC_FREQUENCIES.len()]);
+            changed = true;
+        }
+        if ui.selectable_label(!graphic, "Parametric").clicked() && graphic {
+            // NOTE: Keep the current curve, graphic bands are peaking filters.
+            equalizer.mode = EqualizerMode::Parametric(
+                equalizer
+                    .bands()
+                    .into_iter()
+                    .filter(|band| band.gain != 0.0)
+                    .collect(),
+            );
+            changed = true;
+        }
+
+        ui.separator();
+
+        ui.label("Preamp");
+        changed |= ui
+            .add(
+                egui::DragValue::new(&mut equalizer.preamp)
+                    .range(-24.0..=12.0)
+                    .speed(0.1)
+                    .suffix(" dB"),
+            )
+            .changed();
+    });
+
+    match &mut equalizer.mode {
+        EqualizerMode::Graphic(gains) => {
+            ui.horizontal(|ui| {
+                for (gain, frequency) in gains.iter_mut().zip(GRAPHIC_FREQUENCIES) {
+                    ui.vertical(|ui| {
+                        let hover_text = format!("{gain:+.1} dB");
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(gain, -12.0..=12.0)
+                                    .vertical()
+                                    .step_by(0.5)
+                                    .show_value(false),
+                            )
+                            .on_hover_text(hover_text)
+                            .changed();
+
+                        ui.small(if frequency >= 1_000.0 {
+                            format!("{}k", frequency / 1_000.0)
+                        } else {
+                            format!("{frequency}")
+                        });
+                    });
+                }
+            });
+        }
+        EqualizerMode::Parametric(bands) => {
+            changed |= parametric_ui(ui, bands);
+        }
+    }
+
+    changed
+}
+
+fn parametric_ui(ui: &mut egui::Ui, bands: &mut Vec<EqualizerBand>) -> bool {
+    let mut changed = false;
+    let mut remove = None;
+
+    egui::Grid::new("equalizer_bands")
+        .striped(true)
+        .show(ui, |ui| {
+            for (index, band) in bands.iter_mut().enumerate() {
+                egui::ComboBox::from_id_salt(("equalizer_band_kind", index))
+                    .selected_text(filter_kind_label(band.kind))
+                    .show_ui(ui, |ui| {
+                        for kind in [
+                            FilterKind::Peaking,
+                            FilterKind::LowShelf,
+                            FilterKind::HighShelf,
+                        ] {
+                            changed |= ui
+                                .selectable_value(&mut band.kind, kind, filter_kind_label(kind))
+                                .changed();
+                        }
+                    });
+
+                let frequency_speed = band.frequency * 0.01;
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut band.frequency)
+                            .range(20.0..=20_000.0)
+                            .speed(frequency_speed)
+                            .suffix(" Hz"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut band.gain)
+                            .range(-24.0..=24.0)
+                            .speed(0.1)
+                            .suffix(" dB"),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut band.q)
+                            .range(0.1..=10.0)
+                            .speed(0.01)
+                            .prefix("Q "),
+                    )
+                    .changed();
+
+                if ui.button("Remove").clicked() {
+                    remove = Some(index);
+                }
+
+                ui.end_row();
+            }
+        });
+
+    if let Some(index) = remove {
+        bands.remove(index);
+        changed = true;
+    }
+
+    if ui.button("Add band").clicked() {
+        bands.push(EqualizerBand {
+            kind: FilterKind::Peaking,
+            frequency: 1_000.0,
+            gain: 0.0,
+            q: 1.0,
+        });
+        changed = true;
+    }
+
+    changed
+}
+
+fn automatic_ui(ui: &mut egui::Ui, settings: &mut Settings, state: &mut State) -> bool {
+    let mut changed = false;
+    let names = settings.equalizer_preset_names();
+
+    ui.strong("Automatic presets");
+    ui.weak("Tracks get their presets from the track list context menu.");
+
+    let mut remove = None;
+    egui::Grid::new("equalizer_genres")
+        .striped(true)
+        .show(ui, |ui| {
+            for (genre, name) in &settings.genre_equalizer_presets {
+                ui.label(genre);
+                ui.label(name);
+                if ui.button("Remove").clicked() {
+                    remove = Some(genre.clone());
+                }
+                ui.end_row();
+            }
+        });
+
+    if let Some(genre) = remove {
+        settings.genre_equalizer_presets.remove(&genre);
+        changed = true;
+    }
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut state.genre)
+                .hint_text("Genre")
+                .desired_width(120.0),
+        );
+
+        egui::ComboBox::from_id_salt("equalizer_genre_preset")
+            .selected_text(&state.genre_preset)
+            .show_ui(ui, |ui| {
+                for name in &names {
+                    ui.selectable_value(&mut state.genre_preset, name.clone(), name);
+                }
+            });
+
+        let genre = state.genre.trim().to_lowercase();
+
+        if ui
+            .add_enabled(
+                !genre.is_empty() && names.contains(&state.genre_preset),
+                egui::Button::new("Add"),
+            )
+            .clicked()
+        {
+            settings
+                .genre_equalizer_presets
+                .insert(genre, state.genre_preset.clone());
+            state.genre.clear();
+            changed = true;
+        }
+    });
+
+    if !settings.track_equalizer_presets.is_empty() {
+        ui.label(format!(
+            "{} tracks have their own preset.",
+            settings.track_equalizer_presets.len()
+        ));
+
+        if ui.button("Clear track presets").clicked() {
+            settings.track_equalizer_presets.clear();
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+fn filter_kind_label(kind: FilterKind) -> &'static str {
+    match kind {
+        FilterKind::Peaking => "Peaking",
+        FilterKind::LowShelf => "Low shelf",
+        FilterKind::HighShelf => "High shelf",
+    }
+}
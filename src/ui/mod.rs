@@ -1,3 +1,9 @@
 pub mod control_panel;
 pub mod cover_art;
+pub mod equalizer;
+pub mod notifications;
+pub mod seek_bar;
+pub mod settings;
+pub mod track_info;
 pub mod track_list;
+pub mod visualizer;
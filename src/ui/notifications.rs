@@ -0,0 +1,59 @@
+use eframe::egui;
+
+/// Number of messages shown at once, the rest are counted.
+const MAX_SHOWN: usize = 3;
+
+/// Error messages shown until dismissed, the oldest first.
+///
+/// The response is marked as changed when a message is dismissed.
+pub struct Notifications<'a> {
+    messages: &'a mut Vec<String>,
+}
+
+impl<'a> Notifications<'a> {
+    pub fn new(messages: &'a mut Vec<String>) -> Self {
+        Self { messages }
+    }
+}
+
+impl egui::Widget for Notifications<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let mut dismissed = None;
+        let mut dismiss_all = false;
+
+        let mut response = ui
+            .vertical(|ui| {
+                for (index, message) in self.messages.iter().enumerate().take(MAX_SHOWN) {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+                            dismissed = Some(index);
+                        }
+
+                        ui.colored_label(ui.visuals().warn_fg_color, "⚠");
+                        ui.label(message);
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    if self.messages.len() > MAX_SHOWN {
+                        ui.weak(format!("and {} more", self.messages.len() - MAX_SHOWN));
+                    }
+
+                    if self.messages.len() > 1 && ui.small_button("Dismiss all").clicked() {
+                        dismiss_all = true;
+                    }
+                });
+            })
+            .response;
+
+        if dismiss_all {
+            self.messages.clear();
+            response.mark_changed();
+        } else if let Some(index) = dismissed {
+            self.messages.remove(index);
+            response.mark_changed();
+        }
+
+        response
+    }
+}
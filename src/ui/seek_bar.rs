@@ -0,0 +1,117 @@
+use eframe::egui::{self, Pos2, Rect, Sense, Stroke, Vec2};
+
+use crate::waveform::Waveform;
+
+const HEIGHT: f32 = 28.0;
+
+/// Height of the bar drawn while there is no waveform yet.
+const TRACK_HEIGHT: f32 = 4.0;
+
+/// Seek bar drawn as the waveform of the track, the played part is highlighted.
+///
+/// Clicking or dragging moves `position`, the response is marked as changed when it does.
+pub struct SeekBar<'a> {
+    position: &'a mut f32,
+    total: f32,
+    waveform: Option<&'a Waveform>,
+}
+
+impl<'a> SeekBar<'a> {
+    pub fn new(position: &'a mut f32, total: f32) -> Self {
+        Self {
+            position,
+            total,
+            waveform: None,
+        }
+    }
+
+    /// Waveform of the track, a plain bar is drawn without it.
+    pub fn waveform(mut self, waveform: Option<&'a Waveform>) -> Self {
+        self.waveform = waveform;
+        self
+    }
+}
+
+impl egui::Widget for SeekBar<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let size = Vec2::new(ui.available_width(), HEIGHT);
+        let (rect, mut response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+
+        if (response.clicked() || response.dragged())
+            && let Some(pointer) = response.interact_pointer_pos()
+        {
+            let fraction = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+
+            *self.position = fraction * self.total;
+            response.mark_changed();
+        }
+
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let visuals = ui.visuals();
+        let played_color = visuals.selection.bg_fill;
+        let unplayed_color = visuals.widgets.inactive.bg_fill;
+        let column_color = |x: f32, played_x: f32| {
+            if x <= played_x {
+                played_color
+            } else {
+                unplayed_color
+            }
+        };
+
+        let painter = ui.painter_at(rect);
+        let fraction = if self.total > 0.0 {
+            (*self.position / self.total).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let played_x = rect.left() + rect.width() * fraction;
+        let center = rect.center().y;
+        let half_height = rect.height() / 2.0;
+
+        if let Some(waveform) = self.waveform.filter(|waveform| !waveform.is_empty()) {
+            let columns = (rect.width() / 2.0).max(1.0) as usize;
+            let width = rect.width() / columns as f32;
+
+            for (column, (peak, rms)) in waveform.columns(columns).into_iter().enumerate() {
+                let x = rect.left() + (column as f32 + 0.5) * width;
+                let color = column_color(x, played_x);
+                // NOTE: Keep silent parts visible so that the bar still reads as a bar.
+                let peak = (peak * half_height).max(0.5);
+                let rms = (rms * half_height).max(0.5);
+
+                painter.vline(
+                    x,
+                    center - peak..=center + peak,
+                    Stroke::new(width, color.gamma_multiply(0.5)),
+                );
+                painter.vline(x, center - rms..=center + rms, Stroke::new(width, color));
+            }
+        } else {
+            let track =
+                Rect::from_center_size(rect.center(), Vec2::new(rect.width(), TRACK_HEIGHT));
+
+            painter.rect_filled(track, TRACK_HEIGHT / 2.0, unplayed_color);
+            painter.rect_filled(
+                Rect::from_min_max(track.min, Pos2::new(played_x, track.max.y)),
+                TRACK_HEIGHT / 2.0,
+                played_color,
+            );
+        }
+
+        let playhead_color = if response.hovered() || response.dragged() {
+            visuals.strong_text_color()
+        } else {
+            visuals.text_color()
+        };
+        painter.vline(
+            played_x,
+            rect.y_range(),
+            Stroke::new(2.0, playhead_color.gamma_multiply(0.8)),
+        );
+
+        response
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use eframe::egui;
+
+use crate::config::{LibraryFolder, Settings};
+use crate::player::{Crossfade, CrossfadeCurve, ReplayGainMode};
+
+pub struct SettingsPanel<'a> {
+    settings: &'a mut Settings,
+    output_devices: &'a [String],
+}
+
+impl<'a> SettingsPanel<'a> {
+    pub fn new(settings: &'a mut Settings, output_devices: &'a [String]) -> Self {
+        Self {
+            settings,
+            output_devices,
+        }
+    }
+}
+
+impl egui::Widget for SettingsPanel<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let mut changed = false;
+
+        let mut response = ui
+            .vertical(|ui| {
+                changed |= output_ui(ui, self.settings, self.output_devices);
+
+                ui.separator();
+
+                changed |= playback_ui(ui, self.settings);
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("ReplayGain");
+
+                    egui::ComboBox::from_id_salt("replay_gain")
+                        .selected_text(match self.settings.replay_gain {
+                            ReplayGainMode::Off => "Off",
+                            ReplayGainMode::Track => "Track",
+                            ReplayGainMode::Album => "Album",
+                        })
+                        .show_ui(ui, |ui| {
+                            for (mode, label) in [
+                                (ReplayGainMode::Off, "Off"),
+                                (ReplayGainMode::Track, "Track"),
+                                (ReplayGainMode::Album, "Album"),
+                            ] {
+                                changed |= ui
+                                    .selectable_value(&mut self.settings.replay_gain, mode, label)
+                                    .changed();
+                            }
+                        });
+                });
+
+                changed |= ui
+                    .checkbox(
+                        &mut self.settings.write_replay_gain_tags,
+                        "Write analyzed replay gain to file tags",
+                    )
+                    .changed();
+
+                changed |= ui
+                    .checkbox(
+                        &mut self.settings.write_rating_tags,
+                        "Write ratings to file tags",
+                    )
+                    .changed();
+
+                ui.separator();
+
+                changed |= library_ui(ui, self.settings);
+            })
+            .response;
+
+        if changed {
+            response.mark_changed();
+        }
+
+        response
+    }
+}
+
+fn output_ui(ui: &mut egui::Ui, settings: &mut Settings, output_devices: &[String]) -> bool {
+    let mut changed = false;
+
+    ui.strong("Output");
+
+    egui::ComboBox::from_id_salt("output_device")
+        .selected_text(
+            settings
+                .output_device
+                .as_deref()
+                .unwrap_or("System default"),
+        )
+        .show_ui(ui, |ui| {
+            changed |= ui
+                .selectable_value(&mut settings.output_device, None, "System default")
+                .changed();
+
+            for device in output_devices {
+                changed |= ui
+                    .selectable_value(&mut settings.output_device, Some(device.clone()), device)
+                    .changed();
+            }
+        });
+
+    changed
+}
+
+fn playback_ui(ui: &mut egui::Ui, settings: &mut Settings) -> bool {
+    let mut changed = false;
+
+    ui.strong("Playback");
+
+    let mut crossfade_enabled = settings.crossfade.is_some();
+
+    if ui
+        .checkbox(&mut crossfade_enabled, "Crossfade between tracks")
+        .changed()
+    {
+        settings.crossfade = crossfade_enabled.then_some(Crossfade {
+            duration: Duration::from_secs(5),
+            curve: CrossfadeCurve::EqualPower,
+        });
+        changed = true;
+    }
+
+    if let Some(crossfade) = settings.crossfade.as_mut() {
+        let mut duration = crossfade.duration.as_secs_f32();
+
+        ui.horizontal(|ui| {
+            ui.label("Length");
+
+            if ui
+                .add(
+                    egui::Slider::new(&mut duration, 1.0..=12.0)
+                        .suffix(" s")
+                        .step_by(0.5),
+                )
+                .changed()
+            {
+                crossfade.duration = Duration::from_secs_f32(duration);
+                changed = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Curve");
+
+            egui::ComboBox::from_id_salt("crossfade_curve")
+                .selected_text(match crossfade.curve {
+                    CrossfadeCurve::Linear => "Linear",
+                    CrossfadeCurve::EqualPower => "Equal power",
+                })
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut crossfade.curve, CrossfadeCurve::Linear, "Linear")
+                        .changed();
+                    changed |= ui
+                        .selectable_value(
+                            &mut crossfade.curve,
+                            CrossfadeCurve::EqualPower,
+                            "Equal power",
+                        )
+                        .changed();
+                });
+        });
+
+        ui.weak("Consecutive tracks of the same album are never crossfaded.");
+    }
+
+    ui.horizontal(|ui| {
+        let mut fade = settings.transport_fade.as_millis() as u64;
+
+        ui.label("Fade on pause, stop and seek");
+
+        if ui
+            .add(egui::Slider::new(&mut fade, 0..=500).suffix(" ms"))
+            .changed()
+        {
+            settings.transport_fade = Duration::from_millis(fade);
+            changed = true;
+        }
+    });
+
+    changed
+}
+
+fn library_ui(ui: &mut egui::Ui, settings: &mut Settings) -> bool {
+    let mut changed = false;
+    let mut removed = None;
+
+    ui.strong("Library folders");
+
+    // NOTE: Removing a folder deletes the plays, ratings and bookmarks of its tracks, which
+    // cannot be undone, so it has to be confirmed.
+    let removing_id = egui::Id::new("removing_library_folder");
+    let mut removing = ui.data_mut(|data| data.get_temp::<PathBuf>(removing_id));
+
+    for (idx, folder) in settings.library_folders.iter_mut().enumerate() {
+        ui.push_id(idx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(folder.path.display().to_string());
+
+                if removing.as_ref() == Some(&folder.path) {
+                    ui.label("Remove its tracks with their plays and ratings?");
+
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(idx);
+                        removing = None;
+                    }
+                    if ui.small_button("Cancel").clicked() {
+                        removing = None;
+                    }
+                } else if ui
+                    .small_button("✖")
+                    .on_hover_text("Remove the folder and its tracks from the library")
+                    .clicked()
+                {
+                    removing = Some(folder.path.clone());
+                }
+            });
+
+            changed |= ui
+                .checkbox(&mut folder.follow_symlinks, "Follow symbolic links")
+                .changed();
+            changed |= patterns_ui(ui, "Include", &mut folder.include);
+            changed |= patterns_ui(ui, "Exclude", &mut folder.exclude);
+        });
+    }
+
+    if let Some(idx) = removed {
+        settings.library_folders.remove(idx);
+        changed = true;
+    }
+
+    ui.data_mut(|data| match removing {
+        Some(path) => data.insert_temp(removing_id, path),
+        None => data.remove::<PathBuf>(removing_id),
+    });
+
+    let id = egui::Id::new("new_library_folder");
+    let mut path = ui
+        .data_mut(|data| data.get_temp::<String>(id))
+        .unwrap_or_default();
+
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut path).hint_text("Path of a folder"));
+
+        let folder = PathBuf::from(path.trim());
+        let addable = folder.is_dir()
+            && !settings
+                .library_folders
+                .iter()
+                .any(|existing| existing.path == folder);
+
+        if ui
+            .add_enabled(addable, egui::Button::new("Add folder"))
+            .clicked()
+        {
+            settings.library_folders.push(LibraryFolder::new(folder));
+            path.clear();
+            changed = true;
+        }
+    });
+
+    ui.data_mut(|data| data.insert_temp(id, path));
+
+    changed
+}
+
+/// Edits glob patterns as a single line separated by `;`, applied once the line loses focus.
+fn patterns_ui(ui: &mut egui::Ui, label: &str, patterns: &mut Vec<String>) -> bool {
+    // NOTE: The line is kept while it is edited, so that each keystroke does not save the
+    // settings and rescan the library.
+    let id = ui.id().with(label);
+    let mut text = ui
+        .data_mut(|data| data.get_temp::<String>(id))
+        .unwrap_or_else(|| patterns.join(";"));
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label(label);
+
+        let response = ui
+            .add(egui::TextEdit::singleline(&mut text).hint_text("*.flac;Podcasts/**"))
+            .on_hover_text("Glob patterns relative to the folder, separated by ;");
+
+        if response.lost_focus() {
+            let edited: Vec<_> = text
+                .split(';')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(ToOwned::to_owned)
+                .collect();
+
+            changed = edited != *patterns;
+            *patterns = edited;
+        }
+
+        ui.data_mut(|data| {
+            if response.has_focus() {
+                data.insert_temp(id, text);
+            } else {
+                data.remove::<String>(id);
+            }
+        });
+    });
+
+    changed
+}
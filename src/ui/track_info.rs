@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use eframe::egui;
+
+use crate::track::Track;
+
+/// Tags and audio properties of a track, for the track info dialog.
+pub struct TrackInfo<'a> {
+    track: &'a Track,
+}
+
+impl<'a> TrackInfo<'a> {
+    pub fn new(track: &'a Track) -> Self {
+        Self { track }
+    }
+}
+
+impl egui::Widget for TrackInfo<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let track = self.track;
+        let properties = &track.properties;
+        let gain = |gain: Option<f32>, peak: Option<f32>| {
+            gain.map(|gain| match peak {
+                Some(peak) => format!("{gain:+.2} dB, peak {peak:.6}"),
+                None => format!("{gain:+.2} dB"),
+            })
+        };
+
+        let rows = [
+            ("Title", track.title.clone()),
+            ("Artist", track.artist.clone()),
+            ("Album", track.album.clone()),
+            ("Album artist", track.album_artist.clone()),
+            ("Genre", track.genre.clone()),
+            (
+                "Track",
+                number_of(track.track.as_deref(), track.track_total.as_deref()),
+            ),
+            (
+                "Disc",
+                number_of(track.disc.as_deref(), track.disc_total.as_deref()),
+            ),
+            ("Duration", track.duration.map(format_duration)),
+            ("Codec", properties.codec.clone()),
+            ("Bitrate", properties.bitrate.map(format_bitrate)),
+            (
+                "Sample rate",
+                properties.sample_rate.map(format_sample_rate),
+            ),
+            (
+                "Bit depth",
+                properties.bit_depth.map(|v| format!("{v} bit")),
+            ),
+            ("Channels", properties.channels.map(format_channels)),
+            ("File size", properties.file_size.map(format_file_size)),
+            (
+                "Track gain",
+                gain(track.replay_gain.track_gain, track.replay_gain.track_peak),
+            ),
+            (
+                "Album gain",
+                gain(track.replay_gain.album_gain, track.replay_gain.album_peak),
+            ),
+            ("Path", Some(track.path.display().to_string())),
+        ];
+
+        egui::Grid::new("track_info")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                for (name, value) in rows {
+                    ui.strong(name);
+                    ui.label(value.as_deref().unwrap_or("-"));
+                    ui.end_row();
+                }
+            })
+            .response
+    }
+}
+
+fn number_of(number: Option<&str>, total: Option<&str>) -> Option<String> {
+    match (number?, total) {
+        (number, Some(total)) => Some(format!("{number} of {total}")),
+        (number, None) => Some(number.to_owned()),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn format_bitrate(kbps: u32) -> String {
+    format!("{kbps} kbps")
+}
+
+pub fn format_sample_rate(hz: u32) -> String {
+    if hz.is_multiple_of(1_000) {
+        format!("{} kHz", hz / 1_000)
+    } else {
+        format!("{:.1} kHz", f64::from(hz) / 1_000.0)
+    }
+}
+
+pub fn format_channels(channels: u8) -> String {
+    match channels {
+        1 => String::from("Mono"),
+        2 => String::from("Stereo"),
+        channels => format!("{channels} channels"),
+    }
+}
+
+pub fn format_file_size(bytes: u64) -> String {
+    const MIB: u64 = 1_024 * 1_024;
+
+    if bytes >= MIB {
+        format!("{:.1} MB", bytes as f64 / MIB as f64)
+    } else {
+        format!("{} KB", bytes.div_ceil(1_024))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{
+        format_bitrate, format_channels, format_duration, format_file_size, format_sample_rate,
+        number_of,
+    };
+
+    #[test]
+    fn formats_properties() {
+        assert_eq!(
+            format_duration(Duration::from_secs(3_725)),
+            "62:05",
+            "Duration in minutes."
+        );
+        assert_eq!(format_bitrate(320), "320 kbps", "Bitrate in kbps.");
+        assert_eq!(format_sample_rate(48_000), "48 kHz", "Whole sample rate.");
+        assert_eq!(
+            format_sample_rate(44_100),
+            "44.1 kHz",
+            "Fractional sample rate."
+        );
+        assert_eq!(format_channels(1), "Mono", "Single channel.");
+        assert_eq!(format_channels(2), "Stereo", "Two channels.");
+        assert_eq!(format_channels(6), "6 channels", "Surround channels.");
+        assert_eq!(format_file_size(1), "1 KB", "Small file rounded up.");
+        assert_eq!(
+            format_file_size(5 * 1_024 * 1_024 + 512 * 1_024),
+            "5.5 MB",
+            "Large file."
+        );
+        assert_eq!(
+            number_of(Some("3"), Some("12")).as_deref(),
+            Some("3 of 12"),
+            "Track of total."
+        );
+        assert_eq!(
+            number_of(Some("3"), None).as_deref(),
+            Some("3"),
+            "Track without total."
+        );
+        assert_eq!(number_of(None, Some("12")), None, "Total without track.");
+    }
+}
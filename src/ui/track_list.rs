@@ -1,8 +1,14 @@
+use std::cmp::Ordering;
+
 use eframe::egui;
 use eframe::egui::{Id, include_image};
-use egui_extras::{Column, TableBuilder};
+use egui_extras::{Column, TableBuilder, TableRow};
 
-use crate::track::Track;
+use crate::database::SearchResults;
+use crate::track::{AudioProperties, MAX_RATING, Track};
+use crate::ui::track_info::{
+    format_bitrate, format_channels, format_file_size, format_sample_rate,
+};
 
 pub type TrackIndex = usize;
 
@@ -12,6 +18,14 @@ pub enum TrackListAction {
     Select(TrackIndex),
 
     SendToCurrentPlaylist(Vec<TrackIndex>),
+    /// `None` removes the preset of the tracks.
+    SetEqualizerPreset(Vec<TrackIndex>, Option<String>),
+    /// `None` leaves the tracks unrated.
+    SetRating(Vec<TrackIndex>, Option<u8>),
+    SetLoved(Vec<TrackIndex>, bool),
+    ShowInfo(TrackIndex),
+    /// The search typed in, given until its results are handed over with `search_results`.
+    Search(String),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -23,6 +37,219 @@ pub enum TrackListIndicator {
 #[derive(Debug, Clone)]
 pub enum TrackListContextMenu {
     SendToCurrentPlaylist,
+    /// Lists the given equalizer preset names.
+    EqualizerPreset(Vec<String>),
+    /// Rating and loved flag of the track, also enables their keyboard shortcuts.
+    Rating,
+    TrackInfo,
+}
+
+/// Syntax of the search, shown when hovering the search field.
+const SEARCH_HELP: &str = "Search tags by the start of their words, such as \
+    artist:radiohead year:>2000 genre:\"post rock\" -live\n\n\
+    Tags: title, artist, album, albumartist, genre\n\
+    Numbers: year, rating, plays, compared with >, >=, <, <= or a range such as 1990..1999\n\
+    Quoted words match as a phrase, words starting with - leave tracks out.";
+
+/// Keys setting the rating of the selected track along with Ctrl, 0 clears it.
+const RATING_KEYS: [egui::Key; MAX_RATING as usize + 1] = [
+    egui::Key::Num0,
+    egui::Key::Num1,
+    egui::Key::Num2,
+    egui::Key::Num3,
+    egui::Key::Num4,
+    egui::Key::Num5,
+];
+
+/// Audio property, year and listening columns that can be shown next to the tag columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtraColumn {
+    Codec,
+    Bitrate,
+    SampleRate,
+    BitDepth,
+    Channels,
+    FileSize,
+    Year,
+    PlayCount,
+    SkipCount,
+    LastPlayed,
+    Rating,
+    Loved,
+}
+
+impl ExtraColumn {
+    const ALL: [Self; 12] = [
+        Self::Codec,
+        Self::Bitrate,
+        Self::SampleRate,
+        Self::BitDepth,
+        Self::Channels,
+        Self::FileSize,
+        Self::Year,
+        Self::PlayCount,
+        Self::SkipCount,
+        Self::LastPlayed,
+        Self::Rating,
+        Self::Loved,
+    ];
+
+    /// Name of the column where it is stored.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Codec => "codec",
+            Self::Bitrate => "bitrate",
+            Self::SampleRate => "sample_rate",
+            Self::BitDepth => "bit_depth",
+            Self::Channels => "channels",
+            Self::FileSize => "file_size",
+            Self::Year => "year",
+            Self::PlayCount => "play_count",
+            Self::SkipCount => "skip_count",
+            Self::LastPlayed => "last_played",
+            Self::Rating => "rating",
+            Self::Loved => "loved",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|column| column.key() == key)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Codec => "Codec",
+            Self::Bitrate => "Bitrate",
+            Self::SampleRate => "Sample rate",
+            Self::BitDepth => "Bit depth",
+            Self::Channels => "Channels",
+            Self::FileSize => "File size",
+            Self::Year => "Year",
+            Self::PlayCount => "Plays",
+            Self::SkipCount => "Skips",
+            Self::LastPlayed => "Last played",
+            Self::Rating => "Rating",
+            Self::Loved => "Loved",
+        }
+    }
+
+    fn value(self, track: &Track) -> Option<String> {
+        let properties = &track.properties;
+
+        match self {
+            Self::Codec => properties.codec.clone(),
+            Self::Bitrate => properties.bitrate.map(format_bitrate),
+            Self::SampleRate => properties.sample_rate.map(format_sample_rate),
+            Self::BitDepth => properties.bit_depth.map(|v| format!("{v} bit")),
+            Self::Channels => properties.channels.map(format_channels),
+            Self::FileSize => properties.file_size.map(format_file_size),
+            Self::Year => track.year.map(|year| year.to_string()),
+            Self::PlayCount => Some(track.play_count.to_string()),
+            Self::SkipCount => Some(track.skip_count.to_string()),
+            Self::LastPlayed => track
+                .last_played
+                .map(|time| time.format("%Y-%m-%d %H:%M").to_string()),
+            Self::Rating => track.rating.map(format_rating),
+            Self::Loved => track.loved.then(|| String::from("♥")),
+        }
+    }
+
+    fn compare(self, a: &Track, b: &Track) -> Ordering {
+        let (a_properties, b_properties) = (&a.properties, &b.properties);
+
+        match self {
+            Self::Codec => a_properties.codec.cmp(&b_properties.codec),
+            Self::Bitrate => a_properties.bitrate.cmp(&b_properties.bitrate),
+            Self::SampleRate => a_properties.sample_rate.cmp(&b_properties.sample_rate),
+            Self::BitDepth => a_properties.bit_depth.cmp(&b_properties.bit_depth),
+            Self::Channels => a_properties.channels.cmp(&b_properties.channels),
+            Self::FileSize => a_properties.file_size.cmp(&b_properties.file_size),
+            Self::Year => a.year.cmp(&b.year),
+            Self::PlayCount => a.play_count.cmp(&b.play_count),
+            Self::SkipCount => a.skip_count.cmp(&b.skip_count),
+            Self::LastPlayed => a.last_played.cmp(&b.last_played),
+            Self::Rating => a.rating.cmp(&b.rating),
+            Self::Loved => a.loved.cmp(&b.loved),
+        }
+    }
+}
+
+/// Order of the rows by one of the extra columns, the library order otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sort {
+    column: ExtraColumn,
+    descending: bool,
+}
+
+impl Sort {
+    /// Sorts by `column` ascending, then descending, then not at all.
+    fn toggle(sort: Option<Self>, column: ExtraColumn) -> Option<Self> {
+        match sort {
+            Some(sort) if sort.column == column && sort.descending => None,
+            Some(sort) if sort.column == column => Some(Self {
+                column,
+                descending: true,
+            }),
+            _ => Some(Self {
+                column,
+                descending: false,
+            }),
+        }
+    }
+
+    fn apply(self, tracks: &mut [(TrackIndex, &Track)]) {
+        tracks.sort_by(|(_, a), (_, b)| {
+            if self.descending {
+                self.column.compare(b, a)
+            } else {
+                self.column.compare(a, b)
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quality {
+    #[default]
+    Any,
+    Lossless,
+    Lossy,
+}
+
+/// Filters on the audio properties, applied on top of the search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PropertiesFilter {
+    pub quality: Quality,
+    /// Only tracks with a bitrate under this many kbps.
+    pub max_bitrate: Option<u32>,
+}
+
+impl PropertiesFilter {
+    const DEFAULT_MAX_BITRATE: u32 = 192;
+
+    fn is_active(self) -> bool {
+        self != Self::default()
+    }
+
+    fn matches(self, properties: &AudioProperties) -> bool {
+        let quality = match self.quality {
+            Quality::Any => true,
+            Quality::Lossless => properties.is_lossless() == Some(true),
+            Quality::Lossy => properties.is_lossless() == Some(false),
+        };
+
+        quality
+            && self
+                .max_bitrate
+                .is_none_or(|max| properties.bitrate.is_some_and(|bitrate| bitrate < max))
+    }
+}
+
+/// Extra columns and filter of the track lists, kept with the settings.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TrackListSettings {
+    pub columns: Vec<ExtraColumn>,
+    pub filter: PropertiesFilter,
 }
 
 #[derive(Default, Clone)]
@@ -30,6 +257,7 @@ struct State {
     scroll_position: f32,
     search_input: String,
     selected_index: Option<TrackIndex>,
+    sort: Option<Sort>,
 }
 
 impl State {
@@ -50,6 +278,8 @@ pub struct TrackList<'a> {
     indicator: Option<TrackListIndicator>,
 
     context_menu: Vec<TrackListContextMenu>,
+    search_results: Option<&'a SearchResults>,
+    settings: Option<&'a mut TrackListSettings>,
 }
 
 impl<'a> TrackList<'a> {
@@ -67,6 +297,8 @@ impl<'a> TrackList<'a> {
             indicator,
 
             context_menu: Vec::new(),
+            search_results: None,
+            settings: None,
         }
     }
 
@@ -74,277 +306,699 @@ impl<'a> TrackList<'a> {
         self.context_menu = menus;
         self
     }
+
+    /// Results of the search typed in, all the tracks are listed until they are given.
+    pub fn search_results(mut self, results: Option<&'a SearchResults>) -> Self {
+        self.search_results = results;
+        self
+    }
+
+    /// Extra columns and filter to show, the response is marked as changed when they change.
+    pub fn settings(mut self, settings: &'a mut TrackListSettings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
 }
 
 impl egui::Widget for TrackList<'_> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         let mut state = State::load(ui.ctx(), self.id).unwrap_or_default();
+        let mut default_settings = TrackListSettings::default();
+        let settings = self.settings.unwrap_or(&mut default_settings);
+        let mut settings_changed = false;
+
+        let mut response = ui
+            .vertical(|ui| {
+                let mut keys = keyboard_ui(ui, &mut state, &self.context_menu);
+
+                let search_input =
+                    search_ui(ui, &mut state, settings, keys.search, &mut settings_changed);
+                // NOTE: Searches run in the background, ask until the results come.
+                if search_input.changed()
+                    || (!state.search_input.is_empty() && self.search_results.is_none())
+                {
+                    *self.action = Some(TrackListAction::Search(state.search_input.clone()));
+                }
+
+                let enter_pressed = ui.input_mut(|input_state| {
+                    input_state.consume_key(egui::Modifiers::NONE, egui::Key::Enter)
+                });
+
+                ui.separator();
+
+                let (tracks, sort) =
+                    visible_tracks(self.tracks, self.search_results, &state, settings);
 
-        ui.vertical(|ui| {
-            let mut widget_focused = false;
-            ui.memory(|memory| {
-                if memory.focused().is_some() {
-                    widget_focused = true;
+                // NOTE: To avoid track clone, store to be act index and handle later.
+                let mut action_index: Option<TrackIndex> = None;
+                let mut sort_clicked = None;
+
+                let mut table = table(ui, settings.columns.len());
+                let total = tracks.len();
+
+                if !state.search_input.is_empty() && total == 1 {
+                    state.selected_index = Some(0);
                 }
-            });
+                if let Some(index) = state.selected_index.as_mut() {
+                    *index = index.to_owned().clamp(0, total.saturating_sub(1));
 
-            let mut search_request = false;
-            let mut select_changed = false;
-            ui.input_mut(|input_state| {
-                if !widget_focused {
-                    if input_state.consume_key(egui::Modifiers::CTRL, egui::Key::F) {
-                        search_request = true;
+                    if enter_pressed {
+                        action_index = tracks.get(*index).map(|item| item.0);
                     }
-                    if input_state.consume_key(egui::Modifiers::NONE, egui::Key::Escape) {
-                        state.selected_index = None;
+                    if let Some(action) = tracks
+                        .get(*index)
+                        .and_then(|(item_index, item)| keys.action(*item_index, item))
+                    {
+                        *self.action = Some(action);
                     }
-                }
-                if input_state.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
-                    if let Some(selected) = state.selected_index.as_mut() {
-                        *selected = selected.saturating_sub(1);
+                    if keys.select_changed {
+                        table = table.scroll_to_row(*index, None);
                     } else {
-                        state.selected_index = Some(TrackIndex::MAX);
+                        table = table.vertical_scroll_offset(state.scroll_position);
                     }
-                    select_changed = true;
                 }
-                if input_state.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) {
-                    if let Some(selected) = state.selected_index.as_mut() {
-                        *selected = selected.saturating_add(1);
-                    } else {
-                        state.selected_index = Some(TrackIndex::MIN);
-                    }
-                    select_changed = true;
+
+                let scroll_output = table
+                    .header(32.0, |header| {
+                        sort_clicked = header_ui(header, &settings.columns, sort);
+                    })
+                    .body(|mut body| {
+                        body.ui_mut().style_mut().interaction.selectable_labels = false;
+
+                        body.rows(24.0, total, |mut row| {
+                            let row_index = row.index();
+
+                            let Some((item_index, item)) = tracks.get(row_index).copied() else {
+                                return;
+                            };
+
+                            if state.selected_index.is_some_and(|index| index == row_index) {
+                                row.set_selected(true);
+                            }
+
+                            cells_ui(
+                                &mut row,
+                                item_index,
+                                item,
+                                self.indicator,
+                                &settings.columns,
+                            );
+
+                            if let Some(action) = context_menu_ui(
+                                &row.response(),
+                                &self.context_menu,
+                                item_index,
+                                item,
+                            ) {
+                                *self.action = Some(action);
+                            }
+
+                            if row.response().clicked() || row.response().secondary_clicked() {
+                                state.selected_index = Some(row_index);
+                                keys.select_changed = true;
+                            }
+
+                            if row.response().double_clicked() {
+                                action_index = Some(item_index);
+                            }
+                        });
+                    });
+
+                state.scroll_position = scroll_output.state.offset.y;
+
+                if let Some(column) = sort_clicked {
+                    state.sort = Sort::toggle(sort, column);
+                    state.selected_index = None;
                 }
-            });
 
-            let search_input = ui.add_sized(
+                if keys.select_changed {
+                    *self.action = state.selected_index.map(TrackListAction::Select);
+                }
+
+                if action_index.is_some() {
+                    *self.action = action_index.map(TrackListAction::Play);
+                }
+
+                state.store(ui.ctx(), self.id);
+            })
+            .response;
+
+        if settings_changed {
+            response.mark_changed();
+        }
+
+        response
+    }
+}
+
+/// Requests made with the keyboard shortcuts of the list.
+#[derive(Default)]
+struct KeyboardRequests {
+    search: bool,
+    select_changed: bool,
+    /// `0` clears the rating.
+    rating: Option<u8>,
+    love: bool,
+}
+
+impl KeyboardRequests {
+    /// Rating or loved toggle of the selected `track`, the loved toggle wins if both are asked.
+    fn action(&self, index: TrackIndex, track: &Track) -> Option<TrackListAction> {
+        if self.love {
+            Some(TrackListAction::SetLoved(vec![index], !track.loved))
+        } else {
+            self.rating.map(|rating| {
+                TrackListAction::SetRating(vec![index], (rating > 0).then_some(rating))
+            })
+        }
+    }
+}
+
+/// Consumes the shortcuts of the list, those typed into a focused widget are left to it.
+///
+/// Rating shortcuts are only taken when the `menus` of the rows have the rating.
+fn keyboard_ui(
+    ui: &egui::Ui,
+    state: &mut State,
+    menus: &[TrackListContextMenu],
+) -> KeyboardRequests {
+    let widget_focused = ui.memory(|memory| memory.focused().is_some());
+    let ratable = menus
+        .iter()
+        .any(|menu| matches!(menu, TrackListContextMenu::Rating));
+    let mut requests = KeyboardRequests::default();
+
+    ui.input_mut(|input_state| {
+        if !widget_focused {
+            if input_state.consume_key(egui::Modifiers::CTRL, egui::Key::F) {
+                requests.search = true;
+            }
+            if input_state.consume_key(egui::Modifiers::NONE, egui::Key::Escape) {
+                state.selected_index = None;
+            }
+        }
+        if !widget_focused && ratable {
+            for (rating, key) in (0..).zip(RATING_KEYS) {
+                if input_state.consume_key(egui::Modifiers::CTRL, key) {
+                    requests.rating = Some(rating);
+                }
+            }
+            if input_state.consume_key(egui::Modifiers::CTRL, egui::Key::L) {
+                requests.love = true;
+            }
+        }
+        if input_state.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
+            if let Some(selected) = state.selected_index.as_mut() {
+                *selected = selected.saturating_sub(1);
+            } else {
+                state.selected_index = Some(TrackIndex::MAX);
+            }
+            requests.select_changed = true;
+        }
+        if input_state.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) {
+            if let Some(selected) = state.selected_index.as_mut() {
+                *selected = selected.saturating_add(1);
+            } else {
+                state.selected_index = Some(TrackIndex::MIN);
+            }
+            requests.select_changed = true;
+        }
+    });
+
+    requests
+}
+
+/// Search field with the column and filter menus, sets `settings_changed` when they change.
+///
+/// The search field takes the focus when `focus` is set.
+fn search_ui(
+    ui: &mut egui::Ui,
+    state: &mut State,
+    settings: &mut TrackListSettings,
+    focus: bool,
+    settings_changed: &mut bool,
+) -> egui::Response {
+    let search_input = ui
+        .with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if columns_ui(ui, &mut settings.columns) | filter_ui(ui, &mut settings.filter) {
+                state.selected_index = None;
+                *settings_changed = true;
+            }
+
+            ui.add_sized(
                 [ui.available_width(), 30.0],
                 egui::TextEdit::singleline(&mut state.search_input)
                     .vertical_align(egui::Align::Center)
                     .hint_text("Search"),
-            );
-            if search_input.changed() {
-                state.selected_index = None;
-            }
-            if search_request {
-                search_input.request_focus();
-            }
+            )
+            .on_hover_text(SEARCH_HELP)
+        })
+        .inner;
 
-            let mut enter_pressed = false;
-            ui.input_mut(|input_state| {
-                if input_state.consume_key(egui::Modifiers::NONE, egui::Key::Enter) {
-                    enter_pressed = true;
-                }
-            });
+    if search_input.changed() {
+        state.selected_index = None;
+    }
+    if focus {
+        search_input.request_focus();
+    }
 
-            ui.separator();
+    search_input
+}
 
-            let tracks = self
-                .tracks
-                .iter()
-                .enumerate()
-                .filter(|item| {
-                    if state.search_input.is_empty() {
-                        return true;
-                    }
-                    format!(
-                        "{} {} {}",
-                        item.1.album.as_deref().unwrap_or(""),
-                        item.1.title.as_deref().unwrap_or(""),
-                        item.1.artist.as_deref().unwrap_or(""),
+/// Tracks passing the filter and the search, sorted by the column returned along with them.
+fn visible_tracks<'t>(
+    tracks: &'t [Track],
+    search_results: Option<&SearchResults>,
+    state: &State,
+    settings: &TrackListSettings,
+) -> (Vec<(TrackIndex, &'t Track)>, Option<Sort>) {
+    let mut tracks = tracks
+        .iter()
+        .enumerate()
+        .filter(|item| settings.filter.matches(&item.1.properties))
+        .filter(|item| {
+            state.search_input.is_empty()
+                || search_results.is_none_or(|results| results.contains(item.1))
+        })
+        .collect::<Vec<(TrackIndex, &Track)>>();
+
+    // NOTE: Hidden columns don't sort the rows.
+    let sort = state
+        .sort
+        .filter(|sort| settings.columns.contains(&sort.column));
+    if let Some(sort) = sort {
+        sort.apply(&mut tracks);
+    }
+
+    (tracks, sort)
+}
+
+/// Table with the tag columns followed by `extra_columns` columns.
+fn table(ui: &mut egui::Ui, extra_columns: usize) -> TableBuilder<'_> {
+    let width = ui.available_width();
+
+    TableBuilder::new(ui)
+        .sense(egui::Sense::click())
+        .striped(true)
+        .resizable(true)
+        .auto_shrink(false)
+        .column(Column::initial(width * 0.1).at_least(48.0).clip(true))
+        .column(
+            Column::initial(width * 0.3)
+                .at_least(width * 0.2)
+                .clip(true),
+        )
+        .column(Column::initial(width * 0.15).at_least(50.0).clip(true))
+        .column(
+            Column::initial(width * 0.3)
+                .at_least(width * 0.2)
+                .clip(true),
+        )
+        .column(Column::remainder().clip(true))
+        .columns(
+            Column::initial(80.0).at_least(48.0).clip(true),
+            extra_columns,
+        )
+        .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+}
+
+/// Column titles, the extra ones sort the rows, returns the one clicked.
+fn header_ui(
+    mut header: TableRow<'_, '_>,
+    columns: &[ExtraColumn],
+    sort: Option<Sort>,
+) -> Option<ExtraColumn> {
+    let mut sort_clicked = None;
+
+    header.col(|ui| {
+        ui.centered_and_justified(|ui| {
+            ui.strong("Playing");
+        });
+    });
+    header.col(|ui| {
+        ui.strong("Album");
+    });
+    header.col(|ui| {
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.strong("Track No.");
+        });
+    });
+    header.col(|ui| {
+        ui.strong("Title");
+    });
+    header.col(|ui| {
+        ui.strong("Artist");
+    });
+    for column in columns {
+        header.col(|ui| {
+            let arrow = match sort {
+                Some(sort) if sort.column == *column && sort.descending => " ⏷",
+                Some(sort) if sort.column == *column => " ⏶",
+                _ => "",
+            };
+
+            if ui
+                .add(
+                    egui::Button::new(
+                        egui::RichText::new(format!("{}{arrow}", column.name())).strong(),
                     )
-                    .to_ascii_lowercase()
-                    .trim()
-                    .contains(&state.search_input.to_ascii_lowercase())
-                })
-                .collect::<Vec<(TrackIndex, &Track)>>();
-
-            // NOTE: To avoid track clone, store to be act index and handle later.
-            let mut action_index: Option<TrackIndex> = None;
-
-            let width = ui.available_width();
-            let mut table = TableBuilder::new(ui)
-                .sense(egui::Sense::click())
-                .striped(true)
-                .resizable(true)
-                .auto_shrink(false)
-                .column(Column::initial(width * 0.1).at_least(48.0).clip(true))
-                .column(
-                    Column::initial(width * 0.3)
-                        .at_least(width * 0.2)
-                        .clip(true),
-                )
-                .column(Column::initial(width * 0.15).at_least(50.0).clip(true))
-                .column(
-                    Column::initial(width * 0.3)
-                        .at_least(width * 0.2)
-                        .clip(true),
+                    .frame(false),
                 )
-                .column(Column::remainder().clip(true))
-                .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
+                .on_hover_text("Sort by this column")
+                .clicked()
+            {
+                sort_clicked = Some(*column);
+            }
+        });
+    }
 
-            let total = tracks.len();
+    sort_clicked
+}
 
-            if !state.search_input.is_empty() && total == 1 {
-                state.selected_index = Some(0);
+/// Cells of the row of `track`.
+fn cells_ui(
+    row: &mut TableRow<'_, '_>,
+    index: TrackIndex,
+    track: &Track,
+    indicator: Option<TrackListIndicator>,
+    columns: &[ExtraColumn],
+) {
+    row.col(|ui| {
+        ui.centered_and_justified(|ui| indicator_ui(ui, indicator, index));
+    });
+    row.col(|ui| {
+        ui.label(track.album.as_deref().unwrap_or("-"));
+    });
+    row.col(|ui| {
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            let disc = track.disc.as_deref().unwrap_or_default();
+            let number = track.track.as_deref().unwrap_or_default();
+
+            match (disc.is_empty(), number.is_empty()) {
+                (false, false) => {
+                    ui.label(format!("{disc}.{number:0>2}"));
+                }
+                (true, false) => {
+                    ui.label(format!("{number:0>2}"));
+                }
+                _ => {}
             }
-            if let Some(index) = state.selected_index.as_mut() {
-                *index = index.to_owned().clamp(0, total.saturating_sub(1));
+        });
+    });
+    row.col(|ui| {
+        if track.unplayable {
+            ui.colored_label(ui.visuals().warn_fg_color, "⚠")
+                .on_hover_text("Cannot be played");
+        }
+        ui.label(track.title.as_deref().unwrap_or("-"));
+    });
+    row.col(|ui| {
+        ui.label(track.artist.as_deref().unwrap_or("-"));
+    });
+    for column in columns {
+        row.col(|ui| {
+            ui.label(column.value(track).as_deref().unwrap_or("-"));
+        });
+    }
+}
+
+/// Play or pause icon in the row of the playing track.
+fn indicator_ui(ui: &mut egui::Ui, indicator: Option<TrackListIndicator>, index: TrackIndex) {
+    let image_size = (16.0, 16.0);
+
+    match indicator {
+        Some(TrackListIndicator::Playing(playing)) if playing == index => {
+            ui.add(
+                egui::Image::new(include_image!("../../assets/icons/play.svg"))
+                    .max_size(image_size.into()),
+            );
+        }
+        Some(TrackListIndicator::Paused(paused)) if paused == index => {
+            ui.add(
+                egui::Image::new(include_image!("../../assets/icons/pause.svg"))
+                    .max_size(image_size.into()),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Context menu of a row with the given `menus`, returns the action of the entry clicked.
+fn context_menu_ui(
+    response: &egui::Response,
+    menus: &[TrackListContextMenu],
+    index: TrackIndex,
+    track: &Track,
+) -> Option<TrackListAction> {
+    if menus.is_empty() {
+        return None;
+    }
+
+    let mut action = None;
 
-                if enter_pressed {
-                    action_index = tracks.get(*index).map(|item| item.0);
+    response.context_menu(|ui| {
+        let mut send_to_queue = None;
+        let mut equalizer_presets = None;
+        let mut rating = false;
+        let mut track_info = false;
+
+        for menu in menus {
+            match menu {
+                TrackListContextMenu::SendToCurrentPlaylist => {
+                    send_to_queue = Some(egui::Button::new("Send to current playlist"));
+                }
+                TrackListContextMenu::EqualizerPreset(names) => {
+                    equalizer_presets = Some(names);
+                }
+                TrackListContextMenu::Rating => {
+                    rating = true;
                 }
-                if select_changed {
-                    table = table.scroll_to_row(*index, None);
-                } else {
-                    table = table.vertical_scroll_offset(state.scroll_position);
+                TrackListContextMenu::TrackInfo => {
+                    track_info = true;
                 }
             }
+        }
 
-            let scroll_output = table
-                .header(32.0, |mut header| {
-                    header.col(|ui| {
-                        ui.centered_and_justified(|ui| {
-                            ui.strong("Playing");
-                        });
-                    });
-                    header.col(|ui| {
-                        ui.strong("Album");
-                    });
-                    header.col(|ui| {
-                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                            ui.strong("Track No.");
-                        });
-                    });
-                    header.col(|ui| {
-                        ui.strong("Title");
-                    });
-                    header.col(|ui| {
-                        ui.strong("Artist");
-                    });
-                })
-                .body(|mut body| {
-                    body.ui_mut().style_mut().interaction.selectable_labels = false;
+        if track_info && ui.button("Track info").clicked() {
+            action = Some(TrackListAction::ShowInfo(index));
+        }
 
-                    body.rows(24.0, total, |mut row| {
-                        let row_index = row.index();
+        if let Some(send_to_queue) = send_to_queue
+            && ui.add(send_to_queue).clicked()
+        {
+            action = Some(TrackListAction::SendToCurrentPlaylist(vec![index]));
+        }
 
-                        let Some(item) = tracks.get(row_index).copied() else {
-                            return;
-                        };
+        if let Some(names) = equalizer_presets {
+            ui.menu_button("Equalizer preset", |ui| {
+                if ui.button("None").clicked() {
+                    action = Some(TrackListAction::SetEqualizerPreset(vec![index], None));
+                }
 
-                        let (item_index, item) = item;
+                for name in names {
+                    if ui.button(name).clicked() {
+                        action = Some(TrackListAction::SetEqualizerPreset(
+                            vec![index],
+                            Some(name.clone()),
+                        ));
+                    }
+                }
+            });
+        }
 
-                        if state.selected_index.is_some_and(|index| index == row_index) {
-                            row.set_selected(true);
-                        }
+        if rating && let Some(rating_action) = rating_ui(ui, index, track) {
+            action = Some(rating_action);
+        }
+    });
 
-                        row.col(|ui| {
-                            ui.centered_and_justified(|ui| {
-                                if let Some(indicator) = self.indicator.as_ref() {
-                                    let image_size = (16.0, 16.0);
-
-                                    match indicator {
-                                        TrackListIndicator::Playing(index) => {
-                                            if item_index.eq(index) {
-                                                ui.add(
-                                                    egui::Image::new(include_image!(
-                                                        "../../assets/icons/play.svg"
-                                                    ))
-                                                    .max_size(image_size.into()),
-                                                );
-                                            }
-                                        }
-                                        TrackListIndicator::Paused(index) => {
-                                            if item_index.eq(index) {
-                                                ui.add(
-                                                    egui::Image::new(include_image!(
-                                                        "../../assets/icons/pause.svg"
-                                                    ))
-                                                    .max_size(image_size.into()),
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
-                            });
-                        });
-                        row.col(|ui| {
-                            ui.label(item.album.as_deref().unwrap_or("-"));
-                        });
-                        row.col(|ui| {
-                            ui.with_layout(
-                                egui::Layout::right_to_left(egui::Align::Center),
-                                |ui| {
-                                    let disc = item.disc.as_deref().unwrap_or_default();
-                                    let track = item.track.as_deref().unwrap_or_default();
-
-                                    match (disc.is_empty(), track.is_empty()) {
-                                        (false, false) => {
-                                            ui.label(format!("{disc}.{track:0>2}"));
-                                        }
-                                        (true, false) => {
-                                            ui.label(format!("{track:0>2}"));
-                                        }
-                                        _ => {}
-                                    }
-                                },
-                            );
-                        });
-                        row.col(|ui| {
-                            ui.label(item.title.as_deref().unwrap_or("-"));
-                        });
-                        row.col(|ui| {
-                            ui.label(item.artist.as_deref().unwrap_or("-"));
-                        });
+    action
+}
 
-                        if !self.context_menu.is_empty() {
-                            row.response().context_menu(|ui| {
-                                let mut send_to_queue = None;
-
-                                for menu in &self.context_menu {
-                                    match menu {
-                                        TrackListContextMenu::SendToCurrentPlaylist => {
-                                            send_to_queue =
-                                                Some(egui::Button::new("Send to current playlist"));
-                                        }
-                                    }
-                                }
-
-                                if let Some(send_to_queue) = send_to_queue
-                                    && ui.add(send_to_queue).clicked()
-                                {
-                                    *self.action =
-                                        Some(TrackListAction::SendToCurrentPlaylist(vec![
-                                            item_index,
-                                        ]));
-                                }
-                            });
+/// Menu toggling the extra columns, returns whether they changed.
+fn columns_ui(ui: &mut egui::Ui, columns: &mut Vec<ExtraColumn>) -> bool {
+    let mut changed = false;
+
+    ui.menu_button("Columns", |ui| {
+        for column in ExtraColumn::ALL {
+            let mut shown = columns.contains(&column);
+
+            if ui.checkbox(&mut shown, column.name()).changed() {
+                // NOTE: Keep the columns in a fixed order whatever order they are toggled in.
+                *columns = ExtraColumn::ALL
+                    .into_iter()
+                    .filter(|c| {
+                        if *c == column {
+                            shown
+                        } else {
+                            columns.contains(c)
                         }
+                    })
+                    .collect();
+                changed = true;
+            }
+        }
+    });
 
-                        if row.response().clicked() || row.response().secondary_clicked() {
-                            state.selected_index = Some(row_index);
-                            select_changed = true;
-                        }
+    changed
+}
 
-                        if row.response().double_clicked() {
-                            action_index = Some(item_index);
-                        }
-                    });
-                });
+/// Rating submenu and loved toggle of a track, returns the action of the one clicked.
+fn rating_ui(ui: &mut egui::Ui, index: TrackIndex, track: &Track) -> Option<TrackListAction> {
+    let mut action = None;
+
+    ui.menu_button("Rating", |ui| {
+        for rating in 0..=MAX_RATING {
+            let label = if rating == 0 {
+                String::from("Not rated")
+            } else {
+                format_rating(rating)
+            };
+            let button = egui::Button::new(label)
+                .selected(track.rating.unwrap_or_default() == rating)
+                .shortcut_text(format!("Ctrl+{rating}"));
+
+            if ui.add(button).clicked() {
+                action = Some(TrackListAction::SetRating(
+                    vec![index],
+                    (rating > 0).then_some(rating),
+                ));
+            }
+        }
+    });
 
-            state.scroll_position = scroll_output.state.offset.y;
+    let mut loved = track.loved;
+    if ui.checkbox(&mut loved, "Loved").clicked() {
+        action = Some(TrackListAction::SetLoved(vec![index], loved));
+    }
 
-            if select_changed {
-                *self.action = state.selected_index.map(TrackListAction::Select);
+    action
+}
+
+/// Rating as filled and empty stars, such as `★★★☆☆`.
+fn format_rating(rating: u8) -> String {
+    let filled = usize::from(rating.min(MAX_RATING));
+
+    let mut stars = "★".repeat(filled);
+    stars.push_str(&"☆".repeat(usize::from(MAX_RATING) - filled));
+
+    stars
+}
+
+/// Menu of the audio properties filter, returns whether it changed.
+fn filter_ui(ui: &mut egui::Ui, filter: &mut PropertiesFilter) -> bool {
+    let previous = *filter;
+    let title = if filter.is_active() {
+        "Filter ●"
+    } else {
+        "Filter"
+    };
+
+    ui.menu_button(title, |ui| {
+        ui.radio_value(&mut filter.quality, Quality::Any, "Any quality");
+        ui.radio_value(&mut filter.quality, Quality::Lossless, "Lossless");
+        ui.radio_value(&mut filter.quality, Quality::Lossy, "Lossy");
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            let mut limited = filter.max_bitrate.is_some();
+
+            if ui.checkbox(&mut limited, "Bitrate under").changed() {
+                filter.max_bitrate = limited.then_some(PropertiesFilter::DEFAULT_MAX_BITRATE);
             }
 
-            if action_index.is_some() {
-                *self.action = action_index.map(TrackListAction::Play);
+            if let Some(max_bitrate) = filter.max_bitrate.as_mut() {
+                ui.add(
+                    egui::DragValue::new(max_bitrate)
+                        .range(8..=9_999)
+                        .suffix(" kbps"),
+                );
             }
+        });
 
-            state.store(ui.ctx(), self.id);
-        })
-        .response
+        if filter.is_active() && ui.button("Clear").clicked() {
+            *filter = PropertiesFilter::default();
+        }
+    });
+
+    *filter != previous
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExtraColumn, PropertiesFilter, Quality};
+    use crate::track::AudioProperties;
+
+    fn properties(codec: Option<&str>, bitrate: Option<u32>) -> AudioProperties {
+        AudioProperties {
+            codec: codec.map(ToOwned::to_owned),
+            bitrate,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filters_by_properties() {
+        let flac = properties(Some("FLAC"), Some(900));
+        let mp3 = properties(Some("MP3"), Some(128));
+        let unknown = properties(None, None);
+
+        let lossless = PropertiesFilter {
+            quality: Quality::Lossless,
+            max_bitrate: None,
+        };
+        let lossy = PropertiesFilter {
+            quality: Quality::Lossy,
+            max_bitrate: None,
+        };
+        let low_bitrate = PropertiesFilter {
+            quality: Quality::Any,
+            max_bitrate: Some(192),
+        };
+
+        assert!(
+            [&flac, &mp3, &unknown]
+                .into_iter()
+                .all(|properties| PropertiesFilter::default().matches(properties)),
+            "Default filter should match every track."
+        );
+        assert!(
+            lossless.matches(&flac) && !lossless.matches(&mp3) && !lossless.matches(&unknown),
+            "Lossless filter should only match lossless codecs."
+        );
+        assert!(
+            lossy.matches(&mp3) && !lossy.matches(&flac) && !lossy.matches(&unknown),
+            "Lossy filter should only match lossy codecs."
+        );
+        assert!(
+            low_bitrate.matches(&mp3)
+                && !low_bitrate.matches(&flac)
+                && !low_bitrate.matches(&unknown),
+            "Bitrate filter should only match known bitrates under the limit."
+        );
+        assert!(
+            !PropertiesFilter {
+                quality: Quality::Any,
+                max_bitrate: Some(128)
+            }
+            .matches(&mp3),
+            "Bitrate limit should be exclusive."
+        );
+    }
+
+    #[test]
+    fn parses_column_keys() {
+        for column in ExtraColumn::ALL {
+            assert_eq!(
+                ExtraColumn::from_key(column.key()),
+                Some(column),
+                "{column:?} should be parsed from its key."
+            );
+        }
+        assert_eq!(
+            ExtraColumn::from_key(""),
+            None,
+            "Empty key should be skipped."
+        );
     }
 }
@@ -0,0 +1,351 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use eframe::egui::{self, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+
+use crate::player::TapBuffer;
+
+/// Number of frames analyzed for the spectrum, a power of two.
+const FFT_SIZE: usize = 2_048;
+
+const BARS: usize = 48;
+const MIN_FREQUENCY: f32 = 30.0;
+const MAX_FREQUENCY: f32 = 16_000.0;
+
+/// Level shown as an empty bar.
+const FLOOR_DB: f32 = -80.0;
+
+/// How fast bars fall back, in full heights per second.
+const BAR_DECAY: f32 = 1.5;
+
+/// Length of the waveform shown by the scope.
+const SCOPE_LENGTH: Duration = Duration::from_millis(25);
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(33);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum VisualizerMode {
+    #[default]
+    Spectrum,
+    Scope,
+}
+
+#[derive(Default, Clone)]
+struct State {
+    mode: VisualizerMode,
+    /// Smoothed bar heights, from `0.0` to `1.0`.
+    bars: Vec<f32>,
+}
+
+impl State {
+    pub fn load(ctx: &egui::Context, id: egui::Id) -> Option<Self> {
+        ctx.data_mut(|d| d.get_persisted(id))
+    }
+
+    pub fn store(self, ctx: &egui::Context, id: egui::Id) {
+        ctx.data_mut(|d| d.insert_persisted(id, self));
+    }
+}
+
+/// Spectrum analyzer or oscilloscope of the samples sent to the output, click to switch.
+pub struct Visualizer<'a> {
+    tap: &'a TapBuffer,
+    active: bool,
+    size: Option<Vec2>,
+}
+
+impl<'a> Visualizer<'a> {
+    pub fn new(tap: &'a TapBuffer) -> Self {
+        Self {
+            tap,
+            active: true,
+            size: None,
+        }
+    }
+
+    /// Whether audio is playing, the visualizer settles down and stops repainting otherwise.
+    pub fn active(mut self, active: bool) -> Self {
+        self.active = active;
+        self
+    }
+
+    pub fn size(mut self, vec2: Vec2) -> Self {
+        self.size = Some(vec2);
+        self
+    }
+}
+
+impl egui::Widget for Visualizer<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let id = ui.next_auto_id();
+        let mut state = State::load(ui.ctx(), id).unwrap_or_default();
+
+        let size = self.size.unwrap_or(ui.available_size());
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click());
+
+        if response.clicked() {
+            state.mode = match state.mode {
+                VisualizerMode::Spectrum => VisualizerMode::Scope,
+                VisualizerMode::Scope => VisualizerMode::Spectrum,
+            };
+        }
+
+        let style = ui.ctx().style();
+        let painter = ui.painter_at(rect);
+        let color = style.visuals.selection.bg_fill;
+
+        painter.rect_filled(
+            rect,
+            style.noninteractive().corner_radius,
+            style.visuals.extreme_bg_color,
+        );
+
+        match state.mode {
+            VisualizerMode::Spectrum => {
+                let levels = if self.active {
+                    spectrum(&self.tap.latest(FFT_SIZE), self.tap.sample_rate())
+                } else {
+                    vec![0.0; BARS]
+                };
+                let decay = BAR_DECAY * ui.input(|input| input.stable_dt);
+
+                state.bars.resize(BARS, 0.0);
+                for (bar, level) in state.bars.iter_mut().zip(levels) {
+                    *bar = level.max(*bar - decay);
+                }
+
+                let width = rect.width() / BARS as f32;
+                for (index, bar) in state.bars.iter().enumerate() {
+                    let left = rect.left() + index as f32 * width;
+
+                    painter.rect_filled(
+                        Rect::from_min_max(
+                            Pos2::new(left + 1.0, rect.bottom() - bar * rect.height()),
+                            Pos2::new(left + width - 1.0, rect.bottom()),
+                        ),
+                        0.0,
+                        color,
+                    );
+                }
+            }
+            VisualizerMode::Scope => {
+                let len = ((SCOPE_LENGTH.as_secs_f32() * self.tap.sample_rate() as f32) as usize)
+                    .min(TapBuffer::MAX_FRAMES / 2);
+                state.bars.clear();
+
+                let samples = if self.active {
+                    scope(&self.tap.latest(len * 2), len)
+                } else {
+                    Vec::new()
+                };
+
+                let points = samples
+                    .iter()
+                    .enumerate()
+                    .map(|(index, sample)| {
+                        Pos2::new(
+                            rect.left() + rect.width() * index as f32 / len.max(1) as f32,
+                            rect.center().y - sample.clamp(-1.0, 1.0) * rect.height() / 2.0,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                if points.len() > 1 {
+                    painter.add(Shape::line(points, Stroke::new(1.5, color)));
+                } else {
+                    painter.hline(rect.x_range(), rect.center().y, Stroke::new(1.5, color));
+                }
+            }
+        }
+
+        if self.active || state.bars.iter().any(|bar| *bar > 0.0) {
+            ui.ctx().request_repaint_after(REFRESH_INTERVAL);
+        }
+
+        state.store(ui.ctx(), id);
+
+        response.on_hover_text("Click to switch between spectrum and scope")
+    }
+}
+
+/// Levels of logarithmically spaced frequency bands, from `0.0` to `1.0`.
+fn spectrum(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let mut re = vec![0.0; FFT_SIZE];
+    let mut im = vec![0.0; FFT_SIZE];
+
+    // NOTE: Right-align so that the latest samples are always analyzed.
+    let offset = FFT_SIZE.saturating_sub(samples.len());
+    for (index, sample) in samples.iter().enumerate() {
+        let position = offset + index;
+        let window = 0.5 - 0.5 * (2.0 * PI * position as f32 / FFT_SIZE as f32).cos();
+
+        if let Some(value) = re.get_mut(position) {
+            *value = sample * window;
+        }
+    }
+
+    fft(&mut re, &mut im);
+
+    // NOTE: A full scale sine wave peaks at a quarter of the size with a Hann window.
+    let scale = 4.0 / FFT_SIZE as f32;
+    let bin_width = sample_rate.max(1) as f32 / FFT_SIZE as f32;
+    let ratio = MAX_FREQUENCY / MIN_FREQUENCY;
+
+    (0..BARS)
+        .map(|bar| {
+            let low = MIN_FREQUENCY * ratio.powf(bar as f32 / BARS as f32);
+            let high = MIN_FREQUENCY * ratio.powf((bar + 1) as f32 / BARS as f32);
+
+            let first = (low / bin_width) as usize;
+            let last = ((high / bin_width) as usize).max(first + 1);
+
+            let magnitude = (first..last)
+                .filter_map(|bin| Some(re.get(bin)?.hypot(*im.get(bin)?)))
+                .fold(0.0, f32::max);
+            let db = 20.0 * (magnitude * scale).max(f32::MIN_POSITIVE).log10();
+
+            ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Picks `len` samples starting at a rising zero crossing, so that the waveform stands still.
+fn scope(samples: &[f32], len: usize) -> Vec<f32> {
+    let trigger = samples
+        .windows(2)
+        .take(samples.len().saturating_sub(len))
+        .position(|pair| matches!(pair, [previous, next] if *previous < 0.0 && *next >= 0.0))
+        .unwrap_or_default();
+
+    samples.iter().skip(trigger).take(len).copied().collect()
+}
+
+/// In-place iterative radix-2 FFT, the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let len = re.len().min(im.len());
+    let bits = len.trailing_zeros();
+
+    for index in 0..len {
+        let reversed = index.reverse_bits() >> (usize::BITS - bits);
+
+        if index < reversed {
+            re.swap(index, reversed);
+            im.swap(index, reversed);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let step = -2.0 * PI / size as f32;
+
+        for (re, im) in re.chunks_exact_mut(size).zip(im.chunks_exact_mut(size)) {
+            let (even_re, odd_re) = re.split_at_mut(size / 2);
+            let (even_im, odd_im) = im.split_at_mut(size / 2);
+
+            let butterflies = even_re
+                .iter_mut()
+                .zip(even_im.iter_mut())
+                .zip(odd_re.iter_mut().zip(odd_im.iter_mut()));
+
+            for (k, ((even_re, even_im), (odd_re, odd_im))) in butterflies.enumerate() {
+                let (sin, cos) = (step * k as f32).sin_cos();
+                let t_re = *odd_re * cos - *odd_im * sin;
+                let t_im = *odd_re * sin + *odd_im * cos;
+
+                *odd_re = *even_re - t_re;
+                *odd_im = *even_im - t_im;
+                *even_re += t_re;
+                *even_im += t_im;
+            }
+        }
+
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use super::{BARS, FFT_SIZE, MAX_FREQUENCY, MIN_FREQUENCY, fft, scope, spectrum};
+
+    fn sine(frequency: f32, sample_rate: f32, phase: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|index| (2.0 * PI * frequency * index as f32 / sample_rate + phase).sin())
+            .collect()
+    }
+
+    #[test]
+    fn finds_bin_of_sine() {
+        let len = 64;
+        let mut re = sine(8.0, len as f32, 0.0, len);
+        let mut im = vec![0.0; len];
+
+        fft(&mut re, &mut im);
+
+        let magnitudes: Vec<f32> = re.iter().zip(&im).map(|(re, im)| re.hypot(*im)).collect();
+        for (bin, magnitude) in magnitudes.iter().enumerate() {
+            if bin == 8 || bin == len - 8 {
+                assert!(
+                    (magnitude - len as f32 / 2.0).abs() < 1e-3,
+                    "Sine should peak in bin {bin}, not {magnitude}."
+                );
+            } else {
+                assert!(*magnitude < 1e-3, "Bin {bin} should be empty.");
+            }
+        }
+    }
+
+    #[test]
+    fn shows_sine_in_its_bar() {
+        let sample_rate = 48_000;
+        let bar = 30;
+        // NOTE: Center of the band of the bar, on a logarithmic scale.
+        let frequency =
+            MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf((bar as f32 + 0.5) / BARS as f32);
+
+        let levels = spectrum(
+            &sine(frequency, sample_rate as f32, 0.0, FFT_SIZE),
+            sample_rate,
+        );
+
+        let (loudest, level) = levels
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("Levels computed.");
+        assert_eq!(
+            loudest, bar,
+            "Sine should show in the bar of its frequency."
+        );
+        assert!(*level > 0.9, "Full scale sine should fill its bar.");
+        assert!(
+            levels.first().is_some_and(|level| *level < 0.5),
+            "Far away bars should stay low."
+        );
+        assert!(
+            spectrum(&[], sample_rate).iter().all(|level| *level == 0.0),
+            "Silence should leave every bar empty."
+        );
+    }
+
+    #[test]
+    fn triggers_on_rising_zero_crossing() {
+        // NOTE: Starts falling, so the first rising zero crossing is half a period in.
+        let samples = sine(1_000.0, 48_000.0, PI / 2.0, 480);
+
+        let shown = scope(&samples, 96);
+        assert_eq!(shown.len(), 96, "Scope should show the requested length.");
+        assert!(
+            matches!(shown.as_slice(), [first, second, ..] if *first < 0.0 && *second >= 0.0),
+            "Scope should start at a rising zero crossing."
+        );
+
+        let flat = vec![0.5; 200];
+        assert_eq!(
+            scope(&flat, 100),
+            vec![0.5; 100],
+            "Scope should start at the beginning without a zero crossing."
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+/// How long it has to be quiet before changes are handed over, files are often written in
+/// several steps and moved in several events.
+const DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// Watches the music directories for files that are created, modified, moved or deleted.
+///
+/// Watching stops when it is dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+    /// Watches `roots` recursively, `on_change` is called from a thread of its own with the
+    /// paths that changed, which may be files or directories.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the watcher cannot be created.
+    pub fn new(
+        roots: &[PathBuf],
+        mut on_change: impl FnMut(Vec<PathBuf>) + Send + 'static,
+    ) -> notify::Result<Self> {
+        let (event_tx, event_rx) = mpsc::channel::<notify::Result<Event>>();
+        let mut watcher = notify::recommended_watcher(event_tx)?;
+
+        for root in roots {
+            if let Err(err) = watcher.watch(root, RecursiveMode::Recursive) {
+                debug!("Failed to watch {}: {err:?}", root.display());
+            }
+        }
+
+        thread::spawn(move || {
+            let mut changed = BTreeSet::new();
+
+            loop {
+                match event_rx.recv_timeout(DEBOUNCE) {
+                    Ok(Ok(event)) => {
+                        if is_change(event.kind) {
+                            changed.extend(event.paths);
+                        }
+                    }
+                    Ok(Err(err)) => debug!("Watch error: {err:?}"),
+                    Err(RecvTimeoutError::Timeout) => {
+                        if !changed.is_empty() {
+                            on_change(std::mem::take(&mut changed).into_iter().collect());
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Whether an event may change a file, reads of the player itself and access times do not.
+fn is_change(kind: EventKind) -> bool {
+    match kind {
+        EventKind::Modify(ModifyKind::Metadata(_)) | EventKind::Any | EventKind::Other => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        EventKind::Access(access) => access == AccessKind::Close(AccessMode::Write),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use notify::EventKind;
+    use notify::event::{
+        AccessKind, AccessMode, CreateKind, DataChange, MetadataKind, ModifyKind, RemoveKind,
+        RenameMode,
+    };
+
+    use super::is_change;
+
+    #[test]
+    fn ignores_reads_and_metadata() {
+        for kind in [
+            EventKind::Create(CreateKind::File),
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            EventKind::Remove(RemoveKind::Folder),
+            EventKind::Access(AccessKind::Close(AccessMode::Write)),
+        ] {
+            assert!(is_change(kind), "{kind:?} should be a change.");
+        }
+
+        for kind in [
+            EventKind::Access(AccessKind::Read),
+            EventKind::Access(AccessKind::Close(AccessMode::Read)),
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)),
+            EventKind::Any,
+            EventKind::Other,
+        ] {
+            assert!(!is_change(kind), "{kind:?} should not be a change.");
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rodio::decoder::DecoderError;
+
+use crate::player::decode_track;
+use crate::track::Track;
+
+/// Number of buckets a track is downsampled to, enough for a seek bar on a wide window.
+const BUCKETS: usize = 1_024;
+
+/// Blocks are measured first since the number of frames is not always known up front.
+const BLOCKS_PER_SECOND: u32 = 100;
+
+/// Downsampled peak and RMS levels of a track, drawn as the seek bar.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Waveform {
+    /// Peak and RMS of every bucket, from `0.0` to `1.0`.
+    buckets: Vec<(f32, f32)>,
+}
+
+impl Waveform {
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Peak and RMS levels of `columns` evenly spread columns, for drawing at a given width.
+    pub fn columns(&self, columns: usize) -> Vec<(f32, f32)> {
+        let len = self.buckets.len();
+
+        (0..columns)
+            .map(|column| {
+                let first = column * len / columns.max(1);
+                let last = ((column + 1) * len / columns.max(1)).max(first + 1);
+
+                self.buckets
+                    .get(first..last.min(len))
+                    .unwrap_or_default()
+                    .iter()
+                    .fold((0.0, 0.0), |(peak, rms), bucket| {
+                        (bucket.0.max(peak), bucket.1.max(rms))
+                    })
+            })
+            .collect()
+    }
+
+    /// Levels quantized to a byte each, peak then RMS, for storage.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let quantize = |level: f32| (level.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        self.buckets
+            .iter()
+            .flat_map(|(peak, rms)| [quantize(*peak), quantize(*rms)])
+            .collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            buckets: bytes
+                .chunks_exact(2)
+                .map(|pair| match pair {
+                    [peak, rms] => (f32::from(*peak) / 255.0, f32::from(*rms) / 255.0),
+                    _ => (0.0, 0.0),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Decodes a track with the playback decoders and downsamples its levels.
+///
+/// Returns `Ok(None)` if `cancelled` is set before the track is fully decoded.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or decoded.
+pub fn measure(track: &Track, cancelled: &AtomicBool) -> Result<Option<Waveform>, DecoderError> {
+    let decoder = decode_track(track)?;
+
+    let channels = usize::from(decoder.channels().max(1));
+    let block_len = (decoder.sample_rate() / BLOCKS_PER_SECOND).max(1) as usize * channels;
+
+    // NOTE: Peak and sum of squares of every block, grouped into buckets once the length is known.
+    let mut blocks = Vec::new();
+    let mut block = (0.0_f32, 0.0_f32);
+    let mut block_filled = 0;
+
+    for sample in decoder {
+        block.0 = block.0.max(sample.abs());
+        block.1 += sample * sample;
+        block_filled += 1;
+
+        if block_filled == block_len {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+
+            blocks.push(block);
+            block = (0.0, 0.0);
+            block_filled = 0;
+        }
+    }
+
+    if block_filled > 0 {
+        blocks.push(block);
+    }
+
+    let len = blocks.len();
+    let buckets = BUCKETS.min(len);
+
+    Ok(Some(Waveform {
+        buckets: (0..buckets)
+            .map(|bucket| {
+                let group = blocks
+                    .get(bucket * len / buckets..(bucket + 1) * len / buckets)
+                    .unwrap_or_default();
+                let peak = group.iter().fold(0.0_f32, |peak, block| peak.max(block.0));
+                let energy = group.iter().map(|block| block.1).sum::<f32>();
+                let rms = (energy / (group.len() * block_len).max(1) as f32).sqrt();
+
+                (peak.min(1.0), rms.min(1.0))
+            })
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::sync::atomic::AtomicBool;
+
+    use super::Waveform;
+
+    #[test]
+    fn stores_levels_as_bytes() {
+        let waveform = Waveform {
+            buckets: vec![(1.0, 0.5), (0.2, 0.1), (0.0, 0.0), (1.5, -0.5)],
+        };
+
+        let bytes = waveform.to_bytes();
+        assert_eq!(bytes.len(), 8, "Every bucket should take two bytes.");
+
+        let stored = Waveform::from_bytes(&bytes);
+        assert_eq!(stored.buckets.len(), 4, "Every bucket should be read back.");
+        for ((peak, rms), (expected_peak, expected_rms)) in
+            stored
+                .buckets
+                .iter()
+                .zip([(1.0, 0.5), (0.2, 0.1), (0.0, 0.0), (1.0, 0.0)])
+        {
+            assert!(
+                (peak - expected_peak).abs() < 1.0 / 255.0
+                    && (rms - expected_rms).abs() < 1.0 / 255.0,
+                "Levels should be quantized to a byte and clamped."
+            );
+        }
+
+        assert_eq!(
+            Waveform::from_bytes(&[255, 255, 255]).buckets,
+            vec![(1.0, 1.0)],
+            "Trailing byte should be ignored."
+        );
+    }
+
+    #[test]
+    fn spreads_buckets_over_columns() {
+        let waveform = Waveform {
+            buckets: vec![(1.0, 0.5), (0.2, 0.1), (0.4, 0.3), (0.0, 0.0)],
+        };
+
+        assert_eq!(
+            waveform.columns(2),
+            vec![(1.0, 0.5), (0.4, 0.3)],
+            "Columns should show the loudest of their buckets."
+        );
+        assert_eq!(
+            waveform.columns(8).len(),
+            8,
+            "Buckets should be repeated over more columns."
+        );
+        assert_eq!(
+            waveform.columns(8).get(5),
+            Some(&(0.4, 0.3)),
+            "Columns should show the bucket under them."
+        );
+        assert_eq!(
+            Waveform::default().columns(3),
+            vec![(0.0, 0.0); 3],
+            "Empty waveform should give silent columns."
+        );
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn measures_decoded_track() {
+        // NOTE: A square wave at half scale for a second, then a second of silence.
+        let samples: Vec<f32> = (0..2 * 48_000 * 2)
+            .map(|index| match index {
+                0..96_000 if index / 96 % 2 == 0 => 0.5,
+                0..96_000 => -0.5,
+                _ => 0.0,
+            })
+            .collect();
+        let track = crate::loudness::test::wav_track("waveform", &samples);
+
+        let waveform = super::measure(&track, &AtomicBool::new(false))
+            .expect("Track decoded.")
+            .expect("Waveform measured.");
+        assert_eq!(
+            waveform.buckets.len(),
+            200,
+            "Short track should have a bucket per block."
+        );
+        let (loud, quiet) = waveform.buckets.split_at(100);
+        assert!(
+            loud.iter()
+                .all(|(peak, rms)| (peak - 0.5).abs() < 0.01 && (rms - 0.5).abs() < 0.01),
+            "Square wave should have the same peak and RMS."
+        );
+        assert!(
+            quiet.iter().all(|(peak, rms)| *peak == 0.0 && *rms == 0.0),
+            "Silence should have no level."
+        );
+
+        assert_eq!(
+            super::measure(&track, &AtomicBool::new(true)).expect("Track decoded."),
+            None,
+            "Cancelled measurement should give nothing."
+        );
+
+        fs::remove_file(&track.path).ok();
+    }
+}